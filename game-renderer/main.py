@@ -1,132 +1,196 @@
 import pygame
 import numpy as np
 import state_processor as sp 
-from multiprocessing import Manager
-import multiprocessing
-import threading
+import json
+import sys
 
 
-def render_terrain(game_state, colour_dict, terrain_width, terrain_height):
-    material_array, height_array = game_state.get_map_data()
+# Hex maps come in the odd-r offset layout. Each cell is drawn two pixels wide
+# and odd rows are shifted right by one pixel (half a cell)
+HEX_ROW_HEIGHT = 0.8660254037844386
 
-    if material_array.ndim == 1:
-        material_array = material_array.reshape((terrain_height, terrain_width))
-        height_array = height_array.reshape((terrain_height, terrain_width))
+def to_offset_layout(terrain_array, first_row):
+    terrain_array = np.repeat(terrain_array, CELL_WIDTH, axis=1)
+    odd_rows = (np.arange(terrain_array.shape[0]) + first_row) % 2 == 1
+    terrain_array[odd_rows] = np.roll(terrain_array[odd_rows], 1, axis=1)
+    return terrain_array
 
-    # Normalize heights 0–1
-    h_min, h_max = height_array.min(), height_array.max()
-    heights_norm = (height_array - h_min) / (h_max - h_min + 1e-9)
+# entity positions are in world space, where neighbouring cell centres are 1
+# apart and hex rows are sqrt(3) / 2 apart
+def world_to_screen(world_x, world_y):
+    if HEX:
+        return (world_x * CELL_WIDTH, world_y / HEX_ROW_HEIGHT)
+    return (world_x, world_y)
+
+def screen_to_cell(screen_x, screen_y):
+    return ((screen_x - (screen_y % 2 if HEX else 0)) // CELL_WIDTH, screen_y)
+
+def terrain_to_surface(material_array, shade_array, colour_dict, first_row=0):
+    terrain_height, terrain_width = material_array.shape
+
+    # Hillshade 0–255 from the state processor, lit from the north west
+    shade_norm = shade_array / 255.0
 
     terrain_array = np.zeros((terrain_height, terrain_width, 3), dtype=np.uint8)
 
     for material_id, base_color in colour_dict.items():
         mask = (material_array == material_id)
-        brightness = 0.55 + heights_norm[mask] * 0.45  
+        brightness = 0.3 + shade_norm[mask] * 0.7
         colour = (np.array(base_color)[None, :] * brightness[:, None]).clip(0, 255)
         terrain_array[mask] = colour.astype(np.uint8)
 
+    if HEX:
+        terrain_array = to_offset_layout(terrain_array, first_row)
+        terrain_width *= CELL_WIDTH
+
     surf = pygame.Surface((terrain_width, terrain_height))
     pygame.surfarray.blit_array(surf, np.transpose(terrain_array, (1, 0, 2)))
 
     return surf
 
-def render_entities(game_state, width, height, entity_color=(255, 255, 0)):
+def render_terrain(snapshot, colour_dict):
+    # materials as they currently look, i.e. with seasonal ice and thaw applied
+    material_array, _ = snapshot.get_map_data()
+    return terrain_to_surface(material_array, snapshot.get_hillshade(), colour_dict)
+
+# forage, drink, seek_mate, rest, flee and wander, indexed by the action column
+# of the entity positions. Entities that haven't decided anything use entity_color
+ACTION_COLOURS = [(0, 160, 0), (0, 200, 255), (255, 105, 180), (128, 128, 128), (255, 60, 0), (255, 255, 0)]
+
+def render_entities(snapshot, width, height, entity_color=(255, 255, 0)):
     BACKGROUND = (0,0,0)
-    entities = gs.get_entity_locations() 
-    surf = pygame.Surface((height, width))
+    entities = snapshot.get_entity_positions()
+    surf = pygame.Surface((width * CELL_WIDTH, height))
     surf.fill(BACKGROUND)
     surf.set_colorkey(BACKGROUND)
     
-    for entity_id, x, y, is_alive in entities:
+    for _entity_id, x, y, _vx, _vy, is_alive, entity_size, action in entities:
         # Draw entity as a circle
-        entity_size = gs.get_entity_size(entity_id)
-        render_color = entity_color if is_alive else (255, 0, 255)
-        pygame.draw.circle(surf, render_color, (int(x), int(y)), entity_size)
+        render_color = ACTION_COLOURS[int(action)] if action >= 0 else entity_color
+        if not is_alive:
+            render_color = (255, 0, 255)
+        pygame.draw.circle(surf, render_color, world_to_screen(x, y), entity_size)
 
     return surf
 
-def reset_game(queue, preserve_terrain: bool):
-    global gs, terrain_surface, entity_surface
-    gs.reset_game_state(preserve_terrain)
-    terrain_surface = render_terrain(gs, colour_dict, WIDTH, HEIGHT)
-    queue.put("gen_entities")
-    entity_surface = render_entities(gs, WIDTH, HEIGHT)
-    queue.put("done")
-
-# Initialize game state
-gs = sp.generate_game_state((800, 800, 10), (100, 100, 500, 500), 5)
-multiprocessing.set_start_method("fork")
-manager = Manager()
-queue = manager.Queue()
-
-# Define material colors
-colour_dict = {
-    0: (255, 0, 0),   # Mud - Red
-    1: (0, 255, 0),   # Grass - Green
-    2: (0, 0, 255),   # Ice - Blue
-}
+# rain, snow and heat, indexed by the weather kind codes from the state processor
+WEATHER_COLOURS = {1: (80, 80, 255), 2: (255, 255, 255), 3: (255, 140, 0)}
+
+def render_weather(snapshot):
+    kinds, intensities = snapshot.get_weather_map()
+    colours = np.zeros(kinds.shape + (3,), dtype=np.uint8)
+    for kind, colour in WEATHER_COLOURS.items():
+        colours[kinds == kind] = colour
+    alpha = (intensities * 120).astype(np.uint8)
+    if HEX:
+        colours = to_offset_layout(colours, 0)
+        alpha = to_offset_layout(alpha, 0)
+
+    surf = pygame.Surface((colours.shape[1], colours.shape[0]), pygame.SRCALPHA)
+    pygame.surfarray.blit_array(surf, np.transpose(colours, (1, 0, 2)))
+    surface_alpha = pygame.surfarray.pixels_alpha(surf)
+    surface_alpha[:] = alpha.T
+    del surface_alpha
+    return surf
+
+# Initialize game state, pass "hex" as the first argument for a hex grid
+TOPOLOGY = sys.argv[1] if len(sys.argv) > 1 else "square"
+gs = sp.generate_game_state((800, 800, 10), (100, 100, 500, 500), 5, topology=TOPOLOGY)
+HEX = gs.get_topology() == "hex"
+# exaggerate the shallow heights so the hillshade shows relief
+gs.set_relief(z_factor=8.0)
+# keep recent ticks so the left and right arrow keys can scrub through them
+gs.enable_history(200)
+CELL_WIDTH = 2 if HEX else 1
+
+# Material colours come from the material registry
+colour_dict = gs.get_material_colours()
+(WIDTH, HEIGHT) = gs.get_terrain_map()
+
+# the simulation runs on its own thread, the loop below only draws the latest
+# snapshot, so frame rate and tick rate are independent. Space starts it
+runner = sp.SimRunner(gs, ticks_per_second=10, paused=True)
 
 # Initialize pygame
-overlay_active = False
 pygame.init()
-font = pygame.font.SysFont(None, 60)
-subfont = pygame.font.SysFont(None, 40)
-(WIDTH, HEIGHT) = gs.get_terrain_map()
-screen = pygame.display.set_mode((WIDTH, HEIGHT))
+screen = pygame.display.set_mode((WIDTH * CELL_WIDTH, HEIGHT))
 clock = pygame.time.Clock()
 
-# Render terrain once
-terrain_surface = render_terrain(gs, colour_dict, WIDTH, HEIGHT)
-
-# Render entities on top of terrain
-entity_surface = render_entities(gs, WIDTH, HEIGHT)
+terrain_version = None
+shown_tick = None
+decisions_enabled = False
 
 # Game loop
 running = True
 while running:
-    clock.tick(10)
+    clock.tick(60)
     
     for event in pygame.event.get():
         if event.type == pygame.QUIT:
             running = False
         elif event.type == pygame.KEYDOWN:
+            snapshot = runner.snapshot()
             if event.key == pygame.K_SPACE: 
-                gs.advance_state()
-                entity_surface = render_entities(gs, WIDTH, HEIGHT)
+                if snapshot.is_paused():
+                    runner.resume()
+                else:
+                    runner.pause()
+            elif event.key == pygame.K_UP:
+                runner.set_speed(snapshot.get_ticks_per_second() * 2)
+            elif event.key == pygame.K_DOWN:
+                runner.set_speed(snapshot.get_ticks_per_second() / 2)
+            elif event.key == pygame.K_LEFT:
+                runner.pause()
+                runner.submit(json.dumps({"type": "rewind", "ticks": 1}))
+            elif event.key == pygame.K_RIGHT:
+                # fails harmlessly when already at the newest recorded tick,
+                # the error is printed once the simulation thread has run it
+                runner.pause()
+                runner.submit(json.dumps({"type": "seek", "tick": snapshot.get_time() + 1}))
+            elif event.key == pygame.K_s:
+                # replay later with sp.replay_game_state or `cargo run -- replay replay.json`
+                with open("replay.json", "w") as replay_file:
+                    replay_file.write(runner.get_game_state().get_replay())
             elif event.key == pygame.K_r:
-                if not overlay_active:
-                    overlay_active = True
-                    preserve_terrain = not (event.mod & pygame.KMOD_LSHIFT)
-                    process = multiprocessing.Process(target=reset_game, args=(queue, preserve_terrain))
-                    process.start()
-
-    if overlay_active:
-        loading_stage = "Generating terrain..."
-
-    try:
-        msg = queue.get_nowait()
-        if msg == "gen_entities":
-            loading_stage = "Generating entities..."
-        if msg == "done":
-            overlay_active = False
-    except:
-        pass
-
-    if not overlay_active:
-        screen.fill((0, 0, 0))
-        screen.blit(terrain_surface, (0, 0))
-        screen.blit(entity_surface, (0, 0))
-    else:
-        overlay = pygame.Surface(screen.get_size(), pygame.SRCALPHA)
-        overlay.fill((0, 0, 0, 150))  # translucent black
-        screen.blit(overlay, (0, 0))
-
-        text = font.render("Resetting...", True, (255, 255, 255))
-        subtext = subfont.render(loading_stage, True, (255, 255, 255))
-        rect = text.get_rect(center=screen.get_rect().center)
-        screen.blit(text, rect)
+                runner.reset(same_map=not (event.mod & pygame.KMOD_LSHIFT))
+            elif event.key == pygame.K_d:
+                # entities pick actions by need and are coloured by them
+                decisions_enabled = not decisions_enabled
+                runner.submit(json.dumps({"type": "set_system_enabled", "name": "decisions", "enabled": decisions_enabled}))
+        elif event.type == pygame.MOUSEBUTTONDOWN:
+            # left click paints grass, middle click rock, right click raises the ground
+            mouse_x, mouse_y = screen_to_cell(*event.pos)
+            if event.button == 1:
+                runner.paint_material(mouse_x, mouse_y, 10, 1)
+            elif event.button == 2:
+                runner.paint_material(mouse_x, mouse_y, 10, 3)
+            elif event.button == 3:
+                runner.raise_terrain(mouse_x, mouse_y, 10, 2)
+
+    snapshot = runner.snapshot()
+    # inputs are run between ticks, so failures come back in a later frame
+    for error in runner.take_errors():
+        print(f"Input failed: {error}")
+    if snapshot.get_terrain_version() != terrain_version:
+        terrain_version = snapshot.get_terrain_version()
+        terrain_surface = render_terrain(snapshot, colour_dict)
+    if snapshot.get_time() != shown_tick:
+        shown_tick = snapshot.get_time()
+        year, season, day, _ = snapshot.get_date()
+        pygame.display.set_caption(f"Year {year}, {season} day {day}")
+        entity_surface = render_entities(snapshot, WIDTH, HEIGHT)
+        weather_surface = render_weather(snapshot)
+
+    screen.fill((0, 0, 0))
+    screen.blit(terrain_surface, (0, 0))
+    screen.blit(weather_surface, (0, 0))
+    screen.blit(entity_surface, (0, 0))
+    # darken towards night using the ambient light from the day/night cycle
+    night = pygame.Surface(screen.get_size(), pygame.SRCALPHA)
+    night.fill((10, 10, 40, int((1.0 - snapshot.get_ambient_light()) * 200)))
+    screen.blit(night, (0, 0))
     
     pygame.display.update()
             
+runner.stop()
 pygame.quit()
-
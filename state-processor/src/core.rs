@@ -1,65 +1,127 @@
 //Defines core simulation handling logic
-use noise::Perlin;
 use rand::Rng;
 
 mod terrain;
+mod materials;
+mod topology;
 mod entity_management;
 mod time_management;
+mod scheduler;
+mod weather;
+mod history;
+mod replay;
 mod game_state;
+mod runner;
+mod systems;
+mod profiling;
 
-pub use terrain::Terrain;
+pub use terrain::{Relief, Terrain, TerrainConfig};
+pub use materials::MaterialRegistry;
+pub use topology::Topology;
 pub use game_state::GameState;
-pub use entity_management::EntityMgmt;
-pub use time_management::TimeMgmt;
+pub use entity_management::{BoundaryPolicy, EntityMgmt, Environment, MovementModel, ObstacleResponse};
+pub use time_management::{Season, TimeMgmt, TimeMode};
+pub use weather::Weather;
+pub use scheduler::{EventAction, Scheduler};
+pub use replay::{GameSetup, Input, ReplayLog};
+pub use runner::{SimRunner, Snapshot};
 
-pub fn generate_terrain(dimensions: (u16, u16, u8), seed: Option<u32>) -> Terrain {
-    println!("Generating terrain!");
-    
-    let random_seed: u32 = match seed {
+const DEFAULT_CHUNK_SIZE: u32 = 256;
+const DEFAULT_CHUNK_MEMORY_MB: u32 = 256;
+
+fn pick_seed(seed: Option<u32>) -> u32 {
+    match seed {
         Some(x) => x,
         None => {let mut rng = rand::rng(); rng.random()},
-    };
+    }
+}
+
+pub fn generate_terrain(dimensions: (u16, u16, u8), seed: Option<u32>, config: Option<TerrainConfig>, materials: Option<MaterialRegistry>) -> Terrain {
+    println!("Generating terrain!");
+    
+    let random_seed: u32 = pick_seed(seed);
 
-    let perlin = Perlin::new(random_seed);
-    let biome_perlin = Perlin::new(random_seed.wrapping_add(1000)); // Different seed for biomes
     let (width, height, depth) = dimensions;
-    let mut new_terrain = Terrain::new(width, height, depth);
-    new_terrain.initialise_terrain(&perlin, &biome_perlin);
+    let mut new_terrain = Terrain::with_config(width.into(), height.into(), depth, config.unwrap_or_default(), materials.unwrap_or_default());
+    new_terrain.initialise_terrain(random_seed);
     //dbg!(new_terrain);
     new_terrain
 
 }
 
-pub fn generate_entities(spawn_zone: (u16, u16, u16, u16), terrain_dims: (u16, u16), amount: Option<u16>) -> EntityMgmt {
+// Chunks are generated lazily, so this returns immediately regardless of size
+pub fn generate_chunked_terrain(dimensions: (u32, u32, u8), seed: Option<u32>, chunk_size: Option<u32>, memory_budget_mb: Option<u32>, config: Option<TerrainConfig>, materials: Option<MaterialRegistry>) -> Terrain {
+    let random_seed: u32 = pick_seed(seed);
+    let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+    let budget = memory_budget_mb.unwrap_or(DEFAULT_CHUNK_MEMORY_MB) as usize * 1024 * 1024;
+
+    Terrain::new_chunked(dimensions, random_seed, chunk_size, budget, config.unwrap_or_default(), materials.unwrap_or_default())
+}
+
+fn generate_entities_for_terrain(terrain: &Terrain, spawn_zone: (u32, u32, u32, u32), amount: Option<u32>, seed: u64) -> EntityMgmt {
+    let mut mgmt = generate_entities(spawn_zone, terrain.get_dims(), amount, seed);
+    mgmt.set_topology(terrain.topology());
+    mgmt
+}
+
+pub fn generate_entities(spawn_zone: (u32, u32, u32, u32), terrain_dims: (u32, u32), amount: Option<u32>, seed: u64) -> EntityMgmt {
     println!("Generating entities!");
     let num_entities = amount.unwrap_or(15);
 
-    let mut mgmt = EntityMgmt::new(spawn_zone, terrain_dims);
+    let mut mgmt = EntityMgmt::new(spawn_zone, terrain_dims, seed);
     mgmt.generate_random_entities(num_entities, None, None);
     mgmt
 }
 
 pub fn generate_clock(initial_time: Option<u32>) -> TimeMgmt {
-    let start_time = match initial_time {
-        Some(x) => x,
-        None => 0,
-    };
+    let start_time = initial_time.unwrap_or_default();
     TimeMgmt::new(start_time)
 }
 
-pub fn generate_game_state(map_size: (u16, u16, u8), spawn_zone: (u16, u16, u16, u16), starting_entities: Option<u16>) -> GameState {
+// the same setup always generates the same starting state
+pub fn generate_from_setup(setup: GameSetup) -> GameState {
     let time = generate_clock(None);
-    let terrain = generate_terrain(map_size, None);
-    let entities = generate_entities(spawn_zone, terrain.get_dims(), starting_entities);
-    let gs = GameState::new(time, terrain, entities);
-    gs
+    let config = Some(setup.terrain_config.clone());
+    let materials = Some(MaterialRegistry::new(setup.materials.clone()));
+    let (width, height, depth) = setup.map_size;
+    let terrain = match setup.chunking {
+        Some((chunk_size, memory_budget_mb)) => generate_chunked_terrain(setup.map_size, Some(setup.seed), Some(chunk_size), Some(memory_budget_mb), config, materials),
+        None => generate_terrain((width as u16, height as u16, depth), Some(setup.seed), config, materials),
+    };
+    let entities = generate_entities_for_terrain(&terrain, setup.spawn_zone, setup.starting_entities, setup.seed.into());
+    GameState::new(time, terrain, entities, setup)
+}
+
+pub fn generate_game_state(map_size: (u16, u16, u8), spawn_zone: (u32, u32, u32, u32), starting_entities: Option<u32>, seed: Option<u32>, terrain_config: Option<TerrainConfig>, materials: Option<MaterialRegistry>) -> GameState {
+    generate_from_setup(GameSetup {
+        seed: pick_seed(seed),
+        map_size: (map_size.0.into(), map_size.1.into(), map_size.2),
+        spawn_zone,
+        starting_entities,
+        chunking: None,
+        terrain_config: terrain_config.unwrap_or_default(),
+        materials: materials.unwrap_or_default().sorted().into_iter().cloned().collect(),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn generate_chunked_game_state(map_size: (u32, u32, u8), spawn_zone: (u32, u32, u32, u32), starting_entities: Option<u32>, seed: Option<u32>, chunk_size: Option<u32>, memory_budget_mb: Option<u32>, terrain_config: Option<TerrainConfig>, materials: Option<MaterialRegistry>) -> GameState {
+    generate_from_setup(GameSetup {
+        seed: pick_seed(seed),
+        map_size,
+        spawn_zone,
+        starting_entities,
+        chunking: Some((chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE), memory_budget_mb.unwrap_or(DEFAULT_CHUNK_MEMORY_MB))),
+        terrain_config: terrain_config.unwrap_or_default(),
+        materials: materials.unwrap_or_default().sorted().into_iter().cloned().collect(),
+    })
 }
 
 /*
 pub fn process_state(mut gs: GameState, steps: Option<u8>) -> GameState{
     println!("Processing passed state");
     for i in 0..steps.unwrap_or(1) {
-        gs.advance_state();
+        gs.advance_state(None);
     }
     gs
 }
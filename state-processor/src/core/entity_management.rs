@@ -1,94 +1,180 @@
-use crate::core::Terrain;
+use crate::core::{Season, Terrain, Weather};
+use crate::core::terrain::PathCache;
+use crate::core::materials::MaterialRegistry;
+use crate::core::replay::Checksum;
+use crate::core::topology::Topology;
 use glam::i32::IVec2;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
 use rand::distr::{Bernoulli, Distribution, Uniform};
 use pyo3::prelude::*;
-use std::collections::HashMap;
-use std::f64::consts::PI;
-use std::cmp::max;
+use std::collections::{BTreeMap, HashMap};
 
+mod boundary;
 mod death_calculations;
-
+mod decisions;
+mod movement;
+mod obstacles;
+
+pub use boundary::BoundaryPolicy;
+use boundary::BoundaryOutcome;
+pub use obstacles::ObstacleResponse;
+pub use movement::MovementModel;
+pub use decisions::Action;
+use decisions::{ActionWeights, Decision, Needs};
+use movement::{StepContext, WalkState};
 use death_calculations::{DeathCalc, WeibullDeath};
 
-const DEFAULT_THREAD_COUNT: u8 = 6;
-
-const BASE_MUD_SCALAR: f64 = 0.6;
-const PROFICIENT_MUD_SCALAR: f64 = 0.8;
-
-const BASE_ICE_SCALAR: f64 = 0.4;
-const PROFICIENT_ICE_SCALAR: f64 = 0.7;
-
 const PEAK_FERTILITY_AGE: f32 = 30.0;
 const MIN_FERTILE_AGE: f32 = 15.0;
 const MAX_FERTILE_AGE: f32 = 45.0;
 const DEFAULT_ENTITY_EXPECTANCY: u8 = 70;
 const DEFAULT_LIFE_STD_DEV: u8 = 15;
-const DEFAULT_NUM_RANDOM_ENTITIES: u8 = 20;
-const DEFAULT_TIME_STEPS: u8 = 1;
+// hunger gained per tick in clear weather
+const BASE_HUNGER_RATE: f32 = 1.0;
+// entities less active than this rest instead of moving, and get hungry slower
+const REST_THRESHOLD: f64 = 0.2;
+const RESTING_HUNGER_FACTOR: f32 = 0.5;
+// hunger at which entities are slowed to half speed
+const HUNGER_SLOWDOWN: f64 = 100.0;
+// hunger eased per tick for each unit of food yield under an entity
+const GRAZING_RATE: f32 = 0.5;
+// the same while entities make decisions, high enough that foraging pays off
+const FORAGING_GRAZING_RATE: f32 = 2.0;
+// thirst gained per tick, and eased for each unit of water yield. Only while
+// entities make decisions, as they otherwise never look for water
+const BASE_THIRST_RATE: f32 = 0.5;
+const DRINKING_RATE: f32 = 4.0;
+// hunger or thirst at which the need to forage or drink is half its strongest
+const NEED_SCALE: f64 = 50.0;
+// cells away entities look for food, water, shelter and mates when deciding
+const SENSE_RADIUS: u32 = 8;
+// exposure hazard per tick at which the need to flee is 1 - 1/e
+const THREAT_SCALE: f64 = 100.0;
+// cells within which a female can find a mate
+const MATING_RADIUS: f64 = 3.0;
+// chance per tick of conceiving at full fertility with a mate nearby
+const CONCEPTION_CHANCE: f64 = 0.01;
+// moves per tick for active entities in continuous time
+const MOVES_PER_TICK: f64 = 1.0;
+// entities with this trait are active at night rather than during the day
+const NOCTURNAL_TRAIT: &str = "nocturnal";
+
+// (position, velocity) in world space
+type Motion = ((f64, f64), (f64, f64));
+
+// what entities see of the world during a tick
+pub struct Environment<'a> {
+    pub map: &'a Terrain,
+    pub weather: &'a Weather,
+    pub season: Season,
+    // 0 at night to 1 at midday, see TimeMgmt::daylight
+    pub daylight: f64,
+}
 
-fn calculate_material_speeds(is_climber: bool, is_skater: bool, grass_speed: f64) -> (f64, f64) {
-    let mud_speed: f64;
-    let ice_speed: f64;
+// movement by species (trait) and for everything else, see Entity::movement
+#[derive(Clone,Debug,Default)]
+struct Movements {
+    default: MovementModel,
+    species: BTreeMap<String, MovementModel>,
+}
 
-    if is_climber {
-        mud_speed = grass_speed * PROFICIENT_MUD_SCALAR;
-    } else {
-        mud_speed = grass_speed * BASE_MUD_SCALAR;
+impl Movements {
+    // the movement its last decision called for, else the entity's own
+    // movement, else that of its first trait with one, else the default
+    fn model_for<'a>(&'a self, entity: &'a Entity) -> &'a MovementModel {
+        entity.decision.as_ref().and_then(|decision| decision.movement.as_ref())
+            .or(entity.movement.as_ref())
+            .or_else(|| entity.traits.iter().find_map(|name| self.species.get(name)))
+            .unwrap_or(&self.default)
     }
+}
 
-    if is_skater {
-        ice_speed = grass_speed * PROFICIENT_ICE_SCALAR;
-    } else {
-        ice_speed = grass_speed * BASE_ICE_SCALAR;
-    }
-    (mud_speed, ice_speed)
+// counts over all entities, kept up to date by the statistics system
+#[derive(IntoPyObject,Clone,Debug,Default)]
+pub struct PopulationStats {
+    pub alive: u32,
+    pub dead: u32,
+    pub pregnant: u32,
+    pub emigrated: u32,
+    pub mean_age: f64,
+    pub mean_hunger: f64,
+    pub mean_thirst: f64,
 }
 
-#[derive(IntoPyObject,Debug)]
+#[derive(Clone,Debug)]
 pub struct Entity {
     age: u8,
     size: u8,
     death_age: u8,
-    hunger: u8,
+    hunger: f32,
+    thirst: f32,
     is_alive: bool,
     is_male: bool,
     is_pregnant: bool,
-    grass_speed: u8,
-    mud_speed: u8,
-    ice_speed: u8,
-    location: (u16, u16),
+    // speed on terrain with a multiplier of 1.0, see MaterialRegistry
+    base_speed: u8,
+    // named traits that materials can give speed multipliers for
+    traits: Vec<String>,
+    // cell the entity is in, derived from position
+    location: (u32, u32),
+    // world space position (see Topology::world_position) and velocity in cells per tick
+    position: (f64, f64),
+    velocity: (f64, f64),
+    // overrides the species and default movement when set
+    movement: Option<MovementModel>,
+    walk: WalkState,
+    // set by the decisions system, see decisions.rs
+    decision: Option<Decision>,
     fertility: f32,
 }
 
-impl Entity {
-    fn new(base_speed: u8, is_climber: bool, is_skater: bool, location: (u16, u16), is_male: bool, death_distr: &impl DeathCalc) -> Entity {
-        let grass_speed: f64 = base_speed.into();
-
-        let (mud_speed, ice_speed) = calculate_material_speeds(is_climber, is_skater, grass_speed);
+// how moves are resolved, copied out of EntityMgmt so entities can be
+// borrowed mutably while moving
+#[derive(Clone, Copy)]
+struct MoveRules {
+    obstacles: ObstacleResponse,
+    boundary: BoundaryPolicy,
+    topology: Topology,
+    map_dims: (u32, u32),
+}
 
+impl Entity {
+    fn new(base_speed: u8, is_climber: bool, is_skater: bool, location: (u32, u32), is_male: bool, death_distr: &impl DeathCalc, rng: &mut impl Rng) -> Entity {
+        let mut traits = vec![];
+        if is_climber {
+            traits.push("climber".to_string());
+        }
+        if is_skater {
+            traits.push("skater".to_string());
+        }
 
-        let death_age = death_distr.get_death_age();
+        let death_age = death_distr.get_death_age(rng);
         //println!("DYING AT: {}", death_age);
 
-        Entity {age: 1, size: 1, hunger: 0, is_alive: true, is_pregnant: false, fertility: 0.0, grass_speed: (grass_speed as u8), mud_speed: (mud_speed as u8), ice_speed: (ice_speed as u8), location, is_male, death_age}
+        let position = (location.0 as f64, location.1 as f64);
+        Entity {age: 1, size: 1, hunger: 0.0, thirst: 0.0, is_alive: true, is_pregnant: false, fertility: 0.0, base_speed, traits, location, position, velocity: (0.0, 0.0), movement: None, walk: WalkState::default(), decision: None, is_male, death_age}
     }
 
-
-    fn update_location(&mut self, new_loc: (u16, u16)) {
-        self.location = new_loc;
+    // moves to the centre of a cell
+    fn place(&mut self, cell: (u32, u32), topology: Topology) {
+        self.location = cell;
+        self.position = topology.world_position(cell.0, cell.1);
     }
 
-    fn grow_older(&mut self, age_increase: u8) {
-        self.age = self.age + age_increase;
+    // ages past 255 years stay at 255
+    fn grow_older(&mut self, age_increase: u32) {
+        self.age = u8::try_from(age_increase).map_or(u8::MAX, |years| self.age.saturating_add(years));
     }
 
     fn grow_bigger(&mut self, size_increase: u8) {
-        self.size = self.size + size_increase;
+        self.size += size_increase;
     }
 
     // for now, fertility follows quadratic growth and decay about the peak age
     fn get_fertility_at_age(age: u8) -> f32 {
-        let quadratic_scalar = (-100.0)/((PEAK_FERTILITY_AGE - MIN_FERTILE_AGE) * (PEAK_FERTILITY_AGE - MAX_FERTILE_AGE));
+        let quadratic_scalar = 100.0/((PEAK_FERTILITY_AGE - MIN_FERTILE_AGE) * (PEAK_FERTILITY_AGE - MAX_FERTILE_AGE));
         (age as f32 - MIN_FERTILE_AGE) * (age as f32 - MAX_FERTILE_AGE) * quadratic_scalar
 
     }
@@ -96,78 +182,293 @@ impl Entity {
     // temporary function for the time being, needs to be set to some 
     // reasonable distribution instead
     fn update_speed(&mut self) {
-        if (self.age <= 30) {
-            self.speed = self.speed + 1;
+        if self.age <= 30 {
+            self.base_speed = self.base_speed.saturating_add(1);
         } else {
-            self.speed = max(self.speed - 1, 0);
+            self.base_speed = self.base_speed.saturating_sub(1);
         }
 
     }
 
     fn update_fertility(&mut self) {
-        if (self.age as f32) < MIN_FERTILE_AGE {
-            self.fertility = 0.0;
-        } else if (self.age as f32) > MAX_FERTILE_AGE {
+        if (self.age as f32) < MIN_FERTILE_AGE || (self.age as f32) > MAX_FERTILE_AGE {
             self.fertility = 0.0;
         } else {
             self.fertility = Self::get_fertility_at_age(self.age);
         }
     }
 
+    // from 0 (asleep) to 1 (fully active) for the given daylight
+    fn activity_level(&self, daylight: f64) -> f64 {
+        if self.traits.iter().any(|name| name == NOCTURNAL_TRAIT) {
+            1.0 - daylight
+        } else {
+            daylight
+        }
+    }
+
+    // hungry entities are weaker and move slower, but never come to a stop
+    fn hunger_speed_factor(&self) -> f64 {
+        1.0 / (1.0 + self.hunger as f64 / HUNGER_SLOWDOWN)
+    }
+
     fn do_death_check(&mut self) -> bool {
         self.is_alive = self.age <= self.death_age;
         !self.is_alive
     }
+
+    // the fields that advancing changes, in declaration order. Cached paths
+    // are left out as they are rebuilt from the terrain
+    fn write_checksum(&self, sum: &mut Checksum) {
+        sum.write(&[self.age, self.size, self.death_age, self.is_alive as u8, self.is_male as u8, self.is_pregnant as u8, self.base_speed]);
+        sum.write(&self.hunger.to_le_bytes());
+        sum.write(&self.thirst.to_le_bytes());
+        for name in &self.traits {
+            sum.write(&(name.len() as u32).to_le_bytes());
+            sum.write(name.as_bytes());
+        }
+        sum.write(&self.location.0.to_le_bytes());
+        sum.write(&self.location.1.to_le_bytes());
+        for value in [self.position.0, self.position.1, self.velocity.0, self.velocity.1, self.walk.heading, self.walk.flight_left] {
+            sum.write(&value.to_le_bytes());
+        }
+        match &self.decision {
+            Some(decision) => {
+                sum.write(&[1, decision.action.index() as u8]);
+                let target = decision.target.unwrap_or((u32::MAX, u32::MAX));
+                sum.write(&target.0.to_le_bytes());
+                sum.write(&target.1.to_le_bytes());
+            },
+            None => sum.write(&[0]),
+        }
+        sum.write(&self.fertility.to_le_bytes());
+    }
 }
 
-#[derive(IntoPyObject,Debug)]
+#[derive(Clone,Debug)]
 pub struct EntityMgmt {
-    spawn_area: (u16, u16, u16, u16),
-    area_dims: (u16, u16),
-    entities: HashMap<u16, Entity>,
+    spawn_area: (u32, u32, u32, u32),
+    area_dims: (u32, u32),
+    // ordered so every run visits entities, and draws random numbers, in the same order
+    entities: BTreeMap<u32, Entity>,
+    boundary: BoundaryPolicy,
+    obstacles: ObstacleResponse,
+    topology: Topology,
+    // entities removed by the emigrate boundary policy
+    emigrated: u32,
+    movements: Movements,
+    // routes shared by entities that path toward a target
+    paths: PathCache,
+    weights: ActionWeights,
+    rng: StdRng,
 }
 
 impl EntityMgmt {
 
-    pub fn new(spawn_area: (u16, u16, u16, u16), area_dims: (u16, u16)) -> EntityMgmt{
-        EntityMgmt {spawn_area, area_dims, entities: HashMap::new()}
+    // all randomness comes from seed, so the same seed and inputs give the same run
+    pub fn new(spawn_area: (u32, u32, u32, u32), area_dims: (u32, u32), seed: u64) -> EntityMgmt{
+        EntityMgmt {spawn_area, area_dims, entities: BTreeMap::new(), boundary: BoundaryPolicy::Clamp, obstacles: ObstacleResponse::Slide, topology: Topology::Square, emigrated: 0, movements: Movements::default(), paths: PathCache::default(), weights: ActionWeights::default(), rng: StdRng::seed_from_u64(seed)}
     }
 
     pub fn reset(&mut self) {
-        *self = Self::new(self.spawn_area, self.area_dims);
+        let (boundary, obstacles, topology) = (self.boundary, self.obstacles, self.topology);
+        let movements = self.movements.clone();
+        let paths = std::mem::take(&mut self.paths);
+        let weights = self.weights.clone();
+        // the random stream carries on rather than restarting
+        let rng = self.rng.clone();
+        *self = Self::new(self.spawn_area, self.area_dims, 0);
+        self.rng = rng;
+        self.movements = movements;
+        self.paths = paths;
+        self.weights = weights;
+        self.boundary = boundary;
+        self.obstacles = obstacles;
+        self.topology = topology;
         self.generate_random_entities(15, None, None);
     }
 
-    fn entity_speed_given_material(entity: &Entity, material: u8) -> u8 {
-        match material {
-            0 => entity.mud_speed,
-            1 => entity.grass_speed,
-            2 => entity.ice_speed,
-            _ => entity.grass_speed,
-        }
+    // in cells per tick
+    fn entity_speed_given_material(entity: &Entity, material: u8, materials: &MaterialRegistry) -> f64 {
+        let multiplier = materials.speed_multiplier(material, &entity.traits);
+        entity.base_speed as f64 * multiplier
+    }
+
+    // positions entities at the centre of their cell for the current topology
+    fn insert_entity(&mut self, id: u32, mut entity: Entity) {
+        entity.place(entity.location, self.topology);
+        self.entities.insert(id, entity);
+    }
+
+    fn move_rules(&self) -> MoveRules {
+        MoveRules {obstacles: self.obstacles, boundary: self.boundary, topology: self.topology, map_dims: self.area_dims}
     }
 
     pub fn get_num_entities(&self) -> usize {
         self.entities.len()
     }
 
-    pub fn generate_random_entities(&mut self, count: u16, life_exp: Option<u8>, life_std_dev: Option<u8>) {
+    pub fn generate_random_entities(&mut self, count: u32, life_exp: Option<u8>, life_std_dev: Option<u8>) {
         let between_x = Uniform::try_from(self.spawn_area.0..self.spawn_area.2).unwrap();
         let between_y = Uniform::try_from(self.spawn_area.1..self.spawn_area.3).unwrap();
         let gender = Bernoulli::new(0.5).unwrap();
         let expectancy = life_exp.unwrap_or(DEFAULT_ENTITY_EXPECTANCY);
-        let deviation = life_exp.unwrap_or(DEFAULT_LIFE_STD_DEV);
+        let deviation = life_std_dev.unwrap_or(DEFAULT_LIFE_STD_DEV);
         let death_distr = WeibullDeath::new(expectancy, deviation);
-        let mut rng = rand::rng();
         for id in 0..count {
-            let spawn_loc_x = between_x.sample(&mut rng);
-            let spawn_loc_y = between_y.sample(&mut rng);
-            let is_male = gender.sample(&mut rng);
-            self.entities.insert(id as u16, Entity::new(30,false, false, (spawn_loc_x, spawn_loc_y), is_male, &death_distr));
+            let spawn_loc_x = between_x.sample(&mut self.rng);
+            let spawn_loc_y = between_y.sample(&mut self.rng);
+            let is_male = gender.sample(&mut self.rng);
+            let entity = Entity::new(30,false, false, (spawn_loc_x, spawn_loc_y), is_male, &death_distr, &mut self.rng);
+            self.insert_entity(id, entity);
+        }
+    }
+
+    // (min_x, min_y, max_x, max_y) of cells a cohort can spawn on, inclusive,
+    // defaulting to the spawn area. The whole area must be on the map
+    pub fn cohort_area(&self, area: Option<(u32, u32, u32, u32)>) -> Result<(u32, u32, u32, u32), String> {
+        // the spawn area leaves out its max edges, as in generate_random_entities
+        let spawn_area = (self.spawn_area.0, self.spawn_area.1, self.spawn_area.2.saturating_sub(1), self.spawn_area.3.saturating_sub(1));
+        let (min_x, min_y, max_x, max_y) = area.unwrap_or(spawn_area);
+        if min_x > max_x || min_y > max_y {
+            return Err(format!("spawn area ({}, {}, {}, {}) has a min past its max", min_x, min_y, max_x, max_y));
+        }
+        if max_x >= self.area_dims.0 || max_y >= self.area_dims.1 {
+            return Err(format!("spawn area ({}, {}, {}, {}) goes past the {}x{} map", min_x, min_y, max_x, max_y, self.area_dims.0, self.area_dims.1));
+        }
+        Ok((min_x, min_y, max_x, max_y))
+    }
+
+    // adds entities with fresh ids at random within area, see cohort_area.
+    // Returns the new ids, which stop short once every id is in use
+    pub fn spawn_cohort(&mut self, count: u32, area: Option<(u32, u32, u32, u32)>) -> Result<Vec<u32>, String> {
+        let (min_x, min_y, max_x, max_y) = self.cohort_area(area)?;
+        let rng = &mut self.rng;
+        let locations: Vec<(u32, u32)> = (0..count)
+            .map(|_| (rng.random_range(min_x..=max_x), rng.random_range(min_y..=max_y)))
+            .collect();
+        Ok(self.spawn_at(&locations))
+    }
+
+    // adds one entity at each location with default life expectancy
+    pub fn spawn_at(&mut self, locations: &[(u32, u32)]) -> Vec<u32> {
+        let death_distr = WeibullDeath::new(DEFAULT_ENTITY_EXPECTANCY, DEFAULT_LIFE_STD_DEV);
+        let gender = Bernoulli::new(0.5).unwrap();
+        let mut ids = vec![];
+        for location in locations {
+            let next_id = match self.entities.keys().max() {
+                Some(max) => match max.checked_add(1) {
+                    Some(id) => id,
+                    None => break,
+                },
+                None => 0,
+            };
+            let is_male = gender.sample(&mut self.rng);
+            let entity = Entity::new(30, false, false, *location, is_male, &death_distr, &mut self.rng);
+            self.insert_entity(next_id, entity);
+            ids.push(next_id);
+        }
+        ids
+    }
+
+    // kills a random fraction of the living entities, returns how many died
+    pub fn cull(&mut self, fraction: f64) -> usize {
+        let mut living: Vec<u32> = self.entities.iter().filter(|(_, entity)| entity.is_alive).map(|(id, _)| *id).collect();
+        living.sort();
+        let amount = (living.len() as f64 * fraction.clamp(0.0, 1.0)).round() as usize;
+        for id in living.choose_multiple(&mut self.rng, amount) {
+            if let Some(entity) = self.entities.get_mut(id) {
+                entity.is_alive = false;
+            }
+        }
+        amount
+    }
+
+    pub fn get_traits(&self, id: u32) -> Option<Vec<String>> {
+        self.entities.get(&id).map(|entity| entity.traits.clone())
+    }
+
+    pub fn set_traits(&mut self, id: u32, traits: Vec<String>) -> bool {
+        match self.entities.get_mut(&id) {
+            Some(entity) => {
+                entity.traits = traits;
+                true
+            },
+            None => false,
         }
     }
 
-    pub fn get_all_entity_locs(&self) -> HashMap<u16, (u16, u16)> {
+    // overrides the species and default movement for one entity, None removes
+    // the override
+    pub fn set_entity_movement(&mut self, id: u32, model: Option<MovementModel>) -> bool {
+        match self.entities.get_mut(&id) {
+            Some(entity) => {
+                entity.movement = model;
+                entity.walk = WalkState::default();
+                true
+            },
+            None => false,
+        }
+    }
+
+    // movement for entities with the trait, None removes it
+    pub fn set_species_movement(&mut self, species: String, model: Option<MovementModel>) {
+        match model {
+            Some(model) => self.movements.species.insert(species, model),
+            None => self.movements.species.remove(&species),
+        };
+    }
+
+    pub fn set_default_movement(&mut self, model: MovementModel) {
+        self.movements.default = model;
+    }
+
+    // the movement the entity currently follows
+    pub fn get_entity_movement(&self, id: u32) -> Option<&MovementModel> {
+        self.entities.get(&id).map(|entity| self.movements.model_for(entity))
+    }
+
+    // shared with Python queries so both reuse the same routes
+    pub fn path_cache(&mut self) -> &mut PathCache {
+        &mut self.paths
+    }
+
+    // the rest of the route from the entity's cell, if it's following one
+    pub fn get_entity_path(&self, id: u32) -> Option<Vec<(u32, u32)>> {
+        let entity = self.entities.get(&id)?;
+        let (path, _) = entity.walk.path.as_ref()?;
+        let cell = entity.location;
+        let here = path.cells.iter().position(|step| *step == cell)?;
+        Some(path.cells[here..].to_vec())
+    }
+
+    pub fn set_pregnant(&mut self, id: u32, pregnant: bool) -> bool {
+        match self.entities.get_mut(&id) {
+            Some(entity) if entity.is_alive => {
+                entity.is_pregnant = pregnant;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // ends a pregnancy with a birth at the mother's location
+    pub fn give_birth(&mut self, mother: u32) -> Option<u32> {
+        let entity = self.entities.get_mut(&mother)?;
+        if !entity.is_alive || !entity.is_pregnant {
+            return None;
+        }
+        entity.is_pregnant = false;
+        let location = entity.location;
+        self.spawn_at(&[location]).first().copied()
+    }
+
+    // id -> (position, velocity) in world space, see Topology::world_position
+    pub fn get_all_entity_motion(&self) -> HashMap<u32, Motion> {
+        self.entities.iter().map(|(id, entity)| (*id, (entity.position, entity.velocity))).collect()
+    }
+
+    pub fn get_all_entity_locs(&self) -> HashMap<u32, (u32, u32)> {
         let mut map = HashMap::new();
         for (id, entity) in &self.entities {
             map.insert(*id, entity.location);
@@ -175,20 +476,106 @@ impl EntityMgmt {
         map
     }
 
-    pub fn is_entity_alive(&self, id: u16) -> bool {
+    pub fn is_entity_alive(&self, id: u32) -> bool {
         self.entities.get(&id).unwrap().is_alive
     }
 
-    fn clamp_entity_movement(map_dims: (u16, u16), curr_pos: (u16, u16), movement: IVec2) -> (u16, u16) {
-        let curr_vec = IVec2::new(curr_pos.0.into(), curr_pos.1.into());
-        let new_pos = curr_vec + movement;
-        let clamped_pos_x = new_pos.x.clamp(0, map_dims.0 as i32);
-        let clamped_pos_y = new_pos.y.clamp(0, map_dims.1 as i32);
-        (clamped_pos_x.try_into().unwrap(), clamped_pos_y.try_into().unwrap())
+    pub fn set_boundary_policy(&mut self, policy: BoundaryPolicy) {
+        self.boundary = policy;
+    }
+
+    pub fn get_boundary_policy(&self) -> BoundaryPolicy {
+        self.boundary
     }
 
+    pub fn set_obstacle_response(&mut self, response: ObstacleResponse) {
+        self.obstacles = response;
+    }
 
-    pub fn get_entity_size(&self, id: u16) -> i8 {
+    pub fn get_obstacle_response(&self) -> ObstacleResponse {
+        self.obstacles
+    }
+
+    // should match the topology of the terrain the entities live on
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+        for entity in self.entities.values_mut() {
+            entity.place(entity.location, topology);
+        }
+    }
+
+    pub fn get_num_emigrated(&self) -> u32 {
+        self.emigrated
+    }
+
+    // moves an entity towards a world space position, tracing the cells in
+    // between past obstacles and through the boundary policy. Returns false if
+    // the entity left the map
+    fn apply_move(rules: MoveRules, entity: &mut Entity, target: (f64, f64), can_step: impl Fn((u32, u32), (u32, u32)) -> bool) -> bool {
+        let start = entity.location;
+        let target_cell = rules.topology.cell_at(target);
+        let movement = IVec2::new((target_cell.0 - start.0 as i64) as i32, (target_cell.1 - start.1 as i64) as i32);
+        let unobstructed = rules.boundary.resolve(rules.map_dims, start, movement);
+        match rules.obstacles.trace(rules.topology, rules.boundary, rules.map_dims, start, movement, can_step) {
+            BoundaryOutcome::Moved(new_location) => {
+                let completed = matches!(unobstructed, BoundaryOutcome::Moved(cell) if cell == new_location);
+                if completed {
+                    // keep the offset within the cell, shifted the same way the
+                    // boundary moved the cell when wrapping or reflecting
+                    let (cx, cy) = rules.topology.world_position(new_location.0, new_location.1);
+                    let (tx, ty) = rules.topology.cell_centre(target_cell.0, target_cell.1);
+                    entity.location = new_location;
+                    entity.position = (target.0 + cx - tx, target.1 + cy - ty);
+                } else {
+                    // blocked part way, stop at the centre of the last open cell
+                    entity.place(new_location, rules.topology);
+                }
+                true
+            },
+            BoundaryOutcome::Exited => {
+                entity.is_alive = false;
+                false
+            },
+        }
+    }
+
+    fn remove_emigrants(&mut self, emigrants: Vec<u32>) {
+        for id in emigrants {
+            self.entities.remove(&id);
+            self.emigrated += 1;
+        }
+    }
+
+    pub fn distance_between(&self, id_a: u32, id_b: u32) -> Option<f64> {
+        let a = self.entities.get(&id_a)?;
+        let b = self.entities.get(&id_b)?;
+        Some(self.boundary.distance(self.topology, self.area_dims, a.location, b.location))
+    }
+
+    // living entities within radius of a position, excluding the one at exclude_id
+    pub fn entities_within(&self, position: (u32, u32), radius: f64, exclude_id: Option<u32>) -> Vec<u32> {
+        let mut found: Vec<u32> = self.entities.iter()
+            .filter(|(id, entity)| entity.is_alive && Some(**id) != exclude_id)
+            .filter(|(_id, entity)| self.boundary.distance(self.topology, self.area_dims, position, entity.location) <= radius)
+            .map(|(id, _entity)| *id)
+            .collect();
+        found.sort();
+        found
+    }
+
+    pub fn get_entity_location(&self, id: u32) -> Option<(u32, u32)> {
+        self.entities.get(&id).map(|entity| entity.location)
+    }
+
+    pub fn get_neighbours(&self, id: u32, radius: f64) -> Vec<u32> {
+        match self.entities.get(&id) {
+            Some(entity) => self.entities_within(entity.location, radius, Some(id)),
+            None => vec![],
+        }
+    }
+
+
+    pub fn get_entity_size(&self, id: u32) -> i8 {
         let ent = self.entities.get(&id);
 
         match ent {
@@ -197,15 +584,17 @@ impl EntityMgmt {
         }
     }
 
-    pub fn get_and_move_entity(&mut self, id: u16, movement: IVec2) -> bool { 
+    pub fn get_and_move_entity(&mut self, id: u32, movement: IVec2) -> bool { 
+        let rules = self.move_rules();
         let relevant_entity: &mut Entity = self.entities.get_mut(&id).unwrap();
-        let new_location = {
-            let loc = relevant_entity.location;
-            Self::clamp_entity_movement(self.area_dims, loc, movement)
-        };
-        relevant_entity.update_location(new_location);
+        let (x, y) = relevant_entity.location;
+        let target = rules.topology.cell_centre(x as i64 + movement.x as i64, y as i64 + movement.y as i64);
+        let stayed = Self::apply_move(rules, relevant_entity, target, |_, _| true);
+        if !stayed && self.boundary == BoundaryPolicy::Emigrate {
+            self.remove_emigrants(vec![id]);
+        }
 
-        true
+        stayed
     }
     
     /*
@@ -214,62 +603,404 @@ impl EntityMgmt {
     }
     */
 
-    fn generate_vector(entity: &Entity, material: u8, direction: f64) -> IVec2 {
-        let speed = Self::entity_speed_given_material(entity, material);
-        let (rot_x, rot_y) = Self::calculate_rotated_components(speed as f64, direction);
-        IVec2::new(rot_x, rot_y)
-    }
-
-    fn calculate_rotated_components(magnitude: f64, angle: f64) -> (i32, i32){
+    // velocity in world space cells per tick. speed_scale multiplies the speed
+    // from the material, e.g. for weather
+    fn calculate_rotated_components(magnitude: f64, angle: f64) -> (f64, f64){
         let cos_angle = angle.cos();
         let sin_angle = angle.sin();
         let rotated_x = cos_angle*magnitude;
         let rotated_y = sin_angle*magnitude;
-        (rotated_x as i32, rotated_y as i32)
+        (rotated_x, rotated_y)
+    }
+
+    // moves entities dt ticks along a random heading. Resting entities stay put,
+    // the rest move slower the less active they are
+    pub fn random_move_all_entities(&mut self, env: &Environment, dt: f64) {
+        let mut emigrants = vec![];
+        let rules = self.move_rules();
+        for (id, entity) in &mut self.entities {
+            let model = self.movements.model_for(entity).clone();
+            if !Self::random_move(rules, &model, &mut self.rng, &mut self.paths, entity, env, dt) {
+                emigrants.push(*id);
+            }
+        }
+        self.remove_emigrants(emigrants);
+    }
+
+    // random_move_all_entities for a single entity
+    pub fn random_move_entity(&mut self, id: u32, env: &Environment, dt: f64) {
+        let rules = self.move_rules();
+        let Some(entity) = self.entities.get_mut(&id) else {
+            return;
+        };
+        let model = self.movements.model_for(entity).clone();
+        if !Self::random_move(rules, &model, &mut self.rng, &mut self.paths, entity, env, dt) {
+            self.remove_emigrants(vec![id]);
+        }
+    }
+
+    // false if the entity left the map and should emigrate
+    #[allow(clippy::too_many_arguments)]
+    fn random_move(rules: MoveRules, model: &MovementModel, rng: &mut StdRng, paths: &mut PathCache, entity: &mut Entity, env: &Environment, dt: f64) -> bool {
+        let activity = entity.activity_level(env.daylight);
+        if !entity.is_alive || activity < REST_THRESHOLD {
+            entity.velocity = (0.0, 0.0);
+            return true;
+        }
+        let map = env.map;
+        let (x, y) = entity.location;
+        let material = map.effective_material(x, y, env.season);
+        let speed_scale = env.weather.effects_at(x, y).speed * activity * entity.hunger_speed_factor();
+        let speed = Self::entity_speed_given_material(entity, material, &map.materials) * speed_scale;
+        let mut ctx = StepContext {
+            position: entity.position, cell: entity.location, step: speed * dt, limit: None, traits: &entity.traits,
+            state: &mut entity.walk, env, topology: rules.topology, rng, paths,
+        };
+        let Some(direction) = model.strategy().next_heading(&mut ctx) else {
+            entity.velocity = (0.0, 0.0);
+            return true;
+        };
+        // strategies heading for a goal stop on it rather than pass it
+        let speed = match ctx.limit {
+            Some(limit) if dt > 0.0 => speed.min(limit / dt),
+            _ => speed,
+        };
+        entity.walk.heading = direction;
+        entity.velocity = Self::calculate_rotated_components(speed, direction);
+        let target = (entity.position.0 + entity.velocity.0 * dt, entity.position.1 + entity.velocity.1 * dt);
+        let can_step = |from: (u32, u32), to: (u32, u32)| map.can_step(from, to, env.season);
+        let stayed = Self::apply_move(rules, entity, target, can_step);
+        stayed || rules.boundary != BoundaryPolicy::Emigrate
     }
 
-    fn random_move_all_entities(&mut self, map: &Terrain) {
-        let between = Uniform::try_from(0.0..(2.0*PI)).unwrap();
-        let mut rng = rand::rng();
-        for (_id, entity) in &mut self.entities {
+    // moves per tick for an entity in continuous time, each covering a tick's
+    // worth of distance. Resting and dead entities don't move
+    pub fn movement_rate(&self, id: u32, env: &Environment) -> f64 {
+        match self.entities.get(&id) {
+            Some(entity) if self.movements.model_for(entity).is_stationary() => 0.0,
+            Some(entity) if entity.is_alive && entity.activity_level(env.daylight) >= REST_THRESHOLD => MOVES_PER_TICK,
+            _ => 0.0,
+        }
+    }
+
+    // hunger builds faster under harsh weather and eases as entities graze the
+    // food their cell yields this season. With deciding set, entities graze
+    // faster, and get thirsty and drink the water of their cell. Resting
+    // entities get hungry and thirsty more slowly
+    pub fn feed_all_entities(&mut self, env: &Environment, dt: f64, deciding: bool) {
+        let grazing_rate = if deciding { FORAGING_GRAZING_RATE } else { GRAZING_RATE };
+        for entity in self.entities.values_mut() {
             if entity.is_alive {
                 let (x, y) = entity.location;
-                let material = map.get_material(x, y);
-                let direction = between.sample(&mut rng);
-                let movement_vector = Self::generate_vector(entity, material, direction);
-                let new_location = Self::clamp_entity_movement(self.area_dims, (x, y), movement_vector);
-                entity.update_location(new_location);
+                let effects = env.weather.effects_at(x, y);
+                let resting = entity.activity_level(env.daylight) < REST_THRESHOLD;
+                let rest_factor = if resting { RESTING_HUNGER_FACTOR } else { 1.0 };
+                let food = env.map.effective_food_yield(x, y, env.season) as f32 * grazing_rate;
+                entity.hunger = (entity.hunger + (BASE_HUNGER_RATE * effects.hunger as f32 * rest_factor - food) * dt as f32).max(0.0);
+                if !deciding {
+                    continue;
+                }
+                let water = env.map.effective_water_yield(x, y, env.season) as f32 * DRINKING_RATE;
+                entity.thirst = (entity.thirst + (BASE_THIRST_RATE * rest_factor - water) * dt as f32).max(0.0);
             }
         }
     }
 
-    fn random_move_random_entities(&mut self, _map: &Terrain) {
-        println!("Moving some entities randomly!");
+    // per tick hazard of dying of exposure where the entity stands, so that
+    // 1 - e^(-hazard * dt) is the chance of dying within dt ticks
+    pub fn exposure_hazard(&self, id: u32, env: &Environment) -> f64 {
+        match self.entities.get(&id) {
+            Some(entity) if entity.is_alive => {
+                let (x, y) = entity.location;
+                Self::hazard(env.weather.effects_at(x, y).death_chance)
+            },
+            _ => 0.0,
+        }
+    }
+
+    // per tick hazard of conceiving, for fertile females with a living male nearby
+    pub fn conception_hazard(&self, id: u32) -> f64 {
+        let Some(entity) = self.entities.get(&id) else {
+            return 0.0;
+        };
+        if !entity.is_alive || entity.is_male || entity.is_pregnant || entity.fertility <= 0.0 {
+            return 0.0;
+        }
+        let has_mate = self.entities_within(entity.location, MATING_RADIUS, Some(id)).iter()
+            .any(|other| self.entities[other].is_male);
+        if has_mate { Self::hazard(entity.fertility as f64 / 100.0 * CONCEPTION_CHANCE) } else { 0.0 }
     }
- 
-    // iterate over all entities, age up one year, attempt death
-    fn age_all_entities(&mut self) {
-        for (_id, entity) in &mut self.entities {
+
+    // how close a male must be for a female to conceive
+    pub fn mating_radius(&self) -> f64 {
+        MATING_RADIUS
+    }
+
+    // the constant hazard giving a chance per tick
+    fn hazard(chance_per_tick: f64) -> f64 {
+        -(1.0 - chance_per_tick.clamp(0.0, 1.0)).max(f64::MIN_POSITIVE).ln()
+    }
+
+    // exposure can kill under harsh weather
+    pub fn apply_exposure(&mut self, env: &Environment, dt: f64) {
+        for id in self.living_ids() {
+            let death_chance = 1.0 - (-self.exposure_hazard(id, env) * dt).exp();
+            if death_chance > 0.0 && self.rng.random_bool(death_chance) {
+                self.kill(id);
+            }
+        }
+    }
+
+    // iterate over all entities and age up by whole calendar years. Those
+    // past their death age are left for apply_old_age
+    pub fn age_all_entities(&mut self, years: u32) {
+        for entity in self.entities.values_mut() {
             if entity.is_alive {
-                entity.grow_older(1);
-                let died = entity.do_death_check();
-                if !died {
-                    entity.grow_bigger(1);
-                    entity.update_speed();
-                    entity.update_fertility();
-                }
+                entity.grow_older(years);
+                entity.grow_bigger(1);
+                entity.update_speed();
+                entity.update_fertility();
             }
         }
     }
 
-    // use to update the state of stored entities (e.g. on event
-    // occurring)
-    pub fn advance_time(&mut self, map: &Terrain, steps: Option<u8>) {
-        let num_steps = steps.unwrap_or(DEFAULT_TIME_STEPS);
-        for _ in 0..num_steps {
-            self.random_move_all_entities(map);
-            self.age_all_entities();
+    // entities past their death age die
+    pub fn apply_old_age(&mut self) {
+        for entity in self.entities.values_mut() {
+            if entity.is_alive {
+                entity.do_death_check();
+            }
         }
-        
+    }
+
+    // fertile females with a living male nearby may conceive, returns the
+    // entities that became pregnant so their births can be scheduled
+    pub fn conceive(&mut self, dt: f64) -> Vec<u32> {
+        let mut conceived = vec![];
+        for id in self.living_ids() {
+            let chance = 1.0 - (-self.conception_hazard(id) * dt).exp();
+            if chance > 0.0 && self.rng.random_bool(chance) {
+                self.set_pregnant(id, true);
+                conceived.push(id);
+            }
+        }
+        conceived
+    }
+
+    pub fn living_ids(&self) -> Vec<u32> {
+        self.entities.iter().filter(|(_, entity)| entity.is_alive).map(|(id, _)| *id).collect()
+    }
+
+    pub fn kill(&mut self, id: u32) {
+        if let Some(entity) = self.entities.get_mut(&id) {
+            entity.is_alive = false;
+        }
+    }
+
+    // uniform in [0, 1), from the same stream as every other random choice
+    pub fn random_unit(&mut self) -> f64 {
+        self.rng.random()
+    }
+
+    // entities in id order, then the emigration count
+    pub fn write_checksum(&self, sum: &mut Checksum) {
+        sum.write(&(self.entities.len() as u32).to_le_bytes());
+        for (id, entity) in &self.entities {
+            sum.write(&id.to_le_bytes());
+            entity.write_checksum(sum);
+        }
+        sum.write(&self.emigrated.to_le_bytes());
+    }
+
+    pub fn population_stats(&self) -> PopulationStats {
+        let living: Vec<&Entity> = self.entities.values().filter(|entity| entity.is_alive).collect();
+        let mean = |value: fn(&Entity) -> f64| {
+            if living.is_empty() { 0.0 } else { living.iter().map(|entity| value(entity)).sum::<f64>() / living.len() as f64 }
+        };
+        PopulationStats {
+            alive: living.len() as u32,
+            dead: (self.entities.len() - living.len()) as u32,
+            pregnant: living.iter().filter(|entity| entity.is_pregnant).count() as u32,
+            emigrated: self.emigrated,
+            mean_age: mean(|entity| entity.age as f64),
+            mean_hunger: mean(|entity| entity.hunger as f64),
+            mean_thirst: mean(|entity| entity.thirst as f64),
+        }
+    }
+
+    pub fn set_action_weight(&mut self, action: &str, weight: f64) -> Result<(), String> {
+        let action = Action::from_name(action).ok_or_else(|| format!("unknown action '{}'", action))?;
+        self.weights.set(action, weight)
+    }
+
+    pub fn get_action_weights(&self) -> Vec<(Action, f64)> {
+        Action::ALL.iter().map(|action| (*action, self.weights.get(*action))).collect()
+    }
+
+    // (action, weighted scores in Action::ALL order) of the entity's last decision
+    pub fn get_entity_decision(&self, id: u32) -> Option<(Action, [f64; 6])> {
+        let decision = self.entities.get(&id)?.decision.as_ref()?;
+        Some((decision.action, decision.scores))
+    }
+
+    // entities go back to their own movement
+    pub fn clear_decisions(&mut self) {
+        for entity in self.entities.values_mut() {
+            entity.decision = None;
+        }
+    }
+
+    // every living entity picks its best scoring action for its current needs.
+    // All decisions are made before any is applied, so the order entities are
+    // visited in doesn't matter
+    pub fn decide_all(&mut self, env: &Environment) {
+        let decisions: Vec<(u32, Decision)> = self.entities.iter()
+            .filter(|(_, entity)| entity.is_alive)
+            .map(|(id, entity)| (*id, self.decide(*id, entity, env)))
+            .collect();
+        for (id, decision) in decisions {
+            if let Some(entity) = self.entities.get_mut(&id) {
+                entity.decision = Some(decision);
+            }
+        }
+    }
+
+    fn needs(entity: &Entity, env: &Environment) -> Needs {
+        let pressing = |level: f32| level as f64 / (level as f64 + NEED_SCALE);
+        let (x, y) = entity.location;
+        let age = entity.age as f64 / entity.death_age.max(1) as f64;
+        let hazard = Self::hazard(env.weather.effects_at(x, y).death_chance);
+        Needs {
+            hunger: pressing(entity.hunger),
+            thirst: pressing(entity.thirst),
+            fertility: if entity.is_pregnant { 0.0 } else { (entity.fertility as f64 / 100.0).clamp(0.0, 1.0) },
+            fatigue: ((1.0 - entity.activity_level(env.daylight)) + age.min(1.0)) / 2.0,
+            threat: 1.0 - (-hazard * THREAT_SCALE).exp(),
+        }
+    }
+
+    // tries actions from the best score down, skipping those the entity can't
+    // carry out from where it is. Wander is always possible
+    fn decide(&self, id: u32, entity: &Entity, env: &Environment) -> Decision {
+        let ranked = self.weights.rank(&Self::needs(entity, env));
+        let mut scores = [0.0; 6];
+        for (action, score) in &ranked {
+            scores[action.index()] = *score;
+        }
+        for (action, score) in ranked {
+            if score <= 0.0 {
+                break;
+            }
+            // keeps heading for the same cell while it's still as good as any
+            let previous = entity.decision.as_ref().filter(|decision| decision.action == action).and_then(|decision| decision.target);
+            let target = match action {
+                Action::Forage => self.best_cell_near(entity, env, previous, |x, y| env.map.effective_food_yield(x, y, env.season))
+                    .filter(|(_, food)| *food > 0.0),
+                Action::Drink => self.best_cell_near(entity, env, previous, |x, y| env.map.effective_water_yield(x, y, env.season))
+                    .filter(|(_, water)| *water > 0.0),
+                Action::Flee => {
+                    let danger = |x, y| -env.weather.effects_at(x, y).death_chance;
+                    let here = danger(entity.location.0, entity.location.1);
+                    self.best_cell_near(entity, env, previous, danger).filter(|(_, safety)| *safety > here)
+                },
+                Action::SeekMate => self.nearest_mate(id, entity).map(|cell| (cell, 0.0)),
+                Action::Rest | Action::Wander => return Decision::new(action, None, scores),
+            };
+            if let Some((cell, _)) = target {
+                return Decision::new(action, Some(cell), scores);
+            }
+        }
+        Decision::new(Action::Wander, None, scores)
+    }
+
+    // (cell, value) of the passable cell within SENSE_RADIUS with the highest
+    // value. Ties go to the entity's own cell, then to preferred, then to the
+    // nearest
+    fn best_cell_near(&self, entity: &Entity, env: &Environment, preferred: Option<(u32, u32)>, value: impl Fn(u32, u32) -> f64) -> Option<((u32, u32), f64)> {
+        let (cx, cy) = entity.location;
+        let rank = |cell: (u32, u32)| {
+            if cell == entity.location {
+                0.0
+            } else if Some(cell) == preferred {
+                0.5
+            } else {
+                self.topology.distance((cx as i64, cy as i64), (cell.0 as i64, cell.1 as i64)) + 1.0
+            }
+        };
+        let mut best: Option<((u32, u32), f64, f64)> = None;
+        for y in cy.saturating_sub(SENSE_RADIUS)..=cy.saturating_add(SENSE_RADIUS).min(self.area_dims.1 - 1) {
+            for x in cx.saturating_sub(SENSE_RADIUS)..=cx.saturating_add(SENSE_RADIUS).min(self.area_dims.0 - 1) {
+                if !env.map.is_passable(x, y, env.season) {
+                    continue;
+                }
+                let (cell_value, cell_rank) = (value(x, y), rank((x, y)));
+                let better = match best {
+                    Some((_, best_value, best_rank)) => cell_value > best_value || (cell_value == best_value && cell_rank < best_rank),
+                    None => true,
+                };
+                if better {
+                    best = Some(((x, y), cell_value, cell_rank));
+                }
+            }
+        }
+        best.map(|(cell, value, _)| (cell, value))
+    }
+
+    // nearest living entity of the other sex within SENSE_RADIUS that could
+    // mate, the entity's own cell once one is close enough
+    fn nearest_mate(&self, id: u32, entity: &Entity) -> Option<(u32, u32)> {
+        let distance = |other: &Entity| self.boundary.distance(self.topology, self.area_dims, entity.location, other.location);
+        let mate = self.entities_within(entity.location, SENSE_RADIUS as f64, Some(id)).into_iter()
+            .map(|other| &self.entities[&other])
+            .filter(|other| other.is_male != entity.is_male && other.fertility > 0.0 && !other.is_pregnant)
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))?;
+        if distance(mate) <= MATING_RADIUS { Some(entity.location) } else { Some(mate.location) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a single entity in the middle of an open 10x10 map
+    fn one_entity(boundary: BoundaryPolicy) -> (EntityMgmt, u32) {
+        let mut mgmt = EntityMgmt::new((0, 0, 10, 10), (10, 10), 1);
+        mgmt.set_boundary_policy(boundary);
+        let id = mgmt.spawn_at(&[(5, 5)])[0];
+        (mgmt, id)
+    }
+
+    fn move_to(mgmt: &mut EntityMgmt, id: u32, target: (f64, f64), open: impl Fn((u32, u32)) -> bool) -> (f64, f64) {
+        let rules = mgmt.move_rules();
+        let entity = mgmt.entities.get_mut(&id).unwrap();
+        EntityMgmt::apply_move(rules, entity, target, |_, to| open(to));
+        entity.position
+    }
+
+    #[test]
+    fn positions_keep_their_offset_within_a_cell() {
+        let (mut mgmt, id) = one_entity(BoundaryPolicy::Clamp);
+        assert_eq!(mgmt.get_all_entity_motion()[&id].0, (5.0, 5.0));
+        let (x, y) = move_to(&mut mgmt, id, (7.3, 5.2), |_| true);
+        assert!((x - 7.3).abs() < 1e-9 && (y - 5.2).abs() < 1e-9, "moved to ({}, {})", x, y);
+        assert_eq!(mgmt.get_entity_location(id), Some((7, 5)));
+    }
+
+    #[test]
+    fn wrapping_carries_the_offset_across_the_edge() {
+        let (mut mgmt, id) = one_entity(BoundaryPolicy::Wrap);
+        move_to(&mut mgmt, id, (0.2, 5.0), |_| true);
+        let (x, y) = move_to(&mut mgmt, id, (-0.7, 5.0), |_| true);
+        assert!((x - 9.3).abs() < 1e-9 && y == 5.0, "wrapped to ({}, {})", x, y);
+        assert_eq!(mgmt.get_entity_location(id), Some((9, 5)));
+    }
+
+    #[test]
+    fn blocked_moves_stop_at_a_cell_centre() {
+        let (mut mgmt, id) = one_entity(BoundaryPolicy::Clamp);
+        mgmt.set_obstacle_response(ObstacleResponse::Stop);
+        let position = move_to(&mut mgmt, id, (8.4, 5.3), |(x, _)| x < 7);
+        assert_eq!(position, (6.0, 5.0));
+        assert_eq!(mgmt.get_entity_location(id), Some((6, 5)));
     }
 }
@@ -0,0 +1,143 @@
+// What happens to entities at the map edge. Movement, neighbour queries and
+// distances all go through here so the edge behaves the same everywhere
+use crate::core::topology::Topology;
+use glam::i32::IVec2;
+use pyo3::prelude::*;
+
+// Kill leaves the entity dead at the edge, Emigrate removes it from the map
+#[pyclass(eq, eq_int)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundaryPolicy {
+    Clamp,
+    Reflect,
+    Wrap,
+    Block,
+    Kill,
+    Emigrate,
+}
+
+// result of trying to move an entity to a new position
+pub enum BoundaryOutcome {
+    Moved((u32, u32)),
+    Exited,
+}
+
+impl BoundaryPolicy {
+    pub fn from_name(name: &str) -> Option<BoundaryPolicy> {
+        match name {
+            "clamp" => Some(BoundaryPolicy::Clamp),
+            "reflect" => Some(BoundaryPolicy::Reflect),
+            "wrap" => Some(BoundaryPolicy::Wrap),
+            "block" => Some(BoundaryPolicy::Block),
+            "kill" => Some(BoundaryPolicy::Kill),
+            "emigrate" => Some(BoundaryPolicy::Emigrate),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoundaryPolicy::Clamp => "clamp",
+            BoundaryPolicy::Reflect => "reflect",
+            BoundaryPolicy::Wrap => "wrap",
+            BoundaryPolicy::Block => "block",
+            BoundaryPolicy::Kill => "kill",
+            BoundaryPolicy::Emigrate => "emigrate",
+        }
+    }
+
+    // mirrors the coordinate back into [0, max], handling moves longer than the map
+    fn reflect_axis(value: i64, max: i64) -> i64 {
+        if max == 0 {
+            return 0;
+        }
+        let period = 2 * max;
+        let folded = value.rem_euclid(period);
+        if folded > max { period - folded } else { folded }
+    }
+
+    pub fn resolve(&self, map_dims: (u32, u32), curr_pos: (u32, u32), movement: IVec2) -> BoundaryOutcome {
+        let max_x = map_dims.0.saturating_sub(1) as i64;
+        let max_y = map_dims.1.saturating_sub(1) as i64;
+        // i64 so moves near the far edge of the largest maps can't overflow
+        let new_pos = (curr_pos.0 as i64 + movement.x as i64, curr_pos.1 as i64 + movement.y as i64);
+        let inside = (0..=max_x).contains(&new_pos.0) && (0..=max_y).contains(&new_pos.1);
+
+        let (x, y) = match self {
+            BoundaryPolicy::Clamp => (new_pos.0.clamp(0, max_x), new_pos.1.clamp(0, max_y)),
+            BoundaryPolicy::Reflect => (Self::reflect_axis(new_pos.0, max_x), Self::reflect_axis(new_pos.1, max_y)),
+            BoundaryPolicy::Wrap => (new_pos.0.rem_euclid(max_x + 1), new_pos.1.rem_euclid(max_y + 1)),
+            BoundaryPolicy::Block => {
+                if inside { new_pos } else { (curr_pos.0 as i64, curr_pos.1 as i64) }
+            },
+            BoundaryPolicy::Kill | BoundaryPolicy::Emigrate => {
+                if !inside {
+                    return BoundaryOutcome::Exited;
+                }
+                new_pos
+            },
+        };
+        BoundaryOutcome::Moved((x as u32, y as u32))
+    }
+
+    // shortest distance from a to b, going across the edge when the world wraps.
+    // Wrapping hex grids need an even height for rows to line up
+    pub fn distance(&self, topology: Topology, map_dims: (u32, u32), a: (u32, u32), b: (u32, u32)) -> f64 {
+        let a = (a.0 as i64, a.1 as i64);
+        let b = (b.0 as i64, b.1 as i64);
+        if *self != BoundaryPolicy::Wrap {
+            return topology.distance(a, b);
+        }
+        let (width, height) = (map_dims.0 as i64, map_dims.1 as i64);
+        let mut shortest = f64::MAX;
+        for offset_x in [-width, 0, width] {
+            for offset_y in [-height, 0, height] {
+                shortest = shortest.min(topology.distance(a, (b.0 + offset_x, b.1 + offset_y)));
+            }
+        }
+        shortest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMS: (u32, u32) = (10, 10);
+
+    fn moved_to(policy: BoundaryPolicy, from: (u32, u32), movement: (i32, i32)) -> Option<(u32, u32)> {
+        match policy.resolve(DIMS, from, IVec2::new(movement.0, movement.1)) {
+            BoundaryOutcome::Moved(cell) => Some(cell),
+            BoundaryOutcome::Exited => None,
+        }
+    }
+
+    #[test]
+    fn each_policy_handles_crossing_the_edge() {
+        let (from, movement) = ((8, 5), (4, 0));
+        assert_eq!(moved_to(BoundaryPolicy::Clamp, from, movement), Some((9, 5)));
+        assert_eq!(moved_to(BoundaryPolicy::Reflect, from, movement), Some((6, 5)));
+        assert_eq!(moved_to(BoundaryPolicy::Wrap, from, movement), Some((2, 5)));
+        assert_eq!(moved_to(BoundaryPolicy::Block, from, movement), Some((8, 5)));
+        assert_eq!(moved_to(BoundaryPolicy::Kill, from, movement), None);
+        assert_eq!(moved_to(BoundaryPolicy::Emigrate, from, movement), None);
+        // inside the map every policy moves normally
+        for policy in [BoundaryPolicy::Clamp, BoundaryPolicy::Block, BoundaryPolicy::Kill] {
+            assert_eq!(moved_to(policy, from, (-3, 2)), Some((5, 7)));
+        }
+    }
+
+    #[test]
+    fn moves_longer_than_the_map_stay_on_it() {
+        assert_eq!(moved_to(BoundaryPolicy::Reflect, (0, 0), (-25, 0)), Some((7, 0)));
+        assert_eq!(moved_to(BoundaryPolicy::Wrap, (0, 0), (-25, 31)), Some((5, 1)));
+    }
+
+    #[test]
+    fn wrapped_distance_goes_across_the_edge() {
+        let (a, b) = ((0, 0), (9, 0));
+        assert_eq!(BoundaryPolicy::Clamp.distance(Topology::Square, DIMS, a, b), 9.0);
+        assert_eq!(BoundaryPolicy::Wrap.distance(Topology::Square, DIMS, a, b), 1.0);
+        assert_eq!(BoundaryPolicy::Wrap.distance(Topology::Square, DIMS, (1, 1), (8, 8)), 18f64.sqrt());
+    }
+}
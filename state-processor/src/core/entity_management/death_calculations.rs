@@ -1,18 +1,15 @@
 use rand::prelude::*;
 use rand_distr::Weibull;
 use roots::SimpleConvergency;
-use roots::{find_root_inverse_quadratic, find_root_brent, SearchError};
+use roots::{find_root_brent, SearchError};
 use statrs::function::gamma::gamma;
 
-const WEIBULL_SHAPE_DEFAULT: f64 = 5.0;
-
 pub trait DeathCalc {
     fn new(exp: u8, std_dev: u8) -> impl DeathCalc;
-    fn get_death_age(&self) -> u8;
+    fn get_death_age(&self, rng: &mut impl Rng) -> u8;
 }
 
 pub struct WeibullDeath {
-    expected_age: u8,
     distribution: Weibull<f64>,
 }
 
@@ -48,12 +45,11 @@ impl WeibullDeath {
 impl DeathCalc for WeibullDeath {
     fn new(exp: u8, std_dev: u8) -> impl DeathCalc {
         let Ok((shape, scale)) = WeibullDeath::calculate_coefficients(exp, std_dev) else { todo!() };
-        WeibullDeath {expected_age: exp, distribution: Weibull::new(shape, scale).unwrap()}
+        WeibullDeath {distribution: Weibull::new(shape, scale).unwrap()}
     }
 
-    fn get_death_age(&self) -> u8 {
-        let mut rng = rand::rng();
-        self.distribution.sample(&mut rng) as u8
+    fn get_death_age(&self, rng: &mut impl Rng) -> u8 {
+        self.distribution.sample(rng) as u8
     }
 
 }
@@ -0,0 +1,185 @@
+// Needs-based choice of what each entity does next. Every action gets a score
+// from 0 to 1 from the entity's needs, which is multiplied by the action's
+// weight, and the best scoring action the entity can carry out wins. Actions
+// are carried out by the movement models in movement.rs
+use super::MovementModel;
+use super::movement::{PathTo, Stationary};
+use pyo3::prelude::*;
+
+// score wander always gets, so entities with no pressing need roam
+const WANDER_SCORE: f64 = 0.2;
+
+#[pyclass(eq, eq_int)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Forage,
+    Drink,
+    SeekMate,
+    Rest,
+    Flee,
+    Wander,
+}
+
+impl Action {
+    // in the order scores are kept, which is also the column value given to
+    // the renderer
+    pub const ALL: [Action; 6] = [Action::Forage, Action::Drink, Action::SeekMate, Action::Rest, Action::Flee, Action::Wander];
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "forage" => Some(Action::Forage),
+            "drink" => Some(Action::Drink),
+            "seek_mate" => Some(Action::SeekMate),
+            "rest" => Some(Action::Rest),
+            "flee" => Some(Action::Flee),
+            "wander" => Some(Action::Wander),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Forage => "forage",
+            Action::Drink => "drink",
+            Action::SeekMate => "seek_mate",
+            Action::Rest => "rest",
+            Action::Flee => "flee",
+            Action::Wander => "wander",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+// how pressing each need is, from 0 to 1
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Needs {
+    pub hunger: f64,
+    pub thirst: f64,
+    // 0 for pregnant and infertile entities
+    pub fertility: f64,
+    // from being out of the entity's active hours and from age
+    pub fatigue: f64,
+    // from the weather at the entity's cell
+    pub threat: f64,
+}
+
+impl Needs {
+    // unweighted scores in Action::ALL order. Hungry or thirsty entities
+    // don't court
+    pub fn scores(&self) -> [f64; 6] {
+        [
+            self.hunger,
+            self.thirst,
+            self.fertility * (1.0 - self.hunger.max(self.thirst)),
+            self.fatigue,
+            self.threat,
+            WANDER_SCORE,
+        ]
+    }
+}
+
+// what each score is multiplied by, all 1 unless changed
+#[derive(Clone, Debug)]
+pub struct ActionWeights([f64; 6]);
+
+impl Default for ActionWeights {
+    fn default() -> ActionWeights {
+        ActionWeights([1.0; 6])
+    }
+}
+
+impl ActionWeights {
+    pub fn get(&self, action: Action) -> f64 {
+        self.0[action.index()]
+    }
+
+    pub fn set(&mut self, action: Action, weight: f64) -> Result<(), String> {
+        if !(weight.is_finite() && weight >= 0.0) {
+            return Err("weight must be a finite number of at least 0".to_string());
+        }
+        self.0[action.index()] = weight;
+        Ok(())
+    }
+
+    // actions from best to worst for the needs, ties going to the earlier action
+    pub fn rank(&self, needs: &Needs) -> Vec<(Action, f64)> {
+        let scores = needs.scores();
+        let mut ranked: Vec<(Action, f64)> = Action::ALL.iter().map(|action| (*action, scores[action.index()] * self.get(*action))).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked
+    }
+}
+
+// the action an entity last chose and why
+#[derive(Clone, Debug)]
+pub struct Decision {
+    pub action: Action,
+    // cell the action heads for, if it has one
+    pub target: Option<(u32, u32)>,
+    // how the action is carried out, None leaves the entity to its own movement
+    pub movement: Option<MovementModel>,
+    // weighted, in Action::ALL order
+    pub scores: [f64; 6],
+}
+
+impl Decision {
+    pub fn new(action: Action, target: Option<(u32, u32)>, scores: [f64; 6]) -> Decision {
+        let movement = match (action, target) {
+            (Action::Rest, _) => Some(MovementModel::Stationary(Stationary {})),
+            (_, Some(target)) => Some(MovementModel::PathTo(PathTo {target, slope_penalty: None})),
+            (_, None) => None,
+        };
+        Decision {action, target, movement, scores}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_names_round_trip() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(action));
+        }
+        assert_eq!(Action::from_name("hunt"), None);
+    }
+
+    #[test]
+    fn the_most_pressing_need_wins() {
+        let weights = ActionWeights::default();
+        // nothing pressing, so the entity wanders
+        assert_eq!(weights.rank(&Needs::default())[0], (Action::Wander, WANDER_SCORE));
+        let needs = Needs {hunger: 0.8, thirst: 0.5, fertility: 1.0, ..Needs::default()};
+        let ranked: Vec<Action> = weights.rank(&needs).iter().map(|(action, _)| *action).collect();
+        assert_eq!(ranked[..3], [Action::Forage, Action::Drink, Action::Wander]);
+        // hunger holds back courting
+        assert!((needs.scores()[Action::SeekMate.index()] - 0.2).abs() < 1e-9);
+        // equal scores go to the earlier action
+        let tied = Needs {hunger: 0.5, thirst: 0.5, ..Needs::default()};
+        assert_eq!(weights.rank(&tied)[0].0, Action::Forage);
+    }
+
+    #[test]
+    fn weights_change_the_ranking_and_must_be_valid() {
+        let mut weights = ActionWeights::default();
+        let needs = Needs {hunger: 0.6, fatigue: 0.4, ..Needs::default()};
+        weights.set(Action::Rest, 2.0).unwrap();
+        assert_eq!(weights.rank(&needs)[0], (Action::Rest, 0.8));
+        for bad in [-1.0, f64::NAN, f64::INFINITY] {
+            assert!(weights.set(Action::Rest, bad).is_err());
+        }
+        assert_eq!(weights.get(Action::Rest), 2.0);
+    }
+
+    #[test]
+    fn decisions_pick_how_the_action_is_carried_out() {
+        let scores = [0.0; 6];
+        assert!(Decision::new(Action::Rest, Some((1, 1)), scores).movement.is_some_and(|movement| movement.is_stationary()));
+        assert!(matches!(Decision::new(Action::Drink, Some((3, 4)), scores).movement, Some(MovementModel::PathTo(PathTo {target: (3, 4), ..}))));
+        assert!(Decision::new(Action::Wander, None, scores).movement.is_none());
+    }
+}
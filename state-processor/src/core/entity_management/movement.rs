@@ -0,0 +1,366 @@
+// How entities choose where to go. Each strategy picks a heading for the next
+// move; how far the move goes still depends on the entity's speed, the terrain,
+// the weather and how active it is
+use crate::core::Environment;
+use crate::core::terrain::{Path, PathCache, PathCost};
+use crate::core::topology::Topology;
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+use std::sync::Arc;
+
+// what a strategy remembers about an entity between moves
+#[derive(Clone, Debug, Default)]
+pub struct WalkState {
+    // radians, the direction of the last move
+    pub heading: f64,
+    // distance left in the current Lévy flight
+    pub flight_left: f64,
+    // route being followed and the terrain revision it was planned for
+    pub path: Option<(Arc<Path>, u64)>,
+}
+
+// what a strategy sees when choosing a heading
+pub struct StepContext<'a> {
+    pub position: (f64, f64),
+    pub cell: (u32, u32),
+    // distance the move will cover, a strategy can shorten it with limit
+    pub step: f64,
+    pub limit: Option<f64>,
+    pub traits: &'a [String],
+    pub state: &'a mut WalkState,
+    pub env: &'a Environment<'a>,
+    pub topology: Topology,
+    pub rng: &'a mut StdRng,
+    // shared by every entity, see EntityMgmt::path_cache
+    pub paths: &'a mut PathCache,
+}
+
+pub trait MovementStrategy {
+    // heading in radians for the next move, or None to stay put
+    fn next_heading(&self, ctx: &mut StepContext) -> Option<f64>;
+}
+
+// a uniformly random heading every move
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RandomWalk {}
+
+impl MovementStrategy for RandomWalk {
+    fn next_heading(&self, ctx: &mut StepContext) -> Option<f64> {
+        Some(ctx.rng.random_range(0.0..TAU))
+    }
+}
+
+// turns away from the last heading by a normally distributed angle, so paths
+// are straighter the smaller turn_std_dev is
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CorrelatedWalk {
+    // radians
+    pub turn_std_dev: f64,
+}
+
+impl MovementStrategy for CorrelatedWalk {
+    fn next_heading(&self, ctx: &mut StepContext) -> Option<f64> {
+        let turn = Normal::new(0.0, self.turn_std_dev).unwrap().sample(ctx.rng);
+        Some((ctx.state.heading + turn).rem_euclid(TAU))
+    }
+}
+
+// straight flights in uniformly random directions, with lengths drawn from a
+// power law with the given exponent between min_flight and max_flight cells
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LevyFlight {
+    pub exponent: f64,
+    pub min_flight: f64,
+    pub max_flight: f64,
+}
+
+impl MovementStrategy for LevyFlight {
+    fn next_heading(&self, ctx: &mut StepContext) -> Option<f64> {
+        if ctx.state.flight_left <= 0.0 {
+            // inverse transform sampling of a Pareto distribution
+            let u: f64 = ctx.rng.random();
+            let length = self.min_flight * (1.0 - u).powf(-1.0 / (self.exponent - 1.0));
+            ctx.state.flight_left = length.min(self.max_flight);
+            ctx.state.heading = ctx.rng.random_range(0.0..TAU);
+        }
+        ctx.state.flight_left -= ctx.step;
+        Some(ctx.state.heading)
+    }
+}
+
+// a random heading pulled toward a target cell, from 0 (unbiased) to 1
+// (straight at it)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BiasedWalk {
+    pub target: (u32, u32),
+    pub bias: f64,
+}
+
+impl MovementStrategy for BiasedWalk {
+    fn next_heading(&self, ctx: &mut StepContext) -> Option<f64> {
+        let random = ctx.rng.random_range(0.0..TAU);
+        let target = ctx.topology.world_position(self.target.0, self.target.1);
+        let (dx, dy) = (target.0 - ctx.position.0, target.1 - ctx.position.1);
+        if dx == 0.0 && dy == 0.0 {
+            return Some(random);
+        }
+        let toward = dy.atan2(dx);
+        let x = (1.0 - self.bias) * random.cos() + self.bias * toward.cos();
+        let y = (1.0 - self.bias) * random.sin() + self.bias * toward.sin();
+        Some(y.atan2(x).rem_euclid(TAU))
+    }
+}
+
+// heads toward the cell target cells ahead on the route, so fast entities
+// don't overshoot turns, and stops short of the goal rather than passing it
+fn head_for(ctx: &mut StepContext, target: (u32, u32), is_goal: bool) -> f64 {
+    let aim = ctx.topology.world_position(target.0, target.1);
+    let (dx, dy) = (aim.0 - ctx.position.0, aim.1 - ctx.position.1);
+    if is_goal {
+        ctx.limit = Some((dx * dx + dy * dy).sqrt());
+    }
+    dy.atan2(dx)
+}
+
+// follows the cheapest route to a target cell, planned with A* and replanned
+// when the entity strays from it or the terrain changes
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PathTo {
+    pub target: (u32, u32),
+    // see PathCost, the default when None
+    #[serde(default)]
+    pub slope_penalty: Option<f64>,
+}
+
+impl MovementStrategy for PathTo {
+    fn next_heading(&self, ctx: &mut StepContext) -> Option<f64> {
+        let cell = ctx.cell;
+        let goal = self.target;
+        if cell == goal {
+            return None;
+        }
+        let revision = ctx.env.map.revision();
+        let on_route = |(path, planned_for): &(Arc<Path>, u64)| {
+            *planned_for == revision && path.cells.last() == Some(&goal) && path.cells.contains(&cell)
+        };
+        if !ctx.state.path.as_ref().is_some_and(on_route) {
+            let cost = PathCost::new(ctx.traits, ctx.env.season, self.slope_penalty);
+            ctx.state.path = ctx.paths.path(ctx.env.map, cell, goal, &cost).map(|path| (path, revision));
+        }
+        let (path, _) = ctx.state.path.clone()?;
+        let here = path.cells.iter().position(|step| *step == cell)?;
+        let ahead = (here + ctx.step.ceil().max(1.0) as usize).min(path.cells.len() - 1);
+        Some(head_for(ctx, path.cells[ahead], ahead == path.cells.len() - 1))
+    }
+}
+
+// steps downhill on a flow field toward a target cell. Entities sharing a
+// target and traits share one field, so this suits crowds heading to one place
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlowTo {
+    pub target: (u32, u32),
+    #[serde(default)]
+    pub slope_penalty: Option<f64>,
+}
+
+impl MovementStrategy for FlowTo {
+    fn next_heading(&self, ctx: &mut StepContext) -> Option<f64> {
+        let cell = ctx.cell;
+        let goal = self.target;
+        let cost = PathCost::new(ctx.traits, ctx.env.season, self.slope_penalty);
+        // chunked maps have no flow fields, see GameState::check_movement
+        let field = ctx.paths.flow_field(ctx.env.map, goal, &cost).ok()?;
+        // follow the field as far ahead as the move goes
+        let mut target = field.next_step(ctx.env.map, cell, &cost)?;
+        for _ in 1..ctx.step.ceil().max(1.0) as usize {
+            match field.next_step(ctx.env.map, target, &cost) {
+                Some(next) => target = next,
+                None => break,
+            }
+        }
+        Some(head_for(ctx, target, target == goal))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Stationary {}
+
+impl MovementStrategy for Stationary {
+    fn next_heading(&self, _ctx: &mut StepContext) -> Option<f64> {
+        None
+    }
+}
+
+// a strategy and its parameters, as assigned to entities and species and given
+// as JSON from Python, e.g. {"model": "correlated_walk", "turn_std_dev": 0.5}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum MovementModel {
+    RandomWalk(RandomWalk),
+    CorrelatedWalk(CorrelatedWalk),
+    LevyFlight(LevyFlight),
+    BiasedWalk(BiasedWalk),
+    PathTo(PathTo),
+    FlowTo(FlowTo),
+    Stationary(Stationary),
+}
+
+impl Default for MovementModel {
+    fn default() -> MovementModel {
+        MovementModel::RandomWalk(RandomWalk {})
+    }
+}
+
+impl MovementModel {
+    pub fn parse(text: &str) -> Result<MovementModel, String> {
+        let model: MovementModel = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        model.validate()?;
+        Ok(model)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            MovementModel::CorrelatedWalk(walk) if !(walk.turn_std_dev >= 0.0 && walk.turn_std_dev <= TAU) => {
+                Err("turn_std_dev must be between 0 and 2π".to_string())
+            },
+            MovementModel::LevyFlight(flight) if !(flight.exponent > 1.0 && flight.exponent <= 3.0) => {
+                Err("exponent must be greater than 1 and at most 3".to_string())
+            },
+            MovementModel::LevyFlight(flight) if !(flight.min_flight > 0.0 && flight.max_flight >= flight.min_flight) => {
+                Err("min_flight must be positive and no more than max_flight".to_string())
+            },
+            MovementModel::BiasedWalk(walk) if !(0.0..=1.0).contains(&walk.bias) => {
+                Err("bias must be between 0 and 1".to_string())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_stationary(&self) -> bool {
+        matches!(self, MovementModel::Stationary(_))
+    }
+
+    pub fn strategy(&self) -> &dyn MovementStrategy {
+        match self {
+            MovementModel::RandomWalk(walk) => walk,
+            MovementModel::CorrelatedWalk(walk) => walk,
+            MovementModel::LevyFlight(flight) => flight,
+            MovementModel::BiasedWalk(walk) => walk,
+            MovementModel::PathTo(path) => path,
+            MovementModel::FlowTo(flow) => flow,
+            MovementModel::Stationary(stationary) => stationary,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Season, Terrain, Weather};
+    use crate::core::materials::{GRASS, ROCK};
+    use crate::core::terrain::MapPoint;
+    use rand::SeedableRng;
+    use std::f64::consts::FRAC_PI_2;
+
+    // grass everywhere except the rock cells
+    fn open_map(width: u32, height: u32, rock: &[(u32, u32)]) -> Terrain {
+        let mut terrain = Terrain::new(width, height, 10);
+        terrain.map = (0..width * height).map(|i| {
+            let material = if rock.contains(&(i % width, i / width)) { ROCK } else { GRASS };
+            MapPoint {height: 0, material}
+        }).collect();
+        terrain
+    }
+
+    // (heading, limit) for one move of the given length from cell
+    fn next(model: &MovementModel, terrain: &Terrain, cell: (u32, u32), step: f64, state: &mut WalkState) -> (Option<f64>, Option<f64>) {
+        let weather = Weather::new(terrain.get_dims(), 1);
+        let env = Environment {map: terrain, weather: &weather, season: Season::Summer, daylight: 1.0};
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut paths = PathCache::default();
+        let mut ctx = StepContext {
+            position: (cell.0 as f64, cell.1 as f64),
+            cell,
+            step,
+            limit: None,
+            traits: &[],
+            state,
+            env: &env,
+            topology: Topology::Square,
+            rng: &mut rng,
+            paths: &mut paths,
+        };
+        let heading = model.strategy().next_heading(&mut ctx);
+        (heading, ctx.limit)
+    }
+
+    #[test]
+    fn models_round_trip_as_json_and_reject_bad_parameters() {
+        let model = MovementModel::parse(r#"{"model": "levy_flight", "exponent": 2.0, "min_flight": 1.0, "max_flight": 5.0}"#).unwrap();
+        assert!(matches!(MovementModel::parse(&model.to_json()).unwrap(), MovementModel::LevyFlight(LevyFlight {max_flight: 5.0, ..})));
+        assert!(matches!(MovementModel::default(), MovementModel::RandomWalk(_)));
+        for bad in [
+            r#"{"model": "correlated_walk", "turn_std_dev": -1.0}"#,
+            r#"{"model": "levy_flight", "exponent": 1.0, "min_flight": 1.0, "max_flight": 5.0}"#,
+            r#"{"model": "levy_flight", "exponent": 2.0, "min_flight": 6.0, "max_flight": 5.0}"#,
+            r#"{"model": "biased_walk", "target": [1, 1], "bias": 2.0}"#,
+            r#"{"model": "teleport"}"#,
+        ] {
+            assert!(MovementModel::parse(bad).is_err(), "{} was accepted", bad);
+        }
+    }
+
+    #[test]
+    fn walks_keep_or_aim_their_heading() {
+        let terrain = open_map(10, 10, &[]);
+        let mut state = WalkState {heading: 1.0, ..WalkState::default()};
+        let straight = MovementModel::CorrelatedWalk(CorrelatedWalk {turn_std_dev: 0.0});
+        assert_eq!(next(&straight, &terrain, (5, 5), 1.0, &mut state).0, Some(1.0));
+        let biased = |target| MovementModel::BiasedWalk(BiasedWalk {target, bias: 1.0});
+        let (east, _) = next(&biased((9, 5)), &terrain, (5, 5), 1.0, &mut state);
+        assert!(east.unwrap().abs() < 1e-9);
+        let (south, _) = next(&biased((5, 9)), &terrain, (5, 5), 1.0, &mut state);
+        assert!((south.unwrap() - FRAC_PI_2).abs() < 1e-9);
+        let stationary = MovementModel::Stationary(Stationary {});
+        assert_eq!(next(&stationary, &terrain, (5, 5), 1.0, &mut state).0, None);
+    }
+
+    #[test]
+    fn levy_flights_hold_their_heading_until_the_flight_ends() {
+        let terrain = open_map(10, 10, &[]);
+        let flight = MovementModel::LevyFlight(LevyFlight {exponent: 2.0, min_flight: 3.0, max_flight: 3.0});
+        let mut state = WalkState::default();
+        let (first, _) = next(&flight, &terrain, (5, 5), 1.0, &mut state);
+        for left in [1.0, 0.0] {
+            assert_eq!(next(&flight, &terrain, (5, 5), 1.0, &mut state).0, first);
+            assert_eq!(state.flight_left, left);
+        }
+        // a new flight of the same length starts
+        next(&flight, &terrain, (5, 5), 1.0, &mut state);
+        assert_eq!(state.flight_left, 2.0);
+    }
+
+    #[test]
+    fn paths_go_around_rock_and_stop_at_the_goal() {
+        // rock between the start and the goal, so the route goes south first
+        let terrain = open_map(3, 3, &[(1, 0), (1, 1)]);
+        let path = MovementModel::PathTo(PathTo {target: (2, 0), slope_penalty: None});
+        let mut state = WalkState::default();
+        let (heading, limit) = next(&path, &terrain, (0, 0), 1.0, &mut state);
+        assert!((heading.unwrap() - FRAC_PI_2).abs() < 1e-9);
+        assert_eq!(limit, None);
+        assert_eq!(state.path.as_ref().unwrap().0.cells.len(), 7);
+        // the last step is cut short at the goal
+        let (heading, limit) = next(&path, &terrain, (2, 1), 2.0, &mut state);
+        assert!((heading.unwrap() + FRAC_PI_2).abs() < 1e-9);
+        assert_eq!(limit, Some(1.0));
+        assert_eq!(next(&path, &terrain, (2, 0), 1.0, &mut state).0, None);
+    }
+}
@@ -0,0 +1,107 @@
+// What entities do when their path runs into an obstacle. Moves are traced
+// cell by cell, so a long jump can't pass through a wall or over a cliff
+use super::boundary::{BoundaryOutcome, BoundaryPolicy};
+use crate::core::topology::Topology;
+use glam::i32::IVec2;
+use pyo3::prelude::*;
+
+// Stop ends the move at the last open cell. Slide tries the steps from
+// Topology::slide_steps instead of a blocked one, so the entity moves along the
+// obstacle. Those steps only advance the move when it was diagonal, otherwise
+// they are sideways and the entity ends beside where it was blocked
+#[pyclass(eq, eq_int)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObstacleResponse {
+    Stop,
+    Slide,
+}
+
+impl ObstacleResponse {
+    pub fn from_name(name: &str) -> Option<ObstacleResponse> {
+        match name {
+            "stop" => Some(ObstacleResponse::Stop),
+            "slide" => Some(ObstacleResponse::Slide),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ObstacleResponse::Stop => "stop",
+            ObstacleResponse::Slide => "slide",
+        }
+    }
+
+    // walks movement from start one step at a time, resolving each cell with the
+    // boundary policy. can_step(from, to) decides whether a step is open
+    pub fn trace(&self, topology: Topology, boundary: BoundaryPolicy, map_dims: (u32, u32), start: (u32, u32), movement: IVec2, can_step: impl Fn((u32, u32), (u32, u32)) -> bool) -> BoundaryOutcome {
+        let origin = (start.0 as i64, start.1 as i64);
+        let target = (origin.0 + movement.x as i64, origin.1 + movement.y as i64);
+        // unbounded cell along the path, and where it ends up on the map
+        let mut cell = origin;
+        let mut position = start;
+
+        for direction in topology.line_steps(origin, target) {
+            let mut options = vec![direction];
+            if *self == ObstacleResponse::Slide {
+                options.extend(topology.slide_steps(direction));
+            }
+
+            let mut advanced = false;
+            for step in options {
+                let next = topology.step(cell, step);
+                let outside = next.0 < 0 || next.1 < 0 || next.0 >= map_dims.0 as i64 || next.1 >= map_dims.1 as i64;
+                // the map edge is a wall for the block policy
+                if outside && boundary == BoundaryPolicy::Block {
+                    continue;
+                }
+                let offset = IVec2::new((next.0 - origin.0) as i32, (next.1 - origin.1) as i32);
+                match boundary.resolve(map_dims, start, offset) {
+                    BoundaryOutcome::Exited => return BoundaryOutcome::Exited,
+                    BoundaryOutcome::Moved(resolved) => {
+                        if can_step(position, resolved) {
+                            cell = next;
+                            position = resolved;
+                            advanced = true;
+                            break;
+                        }
+                    },
+                }
+            }
+            if !advanced {
+                break;
+            }
+        }
+        BoundaryOutcome::Moved(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMS: (u32, u32) = (10, 10);
+
+    fn trace(response: ObstacleResponse, start: (u32, u32), movement: (i32, i32), walls: &[(u32, u32)]) -> (u32, u32) {
+        let can_step = |_from, to| !walls.contains(&to);
+        match response.trace(Topology::Square, BoundaryPolicy::Clamp, DIMS, start, IVec2::new(movement.0, movement.1), can_step) {
+            BoundaryOutcome::Moved(cell) => cell,
+            BoundaryOutcome::Exited => panic!("clamped moves can't exit"),
+        }
+    }
+
+    #[test]
+    fn moves_stop_before_a_wall() {
+        let wall: Vec<(u32, u32)> = (0..10).map(|y| (2, y)).collect();
+        assert_eq!(trace(ObstacleResponse::Stop, (0, 2), (4, 0), &wall), (1, 2));
+        // straight into a wall, sliding only goes sideways
+        assert_eq!(trace(ObstacleResponse::Slide, (0, 2), (4, 0), &wall), (1, 5));
+    }
+
+    #[test]
+    fn diagonal_moves_slide_past_a_corner() {
+        let corner = [(1, 1)];
+        assert_eq!(trace(ObstacleResponse::Stop, (0, 0), (3, 3), &corner), (0, 0));
+        assert_eq!(trace(ObstacleResponse::Slide, (0, 0), (3, 3), &corner), (3, 2));
+    }
+}
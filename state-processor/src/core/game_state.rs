@@ -1,56 +1,282 @@
 use crate::core::Terrain;
-use crate::core::EntityMgmt;
-use crate::core::TimeMgmt;
-use numpy::{PyArray2, PyArrayMethods};
+use crate::core::{BoundaryPolicy, EntityMgmt, MovementModel, ObstacleResponse, Relief, Topology};
+use crate::core::{EventAction, Scheduler, TimeMgmt, TimeMode, Weather};
+use crate::core::{generate_from_setup, GameSetup, Input, ReplayLog};
+use crate::core::entity_management::{Action, PopulationStats};
+use crate::core::history::{DEFAULT_SNAPSHOT_INTERVAL, History, TickState};
+use crate::core::replay::{Checksum, ReplayEntry};
+use crate::core::profiling::{PhaseReport, Profiler};
+use crate::core::systems::{Pipeline, World};
+use crate::core::terrain::{MapPoint, PathCost};
+use numpy::{Element, PyArray1, PyArray2, PyArrayMethods};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use std::sync::Arc;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 
+// added to the setup seed, so weather and regenerated maps draw from their own streams
+const WEATHER_SEED_OFFSET: u64 = 1;
+const MAP_SEED_OFFSET: u64 = 2;
+const DEFAULT_TIME_STEPS: u8 = 1;
+// [id, x, y, velocity x, velocity y, is_alive, size, action], see entity_rows
+pub const ENTITY_COLUMNS: usize = 8;
+const WHOLE_MAP_RELIEF: &str = "use get_slope and get_hillshade_region rather than generating every chunk";
+const WHOLE_MAP_ANALYSIS: &str = "analysis covers the whole map and would generate every chunk";
+
+// (materials, heights) arrays handed to the renderer
+type MapArrays<'py> = (Bound<'py, PyArray2<u8>>, Bound<'py, PyArray2<u8>>);
+type WeatherArrays<'py> = (Bound<'py, PyArray2<u8>>, Bound<'py, PyArray2<f32>>);
+// (cells from start to goal, cost)
+type PathResult = Option<(Vec<(u32, u32)>, f64)>;
+
 #[pyclass]
+#[derive(Clone)]
 pub struct GameState {
     pub time_mgmt: TimeMgmt,
     pub terrain_map: Terrain,
     pub entity_mgmt: EntityMgmt,
+    pub weather: Weather,
+    pub scheduler: Scheduler,
+    // systems run each tick, in order
+    pub systems: Pipeline,
+    // as of the last statistics run
+    stats: PopulationStats,
+    // times each phase of a tick while enabled, not part of the simulation
+    pub profiler: Profiler,
+    // None unless recording for rewind has been switched on
+    history: Option<History>,
+    // seeds the maps generated by resets
+    rng: StdRng,
+    // how the game was created and every input since, see apply
+    replay: ReplayLog,
 }
 
 impl GameState {
-    pub fn new(time: TimeMgmt, terrain: Terrain, entities: EntityMgmt) -> GameState {
-        GameState {time_mgmt: time, terrain_map: terrain, entity_mgmt: entities}
+    pub fn new(time: TimeMgmt, terrain: Terrain, entities: EntityMgmt, setup: GameSetup) -> GameState {
+        let seed = setup.seed as u64;
+        let weather = Weather::new(terrain.get_dims(), seed.wrapping_add(WEATHER_SEED_OFFSET));
+        let rng = StdRng::seed_from_u64(seed.wrapping_add(MAP_SEED_OFFSET));
+        let stats = entities.population_stats();
+        GameState {time_mgmt: time, terrain_map: terrain, entity_mgmt: entities, weather, scheduler: Scheduler::new(), systems: Pipeline::default(), stats, profiler: Profiler::default(), history: None, rng, replay: ReplayLog::new(setup)}
     }
-}
 
-#[pymethods]
-impl GameState {
-    pub fn get_terrain_map(&self) -> (u16, u16) {
-        self.terrain_map.get_dims()
+    // rebuilds a recorded run from its setup and inputs. Returns the final state
+    // and the tick after the first advance whose checksum differs from the
+    // recording, if any
+    pub fn replay(log: &ReplayLog) -> Result<(GameState, Option<u32>), String> {
+        let mut game = generate_from_setup(log.setup.clone());
+        let mut divergence = None;
+        for (i, entry) in log.entries.iter().enumerate() {
+            game.apply(entry.input.clone()).map_err(|e| format!("replay entry {} failed: {}", i, e))?;
+            let checksum = game.replay.entries.last().and_then(|replayed| replayed.checksum);
+            if divergence.is_none() && entry.checksum.is_some() && checksum != entry.checksum {
+                divergence = Some(game.time_mgmt.get_time());
+            }
+        }
+        Ok((game, divergence))
     }
 
-    pub fn reset_game_state(&mut self, same_map: Option<bool>) {
-        let reset_map = !(same_map.unwrap_or(false));
-        self.time_mgmt.reset();
-        if reset_map {
-            self.terrain_map.reset();
+    pub fn replay_log(&self) -> &ReplayLog {
+        &self.replay
+    }
+
+    // every entity in id order, ENTITY_COLUMNS values each
+    pub fn entity_rows(&self) -> Vec<f64> {
+        let mut motion: Vec<_> = self.entity_mgmt.get_all_entity_motion().into_iter().collect();
+        motion.sort_by_key(|(id, _)| *id);
+        let mut rows = Vec::with_capacity(motion.len() * ENTITY_COLUMNS);
+        for (id, ((x, y), (vx, vy))) in motion {
+            let is_alive = self.entity_mgmt.is_entity_alive(id) as u8 as f64;
+            // index into Action::ALL, -1 before the entity has decided anything
+            let action = self.entity_mgmt.get_entity_decision(id).map(|(action, _)| action.index() as f64).unwrap_or(-1.0);
+            rows.extend([id as f64, x, y, vx, vy, is_alive, self.entity_mgmt.get_entity_size(id) as f64, action]);
         }
-        self.entity_mgmt.reset();
+        rows
     }
 
-    fn get_entity_locations<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<u16>>> {
-        let num_entities = self.entity_mgmt.get_num_entities();
-        let map = self.entity_mgmt.get_all_entity_locs();
-        let mut rows: Vec<Vec<u16>> = Vec::with_capacity(num_entities);
+    // covers everything that advancing changes. Chunked maps are left out, as
+    // hashing them would generate every chunk
+    pub fn checksum(&self) -> u64 {
+        let mut sum = Checksum::default();
+        sum.write(&self.time_mgmt.get_time().to_le_bytes());
+        self.entity_mgmt.write_checksum(&mut sum);
+        self.weather.write_checksum(&mut sum);
+        self.scheduler.write_checksum(&mut sum);
+        for point in &self.terrain_map.map {
+            sum.write(&[point.height, point.material]);
+        }
+        sum.finish()
+    }
 
-        for (id, (a, b)) in map {
-            let is_alive = self.entity_mgmt.is_entity_alive(id) as u16;
-            rows.push(vec![id as u16, a, b, is_alive]);
+    // runs an input from outside the simulation and adds it to the replay log.
+    // Returns the id of a scheduled or cancelled event, or the tick reached by
+    // a rewind or seek
+    pub fn apply(&mut self, input: Input) -> Result<Option<u64>, String> {
+        let tick = self.time_mgmt.get_time();
+        let result = self.perform(&input)?;
+        self.record_history(&input);
+        let start = self.profiler.start();
+        let checksum = matches!(input, Input::Advance {..}).then(|| self.checksum());
+        if checksum.is_some() {
+            self.profiler.record("checksum", start);
         }
-        
-        Ok(PyArray2::from_vec2(py, &rows).unwrap().to_owned())
+        self.replay.record(ReplayEntry {tick, input, checksum});
+        Ok(result)
     }
 
-    fn get_entity_size(&self, id: u16) -> i8 {
-        self.entity_mgmt.get_entity_size(id)
+    // flow fields cover the whole map, so chunked maps can't use flow_to
+    fn check_movement(&self, movement: &MovementModel) -> Result<(), String> {
+        movement.validate()?;
+        if matches!(movement, MovementModel::FlowTo(_)) && self.terrain_map.is_chunked() {
+            return Err("flow_to needs a flow field, which chunked maps don't have, use path_to".to_string());
+        }
+        Ok(())
     }
 
-    fn get_map_data<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyArray2<u8>>, Bound<'py, PyArray2<u8>>)> {
+    // terrain edits must start on the map and paint a known material
+    fn check_edit(&self, input: &Input) -> Result<(), String> {
         let (width, height) = self.terrain_map.get_dims();
+        let origin = match input {
+            Input::PaintCircle {x, y, ..} | Input::PaintRect {x, y, ..} | Input::AdjustHeight {x, y, ..}
+            | Input::Flatten {x, y, ..} | Input::Smooth {x, y, ..} => Some((*x, *y)),
+            _ => None,
+        };
+        if let Some((x, y)) = origin && (x >= width || y >= height) {
+            return Err(format!("({}, {}) is outside the {}x{} map", x, y, width, height));
+        }
+        let material = match input {
+            Input::PaintCircle {material, ..} | Input::PaintRect {material, ..} | Input::PaintPolygon {material, ..} => Some(*material),
+            _ => None,
+        };
+        if let Some(material) = material && self.terrain_map.materials.get(material).is_none() {
+            return Err(format!("unknown material {}", material));
+        }
+        if let Some((min_x, min_y, max_x, max_y)) = input.edit_bounds() {
+            self.terrain_map.check_edit(min_x, min_y, max_x, max_y)?;
+        }
+        Ok(())
+    }
+
+    fn perform(&mut self, input: &Input) -> Result<Option<u64>, String> {
+        self.check_edit(input)?;
+        match input {
+            Input::Advance {steps} => self.step(*steps),
+            Input::Reset {same_map} => self.reset(*same_map),
+            Input::SetBoundaryPolicy {policy} => {
+                let policy = BoundaryPolicy::from_name(policy).ok_or_else(|| format!("unknown boundary policy '{}'", policy))?;
+                self.entity_mgmt.set_boundary_policy(policy);
+            },
+            Input::SetObstacleResponse {response} => {
+                let response = ObstacleResponse::from_name(response).ok_or_else(|| format!("unknown obstacle response '{}'", response))?;
+                self.entity_mgmt.set_obstacle_response(response);
+            },
+            Input::SetCliffHeight {cliff_height} => self.terrain_map.set_cliff_height(*cliff_height),
+            Input::SetEntityTraits {id, traits} => {
+                if !self.entity_mgmt.set_traits(*id, traits.clone()) {
+                    return Err(format!("no entity with id {}", id));
+                }
+            },
+            Input::SetEntityMovement {id, movement} => {
+                movement.iter().try_for_each(|movement| self.check_movement(movement))?;
+                if !self.entity_mgmt.set_entity_movement(*id, movement.clone()) {
+                    return Err(format!("no entity with id {}", id));
+                }
+            },
+            Input::SetSpeciesMovement {species, movement} => {
+                movement.iter().try_for_each(|movement| self.check_movement(movement))?;
+                self.entity_mgmt.set_species_movement(species.clone(), movement.clone());
+            },
+            Input::SetDefaultMovement {movement} => {
+                self.check_movement(movement)?;
+                self.entity_mgmt.set_default_movement(movement.clone());
+            },
+            Input::SetActionWeight {action, weight} => self.entity_mgmt.set_action_weight(action, *weight)?,
+            Input::SetCalendar {ticks_per_day, days_per_season, seasons_per_year} => {
+                self.time_mgmt.set_calendar(*ticks_per_day, *days_per_season, *seasons_per_year)?;
+            },
+            Input::SetSeasonsEnabled {enabled} => self.terrain_map.set_seasons_enabled(*enabled),
+            Input::SetWeatherEnabled {enabled} => self.weather.set_enabled(*enabled),
+            Input::PaintCircle {x, y, radius, material} => self.terrain_map.paint_circle(*x, *y, *radius, *material),
+            Input::PaintRect {x, y, width, height, material} => self.terrain_map.paint_rect(*x, *y, *width, *height, *material),
+            Input::PaintPolygon {points, material} => self.terrain_map.paint_polygon(points, *material),
+            Input::AdjustHeight {x, y, radius, amount} => self.terrain_map.adjust_height(*x, *y, *radius, *amount),
+            Input::Flatten {x, y, radius, target} => {
+                let height = target.unwrap_or_else(|| self.terrain_map.get_height(*x, *y));
+                self.terrain_map.flatten(*x, *y, *radius, height);
+            },
+            Input::Smooth {x, y, radius, iterations} => self.terrain_map.smooth(*x, *y, *radius, *iterations),
+            Input::Schedule {tick, interval, action} => {
+                if let EventAction::SpawnCohort {area, ..} = action {
+                    self.entity_mgmt.cohort_area(*area)?;
+                }
+                return Ok(Some(self.schedule(*tick, *interval, action.clone())));
+            },
+            Input::Gestation {entity_id, duration} => {
+                if !self.entity_mgmt.set_pregnant(*entity_id, true) {
+                    return Err(format!("no living entity with id {}", entity_id));
+                }
+                let tick = self.time_mgmt.get_time().saturating_add(*duration);
+                return Ok(Some(self.schedule(tick, None, EventAction::EndGestation {mother: *entity_id})));
+            },
+            Input::CancelEvent {id} => return Ok(self.scheduler.cancel(*id).then_some(*id)),
+            Input::SetTimeMode {mode} => {
+                let mode = TimeMode::from_name(mode).ok_or_else(|| format!("unknown time mode '{}'", mode))?;
+                self.time_mgmt.set_mode(mode);
+            },
+            Input::SetSystemEnabled {name, enabled} => {
+                self.systems.set_enabled(name, *enabled)?;
+                // otherwise entities would keep acting on their last decision
+                if name == "decisions" && !*enabled {
+                    self.entity_mgmt.clear_decisions();
+                }
+            },
+            Input::SetSystemInterval {name, interval} => self.systems.set_interval(name, *interval)?,
+            Input::SetSystemOrder {names} => self.systems.set_order(names)?,
+            Input::EnableHistory {length, snapshot_interval} => {
+                if *length == 0 || *snapshot_interval == 0 {
+                    return Err("length and snapshot_interval must be at least 1".to_string());
+                }
+                // chunked maps can't be copied without generating every chunk
+                if self.terrain_map.is_chunked() {
+                    return Err("history needs the whole map, which chunked maps don't keep".to_string());
+                }
+                let mut history = History::new(*length, *snapshot_interval);
+                history.start(self.time_mgmt.get_time(), self.tick_state());
+                self.history = Some(history);
+            },
+            Input::DisableHistory => self.history = None,
+            Input::Rewind {ticks} => {
+                let restored = self.history_mut()?.rewind(*ticks);
+                return self.restore(restored).map(|tick| Some(tick.into()));
+            },
+            Input::Seek {tick} => {
+                let restored = self.history_mut()?.seek(*tick);
+                return self.restore(restored).map(|tick| Some(tick.into()));
+            },
+        }
+        Ok(None)
+    }
+
+    // whole-map queries would generate every chunk of a chunked map
+    fn check_whole_map(&self, instead: &str) -> PyResult<()> {
+        if self.terrain_map.is_chunked() {
+            return Err(PyValueError::new_err(format!("map is chunked, {}", instead)));
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, input: Input) -> PyResult<Option<u64>> {
+        self.apply(input).map_err(PyValueError::new_err)
+    }
+
+    fn run_for_id(&mut self, input: Input) -> PyResult<u64> {
+        Ok(self.run(input)?.unwrap_or_default())
+    }
+
+    fn points_to_arrays<'py>(py: Python<'py>, dims: (u32, u32), points: &[MapPoint]) -> PyResult<MapArrays<'py>> {
+        let (width, height) = dims;
         let size = (height as usize, width as usize);
         
         // Create numpy arrays
@@ -62,7 +288,7 @@ impl GameState {
             let materials_slice = materials.as_slice_mut()?;
             let heights_slice = heights.as_slice_mut()?;
             
-            for (i, point) in self.terrain_map.map.iter().enumerate() {
+            for (i, point) in points.iter().enumerate() {
                 materials_slice[i] = point.material;
                 heights_slice[i] = point.height;
             }
@@ -71,8 +297,866 @@ impl GameState {
         Ok((materials, heights))
     }
 
-    pub fn advance_state(&mut self) {
+    // row-major values as a (height, width) array
+    fn values_to_array<'py, T: Element>(py: Python<'py>, dims: (u32, u32), values: Vec<T>) -> PyResult<Bound<'py, PyArray2<T>>> {
+        PyArray1::from_vec(py, values).reshape([dims.1 as usize, dims.0 as usize])
+    }
+
+    // row-major values covering the whole map as a (height, width) array
+    fn grid_to_array<'py, T: Element>(&self, py: Python<'py>, values: Vec<T>) -> PyResult<Bound<'py, PyArray2<T>>> {
+        Self::values_to_array(py, self.terrain_map.get_dims(), values)
+    }
+
+    // for Rust systems, ticks are absolute. Returns the event id
+    pub fn schedule(&mut self, tick: u32, interval: Option<u32>, action: EventAction) -> u64 {
+        self.scheduler.schedule(tick, interval, action)
+    }
+
+    fn tick_state(&self) -> TickState {
+        TickState {
+            time_mgmt: self.time_mgmt.clone(),
+            terrain: self.terrain_map.clone(),
+            entity_mgmt: self.entity_mgmt.clone(),
+            weather: self.weather.clone(),
+            scheduler: self.scheduler.clone(),
+            systems: self.systems.clone(),
+            stats: self.stats.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+
+    // history manages itself for its own inputs and resets, see perform
+    fn record_history(&mut self, input: &Input) {
+        if self.history.is_none() {
+            return;
+        }
+        match input {
+            Input::EnableHistory {..} | Input::DisableHistory | Input::Rewind {..} | Input::Seek {..} | Input::Reset {..} => {},
+            Input::Advance {..} => {
+                let start = self.profiler.start();
+                let state = self.history.as_ref().is_some_and(History::state_due).then(|| self.tick_state());
+                let tick = self.time_mgmt.get_time();
+                if let Some(history) = &mut self.history {
+                    history.record_tick(tick, input.clone(), state);
+                }
+                self.profiler.record("history", start);
+            },
+            _ => {
+                if let Some(history) = &mut self.history {
+                    history.record_input(input.clone());
+                }
+            },
+        }
+    }
+
+    // puts back a full state, then performs the inputs from it to the tick
+    // sought. History is set aside meanwhile, so nothing is recorded twice, and
+    // the profiler is paused as these aren't new ticks
+    fn restore(&mut self, restored: Option<(TickState, Vec<Input>)>) -> Result<u32, String> {
+        let (state, inputs) = restored.ok_or_else(|| "tick is not in the history".to_string())?;
+        self.time_mgmt = state.time_mgmt;
+        self.terrain_map.restore(state.terrain);
+        self.entity_mgmt = state.entity_mgmt;
+        self.weather = state.weather;
+        self.scheduler = state.scheduler;
+        self.systems = state.systems;
+        self.stats = state.stats;
+        self.rng = state.rng;
+        let history = self.history.take();
+        let profiling = self.profiler.is_enabled();
+        self.profiler.set_enabled(false);
+        let performed = inputs.iter().try_for_each(|input| self.perform(input).map(|_| ()));
+        self.profiler.set_enabled(profiling);
+        self.history = history;
+        performed.map_err(|e| format!("rebuilding tick failed: {}", e))?;
+        Ok(self.time_mgmt.get_time())
+    }
+
+    fn history_mut(&mut self) -> Result<&mut History, String> {
+        self.history.as_mut().ok_or_else(|| "history is not enabled".to_string())
+    }
+
+    fn reset(&mut self, same_map: bool) {
+        self.time_mgmt.reset();
+        self.weather.reset();
+        self.scheduler.clear();
+        if !same_map {
+            self.terrain_map.reset(self.rng.random());
+        }
+        self.entity_mgmt.reset();
+        self.stats = self.entity_mgmt.population_stats();
+        if self.history.is_some() {
+            let state = self.tick_state();
+            if let Some(history) = &mut self.history {
+                history.start(self.time_mgmt.get_time(), state);
+            }
+        }
+    }
+
+    fn step(&mut self, steps: Option<u8>) {
+        let tick_start = self.profiler.start();
         self.time_mgmt.update();
-        self.entity_mgmt.advance_time(&self.terrain_map, None);
+        let start = self.profiler.start();
+        self.run_due_events();
+        self.profiler.record("events", start);
+        let now = self.time_mgmt.get_time();
+        let (date, daylight) = (self.time_mgmt.get_date(), self.time_mgmt.daylight(now));
+        let mut world = World {
+            time: &mut self.time_mgmt,
+            date,
+            daylight,
+            elapsed: 1,
+            steps: steps.unwrap_or(DEFAULT_TIME_STEPS),
+            deciding: self.systems.is_enabled("decisions"),
+            terrain: &mut self.terrain_map,
+            weather: &mut self.weather,
+            entities: &mut self.entity_mgmt,
+            scheduler: &mut self.scheduler,
+            stats: &mut self.stats,
+        };
+        self.systems.run(&mut world, &mut self.profiler);
+        self.profiler.record("tick", tick_start);
+    }
+
+    fn run_due_events(&mut self) {
+        let now = self.time_mgmt.get_time();
+        while let Some(event) = self.scheduler.pop_due(now) {
+            match event.action {
+                // the area was checked when the event was scheduled
+                EventAction::SpawnCohort {count, area} => {
+                    self.entity_mgmt.spawn_cohort(count, area).ok();
+                },
+                EventAction::PaintMaterial {x, y, radius, material} => self.terrain_map.paint_circle(x, y, radius, material),
+                EventAction::AdjustHeight {x, y, radius, amount} => self.terrain_map.adjust_height(x, y, radius, amount),
+                EventAction::Cull {fraction} => {
+                    self.entity_mgmt.cull(fraction);
+                },
+                EventAction::EndGestation {mother} => {
+                    self.entity_mgmt.give_birth(mother);
+                },
+                EventAction::Custom {..} => {},
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl GameState {
+    pub fn get_terrain_map(&self) -> (u32, u32) {
+        self.terrain_map.get_dims()
+    }
+
+    #[pyo3(signature = (same_map=None))]
+    pub fn reset_game_state(&mut self, same_map: Option<bool>) -> PyResult<()> {
+        self.run(Input::Reset {same_map: same_map.unwrap_or(false)})?;
+        Ok(())
+    }
+
+    fn get_entity_locations<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<u32>>> {
+        let num_entities = self.entity_mgmt.get_num_entities();
+        let map = self.entity_mgmt.get_all_entity_locs();
+        let mut rows: Vec<Vec<u32>> = Vec::with_capacity(num_entities);
+
+        for (id, (a, b)) in map {
+            let is_alive = self.entity_mgmt.is_entity_alive(id) as u32;
+            rows.push(vec![id, a, b, is_alive]);
+        }
+        
+        Ok(PyArray2::from_vec2(py, &rows).unwrap().to_owned())
+    }
+
+    // rows of [id, x, y, velocity x, velocity y, is_alive, size, action] with
+    // positions and velocities in world space, where neighbouring cell centres
+    // are 1 apart. action indexes "forage", "drink", "seek_mate", "rest",
+    // "flee" and "wander", or is -1 for none
+    fn get_entity_positions<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        let values = self.entity_rows();
+        Self::values_to_array(py, (ENTITY_COLUMNS as u32, (values.len() / ENTITY_COLUMNS) as u32), values)
+    }
+
+    // one of "clamp", "reflect", "wrap", "block", "kill" or "emigrate"
+    fn set_boundary_policy(&mut self, policy: &str) -> PyResult<()> {
+        self.run(Input::SetBoundaryPolicy {policy: policy.to_string()})?;
+        Ok(())
+    }
+
+    fn get_boundary_policy(&self) -> &'static str {
+        self.entity_mgmt.get_boundary_policy().name()
+    }
+
+    // "stop" or "slide", what entities do when their path is blocked
+    fn set_obstacle_response(&mut self, response: &str) -> PyResult<()> {
+        self.run(Input::SetObstacleResponse {response: response.to_string()})?;
+        Ok(())
+    }
+
+    fn get_obstacle_response(&self) -> &'static str {
+        self.entity_mgmt.get_obstacle_response().name()
+    }
+
+    // neighbouring cells further apart in height than this are cliffs
+    fn set_cliff_height(&mut self, cliff_height: u8) -> PyResult<()> {
+        self.run(Input::SetCliffHeight {cliff_height})?;
+        Ok(())
+    }
+
+    fn get_cliff_height(&self) -> u8 {
+        self.terrain_map.get_cliff_height()
+    }
+
+    fn is_obstacle(&self, x: u32, y: u32) -> bool {
+        self.terrain_map.is_obstacle(x, y, self.time_mgmt.get_season())
+    }
+
+    fn get_num_emigrated(&self) -> u32 {
+        self.entity_mgmt.get_num_emigrated()
+    }
+
+    // ids of living entities within radius, measured according to the boundary policy
+    fn get_neighbours(&self, id: u32, radius: f64) -> Vec<u32> {
+        self.entity_mgmt.get_neighbours(id, radius)
+    }
+
+    fn get_entity_distance(&self, id_a: u32, id_b: u32) -> Option<f64> {
+        self.entity_mgmt.distance_between(id_a, id_b)
+    }
+
+    // traits control material speeds and behaviour, e.g. "nocturnal"
+    fn get_entity_traits(&self, id: u32) -> Option<Vec<String>> {
+        self.entity_mgmt.get_traits(id)
+    }
+
+    fn set_entity_traits(&mut self, id: u32, traits: Vec<String>) -> PyResult<()> {
+        self.run(Input::SetEntityTraits {id, traits})?;
+        Ok(())
+    }
+
+    // movement as JSON, e.g. {"model": "levy_flight", "exponent": 2.0,
+    // "min_flight": 1.0, "max_flight": 50.0}. Models are random_walk,
+    // correlated_walk (turn_std_dev), levy_flight, biased_walk (target, bias)
+    // and stationary. None returns the entity to its species or the default
+    #[pyo3(signature = (id, movement=None))]
+    fn set_entity_movement(&mut self, id: u32, movement: Option<String>) -> PyResult<()> {
+        let movement = movement.map(|text| MovementModel::parse(&text)).transpose().map_err(PyValueError::new_err)?;
+        self.run(Input::SetEntityMovement {id, movement})?;
+        Ok(())
+    }
+
+    // movement for entities with the given trait, unless they have their own
+    #[pyo3(signature = (species, movement=None))]
+    fn set_species_movement(&mut self, species: String, movement: Option<String>) -> PyResult<()> {
+        let movement = movement.map(|text| MovementModel::parse(&text)).transpose().map_err(PyValueError::new_err)?;
+        self.run(Input::SetSpeciesMovement {species, movement})?;
+        Ok(())
+    }
+
+    // movement for entities without their own or a species one, random_walk
+    // unless changed
+    fn set_default_movement(&mut self, movement: String) -> PyResult<()> {
+        let movement = MovementModel::parse(&movement).map_err(PyValueError::new_err)?;
+        self.run(Input::SetDefaultMovement {movement})?;
+        Ok(())
+    }
+
+    // how much each action's score counts when entities decide what to do, see
+    // the decisions system
+    fn set_action_weight(&mut self, action: String, weight: f64) -> PyResult<()> {
+        self.run(Input::SetActionWeight {action, weight})?;
+        Ok(())
+    }
+
+    fn get_action_weights(&self) -> HashMap<&'static str, f64> {
+        self.entity_mgmt.get_action_weights().into_iter().map(|(action, weight)| (action.name(), weight)).collect()
+    }
+
+    // one of "forage", "drink", "seek_mate", "rest", "flee" or "wander", None
+    // until the decisions system has run
+    fn get_entity_action(&self, id: u32) -> Option<&'static str> {
+        self.entity_mgmt.get_entity_decision(id).map(|(action, _)| action.name())
+    }
+
+    // weighted score of every action at the entity's last decision
+    fn get_entity_scores(&self, id: u32) -> Option<HashMap<&'static str, f64>> {
+        let (_, scores) = self.entity_mgmt.get_entity_decision(id)?;
+        Some(Action::ALL.iter().map(|action| (action.name(), scores[action.index()])).collect())
+    }
+
+    // the movement the entity follows, as JSON
+    fn get_entity_movement(&self, id: u32) -> Option<String> {
+        self.entity_mgmt.get_entity_movement(id).map(MovementModel::to_json)
+    }
+
+    fn get_entity_size(&self, id: u32) -> i8 {
+        self.entity_mgmt.get_entity_size(id)
+    }
+
+    // chunked maps are too large to hand over whole, use get_map_region for them
+    fn get_map_data<'py>(&self, py: Python<'py>) -> PyResult<MapArrays<'py>> {
+        self.check_whole_map("use get_map_region for a window of it")?;
+        Self::points_to_arrays(py, self.terrain_map.get_dims(), &self.terrain_map.map)
+    }
+
+    // same layout as get_map_data, but only for the requested window of the map
+    fn get_map_region<'py>(&self, py: Python<'py>, x: u32, y: u32, width: u32, height: u32) -> PyResult<MapArrays<'py>> {
+        let (region_width, region_height, points) = self.terrain_map.get_region(x, y, width, height);
+        Self::points_to_arrays(py, (region_width, region_height), &points)
+    }
+
+    // "square" or "hex". Hex maps use the odd-r offset layout in get_map_data,
+    // i.e. odd rows are drawn shifted half a cell to the right
+    fn get_topology(&self) -> &'static str {
+        self.terrain_map.topology().name()
+    }
+
+    fn get_cell_neighbours(&self, x: u32, y: u32) -> Vec<(u32, u32)> {
+        self.terrain_map.neighbours(x, y)
+    }
+
+    // hex distance is the number of steps between cells
+    fn get_cell_distance(&self, a: (u32, u32), b: (u32, u32)) -> f64 {
+        self.terrain_map.topology().distance((a.0 as i64, a.1 as i64), (b.0 as i64, b.1 as i64))
+    }
+
+    // (q, r) axial coordinates of an offset (x, y) cell on a hex map
+    fn get_axial_coords(&self, x: u32, y: u32) -> (i64, i64) {
+        Topology::offset_to_axial(x as i64, y as i64)
+    }
+
+    fn get_time(&self) -> u32 {
+        self.time_mgmt.get_time()
+    }
+
+    fn get_season(&self) -> &'static str {
+        self.time_mgmt.get_season().name()
+    }
+
+    // (year, season, day of season, tick of day), all counted from zero
+    #[pyo3(signature = (tick=None))]
+    fn get_date(&self, tick: Option<u32>) -> (u32, &'static str, u32, u32) {
+        let date = self.time_mgmt.date_at(tick.unwrap_or(self.time_mgmt.get_time()));
+        (date.year, date.season.name(), date.day, date.tick_of_day)
+    }
+
+    // fraction of the day from 0 (midnight) to 1
+    fn get_time_of_day(&self) -> f64 {
+        self.time_mgmt.time_of_day(self.time_mgmt.get_time())
+    }
+
+    // brightness from 0.2 at night to 1 at midday, for tinting the map
+    fn get_ambient_light(&self) -> f64 {
+        self.time_mgmt.ambient_light(self.time_mgmt.get_time())
+    }
+
+    fn set_calendar(&mut self, ticks_per_day: u32, days_per_season: u32, seasons_per_year: u32) -> PyResult<()> {
+        self.run(Input::SetCalendar {ticks_per_day, days_per_season, seasons_per_year})?;
+        Ok(())
+    }
+
+    // (ticks per day, days per season, seasons per year)
+    fn get_calendar(&self) -> (u32, u32, u32) {
+        self.time_mgmt.get_calendar()
+    }
+
+    fn set_seasons_enabled(&mut self, enabled: bool) -> PyResult<()> {
+        self.run(Input::SetSeasonsEnabled {enabled})?;
+        Ok(())
+    }
+
+    // material after seasonal freezing and thawing, at the current tick by default
+    #[pyo3(signature = (x, y, tick=None))]
+    fn get_effective_material(&self, x: u32, y: u32, tick: Option<u32>) -> u8 {
+        let season = self.time_mgmt.season_at(tick.unwrap_or(self.time_mgmt.get_time()));
+        self.terrain_map.effective_material(x, y, season)
+    }
+
+    #[pyo3(signature = (x, y, tick=None))]
+    fn get_effective_food_yield(&self, x: u32, y: u32, tick: Option<u32>) -> f64 {
+        let season = self.time_mgmt.season_at(tick.unwrap_or(self.time_mgmt.get_time()));
+        self.terrain_map.effective_food_yield(x, y, season)
+    }
+
+    // like get_map_region, but with materials as they are in the season of the given tick
+    #[pyo3(signature = (x, y, width, height, tick=None))]
+    fn get_effective_map_region<'py>(&self, py: Python<'py>, x: u32, y: u32, width: u32, height: u32, tick: Option<u32>) -> PyResult<MapArrays<'py>> {
+        let season = self.time_mgmt.season_at(tick.unwrap_or(self.time_mgmt.get_time()));
+        let (region_width, region_height, mut points) = self.terrain_map.get_region(x, y, width, height);
+        let (_, _, materials) = self.terrain_map.effective_region(x, y, width, height, season);
+        for (point, material) in points.iter_mut().zip(materials) {
+            point.material = material;
+        }
+        Self::points_to_arrays(py, (region_width, region_height), &points)
+    }
+
+    // material id -> number of cells. Like the other analyses below, this visits
+    // every cell, so chunked maps are refused
+    fn get_material_areas(&self) -> PyResult<HashMap<u8, u64>> {
+        self.check_whole_map(WHOLE_MAP_ANALYSIS)?;
+        Ok(self.terrain_map.material_areas())
+    }
+
+    // material id -> count of cells at each height, indexed 0 to depth
+    fn get_height_histograms(&self) -> PyResult<HashMap<u8, Vec<u64>>> {
+        self.check_whole_map(WHOLE_MAP_ANALYSIS)?;
+        Ok(self.terrain_map.height_histograms())
+    }
+
+    // (labels, count), where neighbouring cells of the same material share a label
+    fn label_material_regions<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyArray2<i32>>, u32)> {
+        self.check_whole_map(WHOLE_MAP_ANALYSIS)?;
+        let (labels, count) = self.terrain_map.label_material_regions();
+        Ok((self.grid_to_array(py, labels)?, count))
+    }
+
+    // (labels, count) of areas connected without crossing obstacles, impassable cells are -1
+    fn label_walkable_regions<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyArray2<i32>>, u32)> {
+        self.check_whole_map(WHOLE_MAP_ANALYSIS)?;
+        let (labels, count) = self.terrain_map.label_walkable_regions(self.time_mgmt.get_season());
+        Ok((self.grid_to_array(py, labels)?, count))
+    }
+
+    // movement cost from (x, y) to every cell in the current season, inf where unreachable
+    #[pyo3(signature = (x, y, traits=None, max_cost=None))]
+    fn get_reachability<'py>(&self, py: Python<'py>, x: u32, y: u32, traits: Option<Vec<String>>, max_cost: Option<f64>) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        self.check_whole_map(WHOLE_MAP_ANALYSIS)?;
+        let traits = traits.unwrap_or_default();
+        let costs = self.terrain_map.reachability((x, y), &traits, max_cost, self.time_mgmt.get_season());
+        self.grid_to_array(py, costs)
+    }
+
+    // (cells from start to goal, cost) of the cheapest route in the current
+    // season, or None if there isn't one. algorithm is "astar" (the default,
+    // shared with entities through the path cache) or "dijkstra"
+    #[pyo3(signature = (start, goal, traits=None, slope_penalty=None, algorithm=None))]
+    fn find_path(&mut self, start: (u32, u32), goal: (u32, u32), traits: Option<Vec<String>>, slope_penalty: Option<f64>, algorithm: Option<String>) -> PyResult<PathResult> {
+        let cost = PathCost::new(&traits.unwrap_or_default(), self.time_mgmt.get_season(), slope_penalty);
+        let path = match algorithm.as_deref().unwrap_or("astar") {
+            "astar" => self.entity_mgmt.path_cache().path(&self.terrain_map, start, goal, &cost),
+            "dijkstra" => self.terrain_map.find_path(start, goal, &cost, false).map(Arc::new),
+            other => return Err(PyValueError::new_err(format!("unknown algorithm: {}", other))),
+        };
+        Ok(path.map(|path| (path.cells.clone(), path.cost)))
+    }
+
+    // cost of the cheapest route from every cell to goal, inf where unreachable.
+    // Entities using flow_to movement step toward lower costs
+    #[pyo3(signature = (goal, traits=None, slope_penalty=None))]
+    fn get_flow_field<'py>(&mut self, py: Python<'py>, goal: (u32, u32), traits: Option<Vec<String>>, slope_penalty: Option<f64>) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        let cost = PathCost::new(&traits.unwrap_or_default(), self.time_mgmt.get_season(), slope_penalty);
+        let field = self.entity_mgmt.path_cache().flow_field(&self.terrain_map, goal, &cost).map_err(PyValueError::new_err)?;
+        self.grid_to_array(py, field.costs.clone())
+    }
+
+    // cells left on the route an entity using path_to movement is following
+    fn get_entity_path(&self, id: u32) -> Option<Vec<(u32, u32)>> {
+        self.entity_mgmt.get_entity_path(id)
+    }
+
+    // (hits, misses) of the path cache since the game started
+    fn get_path_cache_stats(&mut self) -> (u64, u64) {
+        self.entity_mgmt.path_cache().stats()
+    }
+
+    // sun direction and altitude in degrees, and height units per cell width.
+    // Anything left out keeps its current value
+    #[pyo3(signature = (azimuth=None, altitude=None, z_factor=None))]
+    fn set_relief(&mut self, azimuth: Option<f64>, altitude: Option<f64>, z_factor: Option<f64>) {
+        let current = self.terrain_map.get_relief();
+        self.terrain_map.set_relief(Relief {
+            azimuth: azimuth.unwrap_or(current.azimuth),
+            altitude: altitude.unwrap_or(current.altitude),
+            z_factor: z_factor.unwrap_or(current.z_factor),
+        });
+    }
+
+    // slope in degrees at a single cell
+    fn get_slope(&self, x: u32, y: u32) -> f64 {
+        self.terrain_map.slope(x, y)
+    }
+
+    // the whole-map slope, aspect and hillshade are refused for chunked maps,
+    // use get_hillshade_region for a window of them
+    fn get_slope_map<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        self.check_whole_map(WHOLE_MAP_RELIEF)?;
+        let (width, height) = self.terrain_map.get_dims();
+        let (_, _, slopes) = self.terrain_map.slope_region(0, 0, width, height);
+        self.grid_to_array(py, slopes)
+    }
+
+    // downhill direction in degrees clockwise from north, -1 where flat
+    fn get_aspect_map<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        self.check_whole_map(WHOLE_MAP_RELIEF)?;
+        let (width, height) = self.terrain_map.get_dims();
+        let (_, _, aspects) = self.terrain_map.aspect_region(0, 0, width, height);
+        self.grid_to_array(py, aspects)
+    }
+
+    // 0 to 255 brightness lit from the sun set by set_relief
+    fn get_hillshade<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        self.check_whole_map("use get_hillshade_region for a window of it")?;
+        let (width, height) = self.terrain_map.get_dims();
+        self.get_hillshade_region(py, 0, 0, width, height)
+    }
+
+    fn get_hillshade_region<'py>(&self, py: Python<'py>, x: u32, y: u32, width: u32, height: u32) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        let (region_width, region_height, shade) = self.terrain_map.hillshade_region(x, y, width, height);
+        Self::values_to_array(py, (region_width, region_height), shade)
+    }
+
+    fn set_weather_enabled(&mut self, enabled: bool) -> PyResult<()> {
+        self.run(Input::SetWeatherEnabled {enabled})?;
+        Ok(())
+    }
+
+    // times every phase of each tick from now on: events, each system, history,
+    // the replay checksum and the tick as a whole
+    fn enable_profiling(&mut self) {
+        self.profiler.set_enabled(true);
+    }
+
+    // stops timing, the times so far are kept until reset_profiling
+    fn disable_profiling(&mut self) {
+        self.profiler.set_enabled(false);
+    }
+
+    fn reset_profiling(&mut self) {
+        self.profiler.clear();
+    }
+
+    // a dict per phase with calls and total, mean, min and max milliseconds
+    fn get_profile(&self) -> Vec<PhaseReport> {
+        self.profiler.report()
+    }
+
+    // get_profile as a printable table
+    fn format_profile(&self) -> String {
+        self.profiler.format_report()
+    }
+
+    // "ticks" or "events", see set_time_mode
+    fn get_time_mode(&self) -> &'static str {
+        self.time_mgmt.get_mode().name()
+    }
+
+    // "events" runs movement, exposure deaths and conception as continuous-time
+    // events with exponentially distributed waits, "ticks" runs them once a tick
+    fn set_time_mode(&mut self, mode: String) -> PyResult<()> {
+        self.run(Input::SetTimeMode {mode})?;
+        Ok(())
+    }
+
+    // continuous time in event mode, the tick otherwise
+    fn get_clock(&self) -> f64 {
+        self.time_mgmt.get_clock()
+    }
+
+    // (name, enabled, interval) of each system, in the order they run each tick
+    fn get_systems(&self) -> Vec<(&'static str, bool, u32)> {
+        self.systems.systems()
+    }
+
+    fn set_system_enabled(&mut self, name: String, enabled: bool) -> PyResult<()> {
+        self.run(Input::SetSystemEnabled {name, enabled})?;
+        Ok(())
+    }
+
+    // the system runs on ticks that are a multiple of interval
+    fn set_system_interval(&mut self, name: String, interval: u32) -> PyResult<()> {
+        self.run(Input::SetSystemInterval {name, interval})?;
+        Ok(())
+    }
+
+    // names must list every system from get_systems exactly once
+    fn set_system_order(&mut self, names: Vec<String>) -> PyResult<()> {
+        self.run(Input::SetSystemOrder {names})?;
+        Ok(())
+    }
+
+    // alive, dead, pregnant and emigrated counts with mean age, hunger and
+    // thirst, as of the last tick the statistics system ran
+    fn get_population_stats(&self) -> PopulationStats {
+        self.stats.clone()
+    }
+
+    // ("clear" | "rain" | "snow" | "heat", intensity from 0 to 1) over a cell
+    fn get_weather_at(&self, x: u32, y: u32) -> (&'static str, f64) {
+        let (kind, intensity) = self.weather.weather_at(x, y);
+        (kind.name(), intensity)
+    }
+
+    // (kind, centre x, centre y, radius, intensity) of every active front
+    fn get_weather_fronts(&self) -> Vec<(&'static str, f64, f64, f64, f64)> {
+        self.weather.fronts().iter()
+            .map(|front| (front.kind.name(), front.centre.0, front.centre.1, front.radius, front.intensity))
+            .collect()
+    }
+
+    // (kinds, intensities) arrays for a window of the map, kinds are 0 clear,
+    // 1 rain, 2 snow and 3 heat. Covers the whole map by default, chunked maps
+    // need a width and height
+    #[pyo3(signature = (x=None, y=None, width=None, height=None))]
+    fn get_weather_map<'py>(&self, py: Python<'py>, x: Option<u32>, y: Option<u32>, width: Option<u32>, height: Option<u32>) -> PyResult<WeatherArrays<'py>> {
+        if width.is_none() || height.is_none() {
+            self.check_whole_map("give get_weather_map a width and height")?;
+        }
+        let (map_width, map_height) = self.terrain_map.get_dims();
+        let (region_width, region_height, kinds, intensities) = self.weather.raster(x.unwrap_or(0), y.unwrap_or(0), width.unwrap_or(map_width), height.unwrap_or(map_height));
+        let dims = (region_width, region_height);
+        Ok((Self::values_to_array(py, dims, kinds)?, Self::values_to_array(py, dims, intensities)?))
+    }
+
+    // material id -> (r, g, b), from the material registry
+    fn get_material_colours(&self) -> HashMap<u8, (u8, u8, u8)> {
+        self.terrain_map.materials.colours()
+    }
+
+    fn get_material_names(&self) -> HashMap<u8, String> {
+        self.terrain_map.materials.names()
+    }
+
+    // terrain edits, the area they touched can be fetched with take_dirty_rect
+    fn paint_material(&mut self, x: u32, y: u32, radius: u32, material: u8) -> PyResult<()> {
+        self.run(Input::PaintCircle {x, y, radius, material})?;
+        Ok(())
+    }
+
+    fn paint_material_rect(&mut self, x: u32, y: u32, width: u32, height: u32, material: u8) -> PyResult<()> {
+        self.run(Input::PaintRect {x, y, width, height, material})?;
+        Ok(())
+    }
+
+    fn paint_material_polygon(&mut self, points: Vec<(f64, f64)>, material: u8) -> PyResult<()> {
+        self.run(Input::PaintPolygon {points, material})?;
+        Ok(())
+    }
+
+    #[pyo3(signature = (x, y, radius, amount=None))]
+    fn raise_terrain(&mut self, x: u32, y: u32, radius: u32, amount: Option<u8>) -> PyResult<()> {
+        self.run(Input::AdjustHeight {x, y, radius, amount: amount.unwrap_or(1) as i16})?;
+        Ok(())
+    }
+
+    #[pyo3(signature = (x, y, radius, amount=None))]
+    fn lower_terrain(&mut self, x: u32, y: u32, radius: u32, amount: Option<u8>) -> PyResult<()> {
+        self.run(Input::AdjustHeight {x, y, radius, amount: -(amount.unwrap_or(1) as i16)})?;
+        Ok(())
+    }
+
+    // flattens to the given height, or the height at the centre if not given
+    #[pyo3(signature = (x, y, radius, target=None))]
+    fn flatten_terrain(&mut self, x: u32, y: u32, radius: u32, target: Option<u8>) -> PyResult<()> {
+        self.run(Input::Flatten {x, y, radius, target})?;
+        Ok(())
+    }
+
+    #[pyo3(signature = (x, y, radius, iterations=None))]
+    fn smooth_terrain(&mut self, x: u32, y: u32, radius: u32, iterations: Option<u8>) -> PyResult<()> {
+        self.run(Input::Smooth {x, y, radius, iterations: iterations.unwrap_or(1)})?;
+        Ok(())
+    }
+
+    // (x, y, width, height) of terrain changed since the last call, or None
+    fn take_dirty_rect(&mut self) -> Option<(u32, u32, u32, u32)> {
+        self.terrain_map.take_dirty_rect()
+    }
+
+    // The schedule_* methods queue an event for an absolute tick, run at the start
+    // of advance_state once that tick is reached. Events with an interval repeat
+    // every interval ticks. Each returns an id for cancel_event
+    #[pyo3(signature = (tick, count, area=None, interval=None))]
+    fn schedule_spawn(&mut self, tick: u32, count: u32, area: Option<(u32, u32, u32, u32)>, interval: Option<u32>) -> PyResult<u64> {
+        self.run_for_id(Input::Schedule {tick, interval, action: EventAction::SpawnCohort {count, area}})
+    }
+
+    #[pyo3(signature = (tick, x, y, radius, material, interval=None))]
+    fn schedule_paint(&mut self, tick: u32, x: u32, y: u32, radius: u32, material: u8, interval: Option<u32>) -> PyResult<u64> {
+        self.run_for_id(Input::Schedule {tick, interval, action: EventAction::PaintMaterial {x, y, radius, material}})
+    }
+
+    #[pyo3(signature = (tick, x, y, radius, amount, interval=None))]
+    fn schedule_height_change(&mut self, tick: u32, x: u32, y: u32, radius: u32, amount: i16, interval: Option<u32>) -> PyResult<u64> {
+        self.run_for_id(Input::Schedule {tick, interval, action: EventAction::AdjustHeight {x, y, radius, amount}})
+    }
+
+    #[pyo3(signature = (tick, fraction, interval=None))]
+    fn schedule_cull(&mut self, tick: u32, fraction: f64, interval: Option<u32>) -> PyResult<u64> {
+        self.run_for_id(Input::Schedule {tick, interval, action: EventAction::Cull {fraction}})
+    }
+
+    // makes the entity pregnant now and schedules the birth duration ticks later
+    fn schedule_gestation(&mut self, entity_id: u32, duration: u32) -> PyResult<u64> {
+        self.run_for_id(Input::Gestation {entity_id, duration})
+    }
+
+    // fires without changing anything, collect it with take_fired_events
+    #[pyo3(signature = (tick, name, interval=None))]
+    fn schedule_custom(&mut self, tick: u32, name: String, interval: Option<u32>) -> PyResult<u64> {
+        self.run_for_id(Input::Schedule {tick, interval, action: EventAction::Custom {name}})
+    }
+
+    fn cancel_event(&mut self, id: u64) -> PyResult<bool> {
+        Ok(self.run(Input::CancelEvent {id})?.is_some())
+    }
+
+    // (id, tick, label) of pending events in the order they will run
+    fn get_scheduled_events(&self) -> Vec<(u64, u32, String)> {
+        self.scheduler.pending().into_iter().map(|event| (event.id, event.tick, event.action.label())).collect()
+    }
+
+    // (tick, id, label) of events run since the last call
+    fn take_fired_events(&mut self) -> Vec<(u32, u64, String)> {
+        self.scheduler.take_fired()
+    }
+
+    // keeps the last length ticks so they can be returned to with rewind and
+    // seek. The whole state is stored every snapshot_interval ticks, and other
+    // ticks are rebuilt from it by performing the same inputs again. Chunked
+    // maps can't keep history
+    #[pyo3(signature = (length, snapshot_interval=None))]
+    fn enable_history(&mut self, length: u32, snapshot_interval: Option<u32>) -> PyResult<()> {
+        let snapshot_interval = snapshot_interval.unwrap_or(DEFAULT_SNAPSHOT_INTERVAL);
+        self.run(Input::EnableHistory {length, snapshot_interval})?;
+        Ok(())
+    }
+
+    fn disable_history(&mut self) -> PyResult<()> {
+        self.run(Input::DisableHistory)?;
+        Ok(())
+    }
+
+    // (oldest, newest) ticks that can be sought to
+    fn get_history_range(&self) -> Option<(u32, u32)> {
+        self.history.as_ref().and_then(|history| history.range())
+    }
+
+    // goes back up to ticks ticks, returns the tick now shown. Advancing after a
+    // rewind discards the ticks after it
+    #[pyo3(signature = (ticks=None))]
+    fn rewind(&mut self, ticks: Option<u32>) -> PyResult<u32> {
+        Ok(self.run_for_id(Input::Rewind {ticks: ticks.unwrap_or(1)})? as u32)
+    }
+
+    // moves to any tick within get_history_range, backwards or forwards
+    fn seek(&mut self, tick: u32) -> PyResult<u32> {
+        Ok(self.run_for_id(Input::Seek {tick})? as u32)
+    }
+
+    fn get_seed(&self) -> u32 {
+        self.replay.setup.seed
+    }
+
+    // checksum of the current state, as stored in the replay log after each tick
+    fn get_checksum(&self) -> u64 {
+        self.checksum()
+    }
+
+    // JSON of the setup and every input so far, replay it with replay_game_state
+    fn get_replay(&self) -> String {
+        self.replay.to_json()
+    }
+
+    // steps splits the tick into that many equal sub-steps, for smoother movement
+    #[pyo3(signature = (steps=None))]
+    pub fn advance_state(&mut self, steps: Option<u8>) -> PyResult<()> {
+        self.run(Input::Advance {steps})?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{generate_chunked_game_state, generate_game_state};
+
+    fn small_game(seed: u32) -> GameState {
+        generate_game_state((64, 64, 10), (0, 0, 64, 64), Some(20), Some(seed), None, None)
+    }
+
+    // a paint and an advance per tick, returning (tick, checksum) after each
+    fn play(game: &mut GameState, ticks: u32) -> Vec<(u32, u64)> {
+        let mut checksums = vec![];
+        for i in 0..ticks {
+            game.apply(Input::PaintCircle {x: 4 + i * 5, y: 30, radius: 2, material: 3}).unwrap();
+            game.apply(Input::Advance {steps: None}).unwrap();
+            checksums.push((game.time_mgmt.get_time(), game.checksum()));
+        }
+        checksums
+    }
+
+    #[test]
+    fn replay_reproduces_the_recorded_checksums() {
+        let mut game = small_game(2);
+        game.apply(Input::Schedule {tick: 3, interval: Some(4), action: EventAction::SpawnCohort {count: 3, area: Some((10, 10, 20, 20))}}).unwrap();
+        game.apply(Input::SetWeatherEnabled {enabled: true}).unwrap();
+        play(&mut game, 12);
+        let log = ReplayLog::from_json(&game.replay_log().to_json()).unwrap();
+        let (replayed, divergence) = GameState::replay(&log).unwrap();
+        assert_eq!(divergence, None);
+        assert_eq!(replayed.checksum(), game.checksum());
+        assert_eq!(replayed.replay_log().entries.len(), log.entries.len());
+    }
+
+    #[test]
+    fn replay_reports_where_a_run_diverges() {
+        let mut game = small_game(3);
+        play(&mut game, 5);
+        let mut log = game.replay_log().clone();
+        let entry = log.entries.iter_mut().filter(|entry| entry.checksum.is_some()).nth(2).unwrap();
+        entry.checksum = entry.checksum.map(|checksum| checksum ^ 1);
+        let (_, divergence) = GameState::replay(&log).unwrap();
+        assert_eq!(divergence, Some(3));
+    }
+
+    #[test]
+    fn rewind_and_seek_rebuild_ticks_between_snapshots() {
+        let mut game = small_game(1);
+        // with a full state every 4 ticks, most of the 10 ticks are rebuilt
+        game.apply(Input::EnableHistory {length: 20, snapshot_interval: 4}).unwrap();
+        let mut checksums = vec![(game.time_mgmt.get_time(), game.checksum())];
+        checksums.extend(play(&mut game, 10));
+        for (tick, checksum) in checksums.iter().rev().skip(1) {
+            assert_eq!(game.apply(Input::Rewind {ticks: 1}).unwrap(), Some(*tick as u64));
+            assert_eq!(game.checksum(), *checksum, "rewound to tick {}", tick);
+        }
+        for (tick, checksum) in &checksums {
+            game.apply(Input::Seek {tick: *tick}).unwrap();
+            assert_eq!(game.checksum(), *checksum, "sought tick {}", tick);
+        }
+    }
+
+    #[test]
+    fn advancing_after_a_rewind_repeats_the_run() {
+        let mut game = small_game(4);
+        game.apply(Input::EnableHistory {length: 20, snapshot_interval: 3}).unwrap();
+        let checksums = play(&mut game, 7);
+        game.apply(Input::Rewind {ticks: 7}).unwrap();
+        assert_eq!(play(&mut game, 7), checksums);
+        assert_eq!(game.get_history_range(), Some((0, 7)));
+    }
+
+    #[test]
+    fn rewind_restores_terrain_and_pipeline_settings() {
+        let mut game = small_game(5);
+        game.apply(Input::EnableHistory {length: 10, snapshot_interval: 5}).unwrap();
+        play(&mut game, 2);
+        let cliff_height = game.terrain_map.get_cliff_height();
+        game.apply(Input::SetCliffHeight {cliff_height: cliff_height + 1}).unwrap();
+        game.apply(Input::SetSeasonsEnabled {enabled: false}).unwrap();
+        game.apply(Input::SetSystemEnabled {name: "feeding".to_string(), enabled: false}).unwrap();
+        play(&mut game, 2);
+        game.apply(Input::Seek {tick: 2}).unwrap();
+        assert_eq!(game.terrain_map.get_cliff_height(), cliff_height);
+        assert!(game.terrain_map.seasons_enabled());
+        assert!(game.systems.is_enabled("feeding"));
+        // the settings were given before tick 3, so rebuilding it repeats them
+        game.apply(Input::Seek {tick: 3}).unwrap();
+        assert_eq!(game.terrain_map.get_cliff_height(), cliff_height + 1);
+        assert!(!game.terrain_map.seasons_enabled());
+        assert!(!game.systems.is_enabled("feeding"));
+    }
+
+    #[test]
+    fn chunked_maps_refuse_history() {
+        let mut game = generate_chunked_game_state((64, 64, 10), (0, 0, 64, 64), Some(5), Some(1), None, None, None, None);
+        assert!(game.apply(Input::EnableHistory {length: 10, snapshot_interval: 2}).is_err());
     }
 }
@@ -0,0 +1,127 @@
+// Bounded record of recent ticks so a run can be rewound and scrubbed from
+// Python. The whole simulation state is stored every snapshot_interval ticks,
+// and the ticks in between only keep the inputs that led to them. Runs are
+// deterministic, so any tick is rebuilt by restoring the last full state before
+// it and performing those inputs again
+use crate::core::{EntityMgmt, Input, Scheduler, Terrain, TimeMgmt, Weather};
+use crate::core::entity_management::PopulationStats;
+use crate::core::systems::Pipeline;
+use rand::rngs::StdRng;
+use std::collections::VecDeque;
+
+pub const DEFAULT_SNAPSHOT_INTERVAL: u32 = 10;
+
+// everything a tick reads or changes, so performing the same inputs from it
+// repeats the run exactly. Terrain includes cliff height, season overrides and
+// relief, the pipeline its enable flags and intervals, rng seeds resets
+#[derive(Clone)]
+pub struct TickState {
+    pub time_mgmt: TimeMgmt,
+    pub terrain: Terrain,
+    pub entity_mgmt: EntityMgmt,
+    pub weather: Weather,
+    pub scheduler: Scheduler,
+    pub systems: Pipeline,
+    pub stats: PopulationStats,
+    pub rng: StdRng,
+}
+
+#[derive(Clone)]
+struct Frame {
+    tick: u32,
+    // every snapshot_interval frames, and always for the oldest
+    state: Option<TickState>,
+    // performed since the previous frame, ending with the advance to tick
+    inputs: Vec<Input>,
+}
+
+#[derive(Clone)]
+pub struct History {
+    capacity: usize,
+    snapshot_interval: u32,
+    frames: VecDeque<Frame>,
+    // frame the game state currently matches, frames after it are replayable
+    // until a new input is recorded
+    cursor: usize,
+    // performed since the current frame, they go into the next one
+    pending: Vec<Input>,
+}
+
+impl History {
+    // capacity is the number of ticks kept, both arguments are at least 1
+    pub fn new(capacity: u32, snapshot_interval: u32) -> History {
+        History {capacity: capacity.max(1) as usize, snapshot_interval: snapshot_interval.max(1), frames: VecDeque::new(), cursor: 0, pending: vec![]}
+    }
+
+    // starts again from the given state, e.g. when enabled or after a reset
+    pub fn start(&mut self, tick: u32, state: TickState) {
+        self.frames.clear();
+        self.frames.push_back(Frame {tick, state: Some(state), inputs: vec![]});
+        self.cursor = 0;
+        self.pending.clear();
+    }
+
+    // oldest and newest ticks that can be sought to. Up to snapshot_interval - 1
+    // older frames are kept to rebuild the oldest from
+    pub fn range(&self) -> Option<(u32, u32)> {
+        let oldest = self.frames.len().saturating_sub(self.capacity);
+        Some((self.frames.get(oldest)?.tick, self.frames.back()?.tick))
+    }
+
+    pub fn current_tick(&self) -> Option<u32> {
+        self.frames.get(self.cursor).map(|frame| frame.tick)
+    }
+
+    // whether the next recorded tick stores the full state
+    pub fn state_due(&self) -> bool {
+        let since_full = self.frames.range(..=self.cursor).rev().position(|frame| frame.state.is_some());
+        !matches!(since_full, Some(frames) if (frames as u32 + 1) < self.snapshot_interval)
+    }
+
+    // an input that changes the simulation without advancing it
+    pub fn record_input(&mut self, input: Input) {
+        self.branch();
+        self.pending.push(input);
+    }
+
+    // the tick reached by advance, with the full state when state_due
+    pub fn record_tick(&mut self, tick: u32, advance: Input, state: Option<TickState>) {
+        self.branch();
+        let mut inputs = std::mem::take(&mut self.pending);
+        inputs.push(advance);
+        self.frames.push_back(Frame {tick, state, inputs});
+        // the oldest frame must stay full, so frames go a whole interval at a time
+        while self.frames.len() > self.capacity && self.frames.get(1).is_some_and(|frame| frame.state.is_some()) {
+            self.frames.pop_front();
+        }
+        self.cursor = self.frames.len() - 1;
+    }
+
+    // recording after a rewind drops the ticks that were rewound over
+    fn branch(&mut self) {
+        self.frames.truncate(self.cursor + 1);
+    }
+
+    // moves to the frame recorded for tick, returning the last full state before
+    // it and the inputs to perform from there
+    pub fn seek(&mut self, tick: u32) -> Option<(TickState, Vec<Input>)> {
+        let (oldest, _) = self.range()?;
+        if tick < oldest {
+            return None;
+        }
+        let index = self.frames.iter().position(|frame| frame.tick == tick)?;
+        let base = (0..=index).rev().find(|i| self.frames[*i].state.is_some())?;
+        let state = self.frames[base].state.clone()?;
+        let inputs = self.frames.range(base + 1..=index).flat_map(|frame| frame.inputs.iter().cloned()).collect();
+        self.cursor = index;
+        self.pending.clear();
+        Some((state, inputs))
+    }
+
+    // goes back up to ticks ticks, stopping at the oldest tick kept
+    pub fn rewind(&mut self, ticks: u32) -> Option<(TickState, Vec<Input>)> {
+        let (oldest, _) = self.range()?;
+        let target = self.current_tick()?.saturating_sub(ticks).max(oldest);
+        self.seek(target)
+    }
+}
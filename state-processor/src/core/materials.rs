@@ -0,0 +1,195 @@
+// Data-driven material definitions. Terrain stores material ids as u8, the
+// registry maps those ids to everything else modules need to know about them
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+pub const MUD: u8 = 0;
+pub const GRASS: u8 = 1;
+pub const ICE: u8 = 2;
+pub const ROCK: u8 = 3;
+
+fn default_one() -> f64 { 1.0 }
+fn default_true() -> bool { true }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaterialDef {
+    pub id: u8,
+    pub name: String,
+    pub colour: (u8, u8, u8),
+    // speed multiplier for entities without any of the traits below
+    #[serde(default = "default_one")]
+    pub base_speed: f64,
+    // speed multipliers for entities with a given trait, e.g. "climber": 0.8.
+    // The best multiplier out of the entity's traits is used
+    #[serde(default)]
+    pub trait_speed: HashMap<String, f64>,
+    #[serde(default)]
+    pub food_yield: f64,
+    // how much entities on the cell can drink, see EntityMgmt::feed_all_entities
+    #[serde(default)]
+    pub water_yield: f64,
+    #[serde(default = "default_true")]
+    pub passable: bool,
+    // in degrees celsius
+    #[serde(default)]
+    pub temperature: f64,
+    // generation picks the material with the lowest threshold above the
+    // normalised biome noise. Materials without one are never generated
+    #[serde(default)]
+    pub biome_threshold: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MaterialRegistry {
+    materials: HashMap<u8, MaterialDef>,
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> MaterialRegistry {
+        let material = |id: u8, name: &str, colour, base_speed, speed_trait: Option<(&str, f64)>, food_yield, temperature, biome_threshold| MaterialDef {
+            id,
+            name: name.to_string(),
+            colour,
+            base_speed,
+            trait_speed: speed_trait.map(|(name, speed)| (name.to_string(), speed)).into_iter().collect(),
+            food_yield,
+            water_yield: 0.0,
+            passable: true,
+            temperature,
+            biome_threshold: Some(biome_threshold),
+        };
+        // rock is never generated, it is placed by terrain edits
+        let rock = MaterialDef {
+            passable: false,
+            biome_threshold: None,
+            ..material(ROCK, "rock", (128, 128, 128), 0.0, None, 0.0, 5.0, 0.0)
+        };
+        // grass occupies the middle 25% of the biome range
+        MaterialRegistry::new(vec![
+            MaterialDef {water_yield: 0.5, ..material(MUD, "mud", (255, 0, 0), 0.6, Some(("climber", 0.8)), 0.3, 10.0, 0.375)},
+            material(GRASS, "grass", (0, 255, 0), 1.0, None, 1.0, 15.0, 0.625),
+            MaterialDef {water_yield: 1.0, ..material(ICE, "ice", (0, 0, 255), 0.4, Some(("skater", 0.7)), 0.0, -5.0, 1.0)},
+            rock,
+        ])
+    }
+}
+
+impl MaterialRegistry {
+    pub fn new(materials: Vec<MaterialDef>) -> MaterialRegistry {
+        MaterialRegistry {materials: materials.into_iter().map(|def| (def.id, def)).collect()}
+    }
+
+    // expects a JSON list of material definitions
+    pub fn from_json(text: &str) -> Result<MaterialRegistry, String> {
+        let materials: Vec<MaterialDef> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if materials.iter().all(|def| def.biome_threshold.is_none()) {
+            return Err("at least one material needs a biome_threshold".to_string());
+        }
+        // later definitions would silently replace earlier ones
+        let (mut ids, mut names) = (HashSet::new(), HashSet::new());
+        for def in &materials {
+            if !ids.insert(def.id) {
+                return Err(format!("material id {} is defined more than once", def.id));
+            }
+            if !names.insert(def.name.as_str()) {
+                return Err(format!("material name '{}' is defined more than once", def.name));
+            }
+        }
+        Ok(MaterialRegistry::new(materials))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.sorted()).unwrap()
+    }
+
+    pub fn get(&self, id: u8) -> Option<&MaterialDef> {
+        self.materials.get(&id)
+    }
+
+    pub fn id_by_name(&self, name: &str) -> Option<u8> {
+        self.materials.values().find(|def| def.name == name).map(|def| def.id)
+    }
+
+    pub fn sorted(&self) -> Vec<&MaterialDef> {
+        let mut defs: Vec<&MaterialDef> = self.materials.values().collect();
+        defs.sort_by_key(|def| def.id);
+        defs
+    }
+
+    // unknown ids behave like plain ground
+    pub fn speed_multiplier(&self, id: u8, traits: &[String]) -> f64 {
+        match self.get(id) {
+            Some(def) => traits.iter()
+                .filter_map(|name| def.trait_speed.get(name))
+                .fold(def.base_speed, |best, speed| best.max(*speed)),
+            None => 1.0,
+        }
+    }
+
+    pub fn is_passable(&self, id: u8) -> bool {
+        self.get(id).map(|def| def.passable).unwrap_or(true)
+    }
+
+    pub fn food_yield(&self, id: u8) -> f64 {
+        self.get(id).map(|def| def.food_yield).unwrap_or(0.0)
+    }
+
+    pub fn water_yield(&self, id: u8) -> f64 {
+        self.get(id).map(|def| def.water_yield).unwrap_or(0.0)
+    }
+
+    // (threshold, id) pairs in ascending order, used when generating terrain
+    pub fn biome_bands(&self) -> Vec<(f64, u8)> {
+        let mut bands: Vec<(f64, u8)> = self.materials.values()
+            .filter_map(|def| def.biome_threshold.map(|threshold| (threshold, def.id)))
+            .collect();
+        bands.sort_by(|a, b| a.0.total_cmp(&b.0));
+        bands
+    }
+
+    pub fn colours(&self) -> HashMap<u8, (u8, u8, u8)> {
+        self.materials.values().map(|def| (def.id, def.colour)).collect()
+    }
+
+    pub fn names(&self) -> HashMap<u8, String> {
+        self.materials.values().map(|def| (def.id, def.name.clone())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trips_and_applies_defaults() {
+        let registry = MaterialRegistry::from_json(&MaterialRegistry::default().to_json()).unwrap();
+        assert_eq!(registry.id_by_name("ice"), Some(ICE));
+        assert_eq!(registry.biome_bands().iter().map(|(_, id)| *id).collect::<Vec<_>>(), vec![MUD, GRASS, ICE]);
+        assert!(!registry.is_passable(ROCK));
+
+        let sand = MaterialRegistry::from_json(r#"[{"id": 7, "name": "sand", "colour": [200, 190, 120], "biome_threshold": 1.0}]"#).unwrap();
+        let def = sand.get(7).unwrap();
+        assert_eq!((def.base_speed, def.passable, def.food_yield), (1.0, true, 0.0));
+        // unknown ids behave like plain ground
+        assert_eq!(sand.speed_multiplier(9, &[]), 1.0);
+    }
+
+    #[test]
+    fn best_trait_speed_is_used() {
+        let registry = MaterialRegistry::default();
+        let traits = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert_eq!(registry.speed_multiplier(MUD, &[]), 0.6);
+        assert_eq!(registry.speed_multiplier(MUD, &traits(&["skater", "climber"])), 0.8);
+        assert_eq!(registry.speed_multiplier(ICE, &traits(&["climber"])), 0.4);
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        let def = |id: u8, name: &str| format!(r#"{{"id": {}, "name": "{}", "colour": [0, 0, 0], "biome_threshold": 1.0}}"#, id, name);
+        let list = |defs: &[String]| format!("[{}]", defs.join(", "));
+        assert!(MaterialRegistry::from_json(&list(&[def(1, "sand"), def(2, "clay")])).is_ok());
+        assert!(MaterialRegistry::from_json(&list(&[def(1, "sand"), def(1, "clay")])).is_err());
+        assert!(MaterialRegistry::from_json(&list(&[def(1, "sand"), def(2, "sand")])).is_err());
+        assert!(MaterialRegistry::from_json(r#"[{"id": 1, "name": "sand", "colour": [0, 0, 0]}]"#).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+// Optional timing of each phase of a tick, so the cost of new mechanics shows
+// up. Phases are reported in the order they first ran
+use pyo3::prelude::*;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+struct PhaseTimes {
+    name: &'static str,
+    calls: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+// one row of the report, times in milliseconds
+#[derive(IntoPyObject, Clone, Debug)]
+pub struct PhaseReport {
+    pub name: &'static str,
+    pub calls: u64,
+    pub total_ms: f64,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    enabled: bool,
+    phases: Vec<PhaseTimes>,
+}
+
+impl Profiler {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn clear(&mut self) {
+        self.phases.clear();
+    }
+
+    // None when disabled, pass the result to record once the phase is done
+    pub fn start(&self) -> Option<Instant> {
+        self.enabled.then(Instant::now)
+    }
+
+    pub fn record(&mut self, name: &'static str, start: Option<Instant>) {
+        let Some(start) = start else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        match self.phases.iter_mut().find(|phase| phase.name == name) {
+            Some(phase) => {
+                phase.calls += 1;
+                phase.total += elapsed;
+                phase.min = phase.min.min(elapsed);
+                phase.max = phase.max.max(elapsed);
+            },
+            None => self.phases.push(PhaseTimes {name, calls: 1, total: elapsed, min: elapsed, max: elapsed}),
+        }
+    }
+
+    pub fn report(&self) -> Vec<PhaseReport> {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        self.phases.iter().map(|phase| PhaseReport {
+            name: phase.name,
+            calls: phase.calls,
+            total_ms: ms(phase.total),
+            mean_ms: ms(phase.total) / phase.calls as f64,
+            min_ms: ms(phase.min),
+            max_ms: ms(phase.max),
+        }).collect()
+    }
+
+    // the report as a plain text table
+    pub fn format_report(&self) -> String {
+        let mut table = format!("{:<14}{:>8}{:>12}{:>12}{:>12}{:>12}\n", "phase", "calls", "mean ms", "min ms", "max ms", "total ms");
+        for row in self.report() {
+            table += &format!("{:<14}{:>8}{:>12.4}{:>12.4}{:>12.4}{:>12.2}\n", row.name, row.calls, row.mean_ms, row.min_ms, row.max_ms, row.total_ms);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_profilers_record_nothing() {
+        let mut profiler = Profiler::default();
+        let start = profiler.start();
+        assert!(start.is_none());
+        profiler.record("movement", start);
+        assert!(profiler.report().is_empty());
+    }
+
+    #[test]
+    fn phases_are_reported_in_the_order_they_first_ran() {
+        let mut profiler = Profiler::default();
+        profiler.set_enabled(true);
+        for name in ["weather", "movement", "weather", "death", "movement", "weather"] {
+            let start = profiler.start();
+            profiler.record(name, start);
+        }
+        let report = profiler.report();
+        let rows: Vec<(&str, u64)> = report.iter().map(|row| (row.name, row.calls)).collect();
+        assert_eq!(rows, vec![("weather", 3), ("movement", 2), ("death", 1)]);
+        for row in &report {
+            assert!(row.min_ms <= row.mean_ms && row.mean_ms <= row.max_ms);
+            assert!((row.mean_ms * row.calls as f64 - row.total_ms).abs() < 1e-9);
+        }
+        // a header and a line per phase
+        assert_eq!(profiler.format_report().lines().count(), 4);
+        profiler.clear();
+        assert!(profiler.report().is_empty());
+    }
+}
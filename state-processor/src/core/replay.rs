@@ -0,0 +1,156 @@
+// Recording and replaying runs. A run is rebuilt from its GameSetup and fed the
+// same inputs at the same ticks. All randomness is seeded from the setup, so a
+// replay only differs from the recording when the simulation code has changed,
+// and the checksum stored after every tick shows where that first happens
+use crate::core::{EventAction, MovementModel};
+use crate::core::materials::MaterialDef;
+use crate::core::terrain::TerrainConfig;
+use serde::{Deserialize, Serialize};
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+// inputs kept in a log, roughly a day of ticks at 10 per second. Recording
+// stops beyond this and the log is marked as truncated
+const MAX_REPLAY_ENTRIES: usize = 1_000_000;
+
+// everything needed to generate the starting state again
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameSetup {
+    pub seed: u32,
+    pub map_size: (u32, u32, u8),
+    pub spawn_zone: (u32, u32, u32, u32),
+    pub starting_entities: Option<u32>,
+    // (chunk size, memory budget in MB) for chunked maps
+    pub chunking: Option<(u32, u32)>,
+    pub terrain_config: TerrainConfig,
+    pub materials: Vec<MaterialDef>,
+}
+
+// changes made to a running game from outside the simulation, i.e. by Python
+// or the CLI. Display settings such as relief don't affect the run and aren't
+// recorded
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Input {
+    Advance {steps: Option<u8>},
+    Reset {same_map: bool},
+    SetBoundaryPolicy {policy: String},
+    SetObstacleResponse {response: String},
+    SetCliffHeight {cliff_height: u8},
+    SetEntityTraits {id: u32, traits: Vec<String>},
+    // None returns to the species or default movement
+    SetEntityMovement {id: u32, movement: Option<MovementModel>},
+    SetSpeciesMovement {species: String, movement: Option<MovementModel>},
+    SetDefaultMovement {movement: MovementModel},
+    SetActionWeight {action: String, weight: f64},
+    SetCalendar {ticks_per_day: u32, days_per_season: u32, seasons_per_year: u32},
+    SetSeasonsEnabled {enabled: bool},
+    SetWeatherEnabled {enabled: bool},
+    PaintCircle {x: u32, y: u32, radius: u32, material: u8},
+    PaintRect {x: u32, y: u32, width: u32, height: u32, material: u8},
+    PaintPolygon {points: Vec<(f64, f64)>, material: u8},
+    AdjustHeight {x: u32, y: u32, radius: u32, amount: i16},
+    // flattens to the height at the centre when target is None
+    Flatten {x: u32, y: u32, radius: u32, target: Option<u8>},
+    Smooth {x: u32, y: u32, radius: u32, iterations: u8},
+    Schedule {tick: u32, interval: Option<u32>, action: EventAction},
+    Gestation {entity_id: u32, duration: u32},
+    CancelEvent {id: u64},
+    SetTimeMode {mode: String},
+    SetSystemEnabled {name: String, enabled: bool},
+    SetSystemInterval {name: String, interval: u32},
+    // every system's name, in the new running order
+    SetSystemOrder {names: Vec<String>},
+    EnableHistory {length: u32, snapshot_interval: u32},
+    DisableHistory,
+    Rewind {ticks: u32},
+    Seek {tick: u32},
+}
+
+impl Input {
+    // (min_x, min_y, max_x, max_y) of the cells an edit can touch, None for
+    // inputs that don't edit the map
+    pub fn edit_bounds(&self) -> Option<(u32, u32, u32, u32)> {
+        match self {
+            Input::PaintCircle {x, y, radius, ..} | Input::AdjustHeight {x, y, radius, ..}
+            | Input::Flatten {x, y, radius, ..} | Input::Smooth {x, y, radius, ..} => {
+                Some((x.saturating_sub(*radius), y.saturating_sub(*radius), x.saturating_add(*radius), y.saturating_add(*radius)))
+            },
+            Input::PaintRect {x, y, width, height, ..} => {
+                (*width > 0 && *height > 0).then(|| (*x, *y, x.saturating_add(width - 1), y.saturating_add(height - 1)))
+            },
+            Input::PaintPolygon {points, ..} => {
+                let bound = |value: f64| value.clamp(0.0, u32::MAX as f64) as u32;
+                let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+                let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+                let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+                let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+                (points.len() >= 3).then(|| (bound(min_x), bound(min_y), bound(max_x), bound(max_y)))
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayEntry {
+    // tick the input was given at
+    pub tick: u32,
+    pub input: Input,
+    // checksum of the state after an advance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayLog {
+    pub setup: GameSetup,
+    pub entries: Vec<ReplayEntry>,
+    // set once the log is full, it then only covers the start of the run
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+impl ReplayLog {
+    pub fn new(setup: GameSetup) -> ReplayLog {
+        ReplayLog {setup, entries: vec![], truncated: false}
+    }
+
+    // adds an entry unless the log is full
+    pub fn record(&mut self, entry: ReplayEntry) {
+        if self.entries.len() < MAX_REPLAY_ENTRIES {
+            self.entries.push(entry);
+        } else {
+            self.truncated = true;
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<ReplayLog, String> {
+        serde_json::from_str(text).map_err(|e| e.to_string())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+// 64 bit FNV-1a, fixed so checksums stay comparable across builds and platforms
+pub struct Checksum(u64);
+
+impl Default for Checksum {
+    fn default() -> Checksum {
+        Checksum(FNV_OFFSET)
+    }
+}
+
+impl Checksum {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
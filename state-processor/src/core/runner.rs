@@ -0,0 +1,390 @@
+// Runs a game on its own thread at a target tick rate. After every change the
+// thread publishes an immutable Snapshot of what the renderer draws, swapped in
+// behind an Arc, so reading one never waits for a tick to finish
+use crate::core::{GameState, Input, Season};
+use crate::core::game_state::ENTITY_COLUMNS;
+use numpy::{Element, PyArray1, PyArray2, PyArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TICKS_PER_SECOND: f64 = 10.0;
+
+enum Command {
+    Input(Input),
+    Pause,
+    Resume,
+    SetSpeed(f64),
+    Stop,
+}
+
+// terrain as drawn, rebuilt only when the map is edited or the season changes
+struct TerrainView {
+    version: u64,
+    season: Season,
+    dims: (u32, u32),
+    // with seasonal freezing and thawing applied
+    materials: Vec<u8>,
+    heights: Vec<u8>,
+    hillshade: Vec<u8>,
+}
+
+struct SnapshotData {
+    time: u32,
+    date: (u32, &'static str, u32, u32),
+    ambient_light: f64,
+    paused: bool,
+    ticks_per_second: f64,
+    // rows of GameState::entity_rows
+    entities: Vec<f64>,
+    // None for chunked maps, which are too large to copy each tick
+    terrain: Option<Arc<TerrainView>>,
+    weather: Option<(Vec<u8>, Vec<f32>)>,
+}
+
+struct Shared {
+    game: Mutex<GameState>,
+    latest: Mutex<Arc<SnapshotData>>,
+    // inputs that failed on the simulation thread
+    errors: Mutex<Vec<String>>,
+}
+
+fn to_array<'py, T: Element>(py: Python<'py>, dims: (u32, u32), values: Vec<T>) -> PyResult<Bound<'py, PyArray2<T>>> {
+    PyArray1::from_vec(py, values).reshape([dims.1 as usize, dims.0 as usize])
+}
+
+// the state of a runner at one point in time, cheap to take and never changes
+#[pyclass(frozen)]
+#[derive(Clone)]
+pub struct Snapshot {
+    data: Arc<SnapshotData>,
+}
+
+#[pymethods]
+impl Snapshot {
+    pub fn get_time(&self) -> u32 {
+        self.data.time
+    }
+
+    // (year, season, day of season, tick of day)
+    fn get_date(&self) -> (u32, &'static str, u32, u32) {
+        self.data.date
+    }
+
+    fn get_season(&self) -> &'static str {
+        self.data.date.1
+    }
+
+    fn get_ambient_light(&self) -> f64 {
+        self.data.ambient_light
+    }
+
+    fn is_paused(&self) -> bool {
+        self.data.paused
+    }
+
+    pub fn get_ticks_per_second(&self) -> f64 {
+        self.data.ticks_per_second
+    }
+
+    // as in GameState.get_entity_positions
+    fn get_entity_positions<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        let rows = (self.data.entities.len() / ENTITY_COLUMNS) as u32;
+        to_array(py, (ENTITY_COLUMNS as u32, rows), self.data.entities.clone())
+    }
+
+    // changes whenever the terrain needs redrawing, None for chunked maps
+    fn get_terrain_version(&self) -> Option<u64> {
+        self.data.terrain.as_ref().map(|terrain| terrain.version)
+    }
+
+    // (materials, heights) for the whole map, with materials as they look this season
+    #[allow(clippy::type_complexity)]
+    fn get_map_data<'py>(&self, py: Python<'py>) -> PyResult<Option<(Bound<'py, PyArray2<u8>>, Bound<'py, PyArray2<u8>>)>> {
+        let Some(terrain) = &self.data.terrain else {
+            return Ok(None);
+        };
+        Ok(Some((to_array(py, terrain.dims, terrain.materials.clone())?, to_array(py, terrain.dims, terrain.heights.clone())?)))
+    }
+
+    fn get_hillshade<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyArray2<u8>>>> {
+        match &self.data.terrain {
+            Some(terrain) => Ok(Some(to_array(py, terrain.dims, terrain.hillshade.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    // (kinds, intensities) as in GameState.get_weather_map
+    #[allow(clippy::type_complexity)]
+    fn get_weather_map<'py>(&self, py: Python<'py>) -> PyResult<Option<(Bound<'py, PyArray2<u8>>, Bound<'py, PyArray2<f32>>)>> {
+        let (Some((kinds, intensities)), Some(terrain)) = (&self.data.weather, &self.data.terrain) else {
+            return Ok(None);
+        };
+        Ok(Some((to_array(py, terrain.dims, kinds.clone())?, to_array(py, terrain.dims, intensities.clone())?)))
+    }
+}
+
+// Owns a copy of a game and advances it on a background thread. Edits are
+// queued as replay inputs and run between ticks, so the copy's replay log
+// still covers everything that happened to it
+#[pyclass]
+pub struct SimRunner {
+    shared: Arc<Shared>,
+    commands: Sender<Command>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SimRunner {
+    pub fn start(mut game: GameState, ticks_per_second: f64, paused: bool) -> SimRunner {
+        let terrain = Self::terrain_view(&mut game, None);
+        let latest = Self::capture(&game, terrain, paused, ticks_per_second);
+        let shared = Arc::new(Shared {game: Mutex::new(game), latest: Mutex::new(Arc::new(latest)), errors: Mutex::new(vec![])});
+        let (commands, receiver) = mpsc::channel();
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || Self::run(thread_shared, receiver, ticks_per_second, paused));
+        SimRunner {shared, commands, thread: Some(thread)}
+    }
+
+    fn send(&self, command: Command) {
+        // the thread only goes away once stopped, after which commands do nothing
+        let _ = self.commands.send(command);
+    }
+
+    pub fn latest(&self) -> Snapshot {
+        Snapshot {data: self.shared.latest.lock().unwrap().clone()}
+    }
+
+    pub fn stop(&mut self) {
+        self.send(Command::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn run(shared: Arc<Shared>, commands: Receiver<Command>, mut ticks_per_second: f64, mut paused: bool) {
+        let mut next_tick = Instant::now();
+        let mut terrain = shared.latest.lock().unwrap().terrain.clone();
+        loop {
+            let received = if paused {
+                commands.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                commands.recv_timeout(next_tick.saturating_duration_since(Instant::now()))
+            };
+            let mut game = shared.game.lock().unwrap();
+            match received {
+                Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                Ok(Command::Pause) => paused = true,
+                Ok(Command::Resume) => {
+                    paused = false;
+                    next_tick = Instant::now();
+                },
+                Ok(Command::SetSpeed(speed)) => ticks_per_second = speed,
+                Ok(Command::Input(input)) => {
+                    if let Err(error) = game.apply(input) {
+                        shared.errors.lock().unwrap().push(error);
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = game.apply(Input::Advance {steps: None});
+                    // a slow tick delays the next one rather than causing a burst
+                    next_tick = (next_tick + Duration::from_secs_f64(1.0 / ticks_per_second)).max(Instant::now());
+                },
+            }
+            let start = game.profiler.start();
+            terrain = Self::terrain_view(&mut game, terrain);
+            let snapshot = Self::capture(&game, terrain.clone(), paused, ticks_per_second);
+            game.profiler.record("export", start);
+            drop(game);
+            *shared.latest.lock().unwrap() = Arc::new(snapshot);
+        }
+    }
+
+    // reuses the previous view unless the map was edited or the season changed
+    fn terrain_view(game: &mut GameState, previous: Option<Arc<TerrainView>>) -> Option<Arc<TerrainView>> {
+        if game.terrain_map.is_chunked() {
+            return None;
+        }
+        let season = game.time_mgmt.get_season();
+        let edited = game.terrain_map.take_dirty_rect().is_some();
+        if let Some(view) = &previous && !edited && view.season == season {
+            return previous;
+        }
+        let (width, height) = game.terrain_map.get_dims();
+        let (_, _, materials) = game.terrain_map.effective_region(0, 0, width, height, season);
+        let (_, _, hillshade) = game.terrain_map.hillshade_region(0, 0, width, height);
+        let heights = game.terrain_map.map.iter().map(|point| point.height).collect();
+        let version = previous.map(|view| view.version + 1).unwrap_or(0);
+        Some(Arc::new(TerrainView {version, season, dims: (width, height), materials, heights, hillshade}))
+    }
+
+    fn capture(game: &GameState, terrain: Option<Arc<TerrainView>>, paused: bool, ticks_per_second: f64) -> SnapshotData {
+        let time = game.time_mgmt.get_time();
+        let date = game.time_mgmt.get_date();
+        let entities = game.entity_rows();
+        let weather = terrain.as_ref().map(|view| {
+            let (_, _, kinds, intensities) = game.weather.raster(0, 0, view.dims.0, view.dims.1);
+            (kinds, intensities)
+        });
+        SnapshotData {
+            time,
+            date: (date.year, date.season.name(), date.day, date.tick_of_day),
+            ambient_light: game.time_mgmt.ambient_light(time),
+            paused,
+            ticks_per_second,
+            entities,
+            terrain,
+            weather,
+        }
+    }
+
+    pub fn queue(&self, input: Input) {
+        self.send(Command::Input(input));
+    }
+}
+
+impl Drop for SimRunner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[pymethods]
+impl SimRunner {
+    // runs a copy of game_state, the original is left untouched
+    #[new]
+    #[pyo3(signature = (game_state, ticks_per_second=None, paused=None))]
+    fn new(game_state: PyRef<GameState>, ticks_per_second: Option<f64>, paused: Option<bool>) -> PyResult<SimRunner> {
+        let ticks_per_second = ticks_per_second.unwrap_or(DEFAULT_TICKS_PER_SECOND);
+        if !(ticks_per_second > 0.0 && ticks_per_second.is_finite()) {
+            return Err(PyValueError::new_err("ticks_per_second must be positive"));
+        }
+        Ok(SimRunner::start(game_state.clone(), ticks_per_second, paused.unwrap_or(false)))
+    }
+
+    // the latest published state, never waits for the simulation
+    fn snapshot(&self) -> Snapshot {
+        self.latest()
+    }
+
+    fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    fn set_speed(&self, ticks_per_second: f64) -> PyResult<()> {
+        if !(ticks_per_second > 0.0 && ticks_per_second.is_finite()) {
+            return Err(PyValueError::new_err("ticks_per_second must be positive"));
+        }
+        self.send(Command::SetSpeed(ticks_per_second));
+        Ok(())
+    }
+
+    // queues any replay input, as JSON in the format used by get_replay, e.g.
+    // {"type": "seek", "tick": 10}. Failures show up in take_errors
+    fn submit(&self, input: &str) -> PyResult<()> {
+        let input: Input = serde_json::from_str(input).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.queue(input);
+        Ok(())
+    }
+
+    fn paint_material(&self, x: u32, y: u32, radius: u32, material: u8) {
+        self.queue(Input::PaintCircle {x, y, radius, material});
+    }
+
+    #[pyo3(signature = (x, y, radius, amount=None))]
+    fn raise_terrain(&self, x: u32, y: u32, radius: u32, amount: Option<u8>) {
+        self.queue(Input::AdjustHeight {x, y, radius, amount: amount.unwrap_or(1) as i16});
+    }
+
+    #[pyo3(signature = (same_map=None))]
+    fn reset(&self, same_map: Option<bool>) {
+        self.queue(Input::Reset {same_map: same_map.unwrap_or(false)});
+    }
+
+    fn take_errors(&self) -> Vec<String> {
+        std::mem::take(&mut *self.shared.errors.lock().unwrap())
+    }
+
+    // copy of the full game for queries snapshots don't cover, waits for the
+    // current tick to finish
+    fn get_game_state(&self) -> GameState {
+        self.shared.game.lock().unwrap().clone()
+    }
+
+    // stops the thread, the runner can't be restarted afterwards
+    #[pyo3(name = "stop")]
+    fn stop_py(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::generate_game_state;
+
+    fn small_runner(paused: bool) -> SimRunner {
+        let game = generate_game_state((32, 32, 10), (0, 0, 32, 32), Some(5), Some(1), None, None);
+        SimRunner::start(game, 200.0, paused)
+    }
+
+    // polls the published snapshots until done holds, None after a few seconds
+    fn wait_for(runner: &SimRunner, done: impl Fn(&Snapshot) -> bool) -> Option<Snapshot> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            let snapshot = runner.latest();
+            if done(&snapshot) {
+                return Some(snapshot);
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        None
+    }
+
+    #[test]
+    fn paused_runners_only_run_queued_inputs() {
+        let mut runner = small_runner(true);
+        runner.queue(Input::Advance {steps: None});
+        runner.queue(Input::Advance {steps: None});
+        let snapshot = wait_for(&runner, |snapshot| snapshot.get_time() == 2).expect("queued advances never ran");
+        assert!(snapshot.is_paused());
+        // nothing else ticks while paused
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(runner.latest().get_time(), 2);
+        runner.stop();
+    }
+
+    #[test]
+    fn resumed_runners_tick_at_the_set_speed() {
+        let mut runner = small_runner(true);
+        runner.send(Command::SetSpeed(500.0));
+        runner.send(Command::Resume);
+        let snapshot = wait_for(&runner, |snapshot| snapshot.get_time() >= 5).expect("resumed runner never ticked");
+        assert!(!snapshot.is_paused());
+        assert_eq!(snapshot.get_ticks_per_second(), 500.0);
+        runner.send(Command::Pause);
+        let paused = wait_for(&runner, |snapshot| snapshot.is_paused()).expect("runner never paused").get_time();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(runner.latest().get_time(), paused);
+        runner.stop();
+    }
+
+    #[test]
+    fn failed_inputs_are_kept_for_take_errors() {
+        let mut runner = small_runner(true);
+        runner.queue(Input::Seek {tick: 10});
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while runner.shared.errors.lock().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(runner.take_errors().len(), 1);
+        assert!(runner.take_errors().is_empty());
+        runner.stop();
+    }
+}
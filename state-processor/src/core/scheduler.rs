@@ -0,0 +1,251 @@
+// Events scheduled for future ticks. Due events are run in order of tick, then
+// of when they were scheduled, so the same schedule always plays out the same way
+use crate::core::replay::Checksum;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+// fired events kept for Python to collect, oldest are dropped beyond this
+const MAX_FIRED_LOG: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventAction {
+    // new entities at random in area, or the spawn area when None
+    SpawnCohort {count: u32, area: Option<(u32, u32, u32, u32)>},
+    PaintMaterial {x: u32, y: u32, radius: u32, material: u8},
+    AdjustHeight {x: u32, y: u32, radius: u32, amount: i16},
+    // kills this fraction of the living entities, chosen at random
+    Cull {fraction: f64},
+    // the mother gives birth at her location, if she is still alive and pregnant
+    EndGestation {mother: u32},
+    // does nothing itself, shows up in the fired log for Python to act on
+    Custom {name: String},
+}
+
+impl EventAction {
+    pub fn label(&self) -> String {
+        match self {
+            EventAction::SpawnCohort {count, ..} => format!("spawn_cohort({})", count),
+            EventAction::PaintMaterial {material, ..} => format!("paint_material({})", material),
+            EventAction::AdjustHeight {amount, ..} => format!("adjust_height({})", amount),
+            EventAction::Cull {fraction} => format!("cull({})", fraction),
+            EventAction::EndGestation {mother} => format!("end_gestation({})", mother),
+            EventAction::Custom {name} => name.clone(),
+        }
+    }
+
+    // a tag for the variant, then its fields in declaration order
+    fn write_checksum(&self, sum: &mut Checksum) {
+        match self {
+            EventAction::SpawnCohort {count, area} => {
+                sum.write(&[0]);
+                sum.write(&count.to_le_bytes());
+                match area {
+                    Some((min_x, min_y, max_x, max_y)) => {
+                        sum.write(&[1]);
+                        for value in [min_x, min_y, max_x, max_y] {
+                            sum.write(&value.to_le_bytes());
+                        }
+                    },
+                    None => sum.write(&[0]),
+                }
+            },
+            EventAction::PaintMaterial {x, y, radius, material} => {
+                sum.write(&[1]);
+                for value in [x, y, radius] {
+                    sum.write(&value.to_le_bytes());
+                }
+                sum.write(&[*material]);
+            },
+            EventAction::AdjustHeight {x, y, radius, amount} => {
+                sum.write(&[2]);
+                for value in [x, y, radius] {
+                    sum.write(&value.to_le_bytes());
+                }
+                sum.write(&amount.to_le_bytes());
+            },
+            EventAction::Cull {fraction} => {
+                sum.write(&[3]);
+                sum.write(&fraction.to_le_bytes());
+            },
+            EventAction::EndGestation {mother} => {
+                sum.write(&[4]);
+                sum.write(&mother.to_le_bytes());
+            },
+            EventAction::Custom {name} => {
+                sum.write(&[5]);
+                sum.write(&(name.len() as u32).to_le_bytes());
+                sum.write(name.as_bytes());
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScheduledEvent {
+    pub id: u64,
+    pub tick: u32,
+    // recurring events are scheduled again this many ticks after firing
+    pub interval: Option<u32>,
+    pub action: EventAction,
+    // insertion order, breaks ties between events due on the same tick
+    sequence: u64,
+}
+
+impl PartialEq for ScheduledEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScheduledEvent {}
+
+impl PartialOrd for ScheduledEvent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// reversed, so the max-heap pops the earliest event first
+impl Ord for ScheduledEvent {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.tick, other.sequence).cmp(&(self.tick, self.sequence))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Scheduler {
+    queue: BinaryHeap<ScheduledEvent>,
+    next_id: u64,
+    next_sequence: u64,
+    // (tick, id, label) of events that have run
+    fired: VecDeque<(u32, u64, String)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Scheduler::new();
+    }
+
+    fn push(&mut self, id: u64, tick: u32, interval: Option<u32>, action: EventAction) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.queue.push(ScheduledEvent {id, tick, interval, action, sequence});
+    }
+
+    // returns the id used to cancel the event. An interval of 0 is treated as 1
+    pub fn schedule(&mut self, tick: u32, interval: Option<u32>, action: EventAction) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.push(id, tick, interval.map(|every| every.max(1)), action);
+        id
+    }
+
+    // cancels every future occurrence, returns false if there was none
+    pub fn cancel(&mut self, id: u64) -> bool {
+        let before = self.queue.len();
+        self.queue.retain(|event| event.id != id);
+        self.queue.len() != before
+    }
+
+    // removes the next event due at or before now, rescheduling it if it recurs.
+    // Occurrences that were already missed are skipped rather than run in a
+    // burst, and events that would recur past the last tick are dropped
+    pub fn pop_due(&mut self, now: u32) -> Option<ScheduledEvent> {
+        if self.queue.peek()?.tick > now {
+            return None;
+        }
+        let event = self.queue.pop()?;
+        if let Some(interval) = event.interval {
+            let occurrences = (now - event.tick) / interval + 1;
+            let next = occurrences.checked_mul(interval).and_then(|offset| event.tick.checked_add(offset));
+            if let Some(next) = next {
+                self.push(event.id, next, event.interval, event.action.clone());
+            }
+        }
+        if self.fired.len() == MAX_FIRED_LOG {
+            self.fired.pop_front();
+        }
+        self.fired.push_back((now, event.id, event.action.label()));
+        Some(event)
+    }
+
+    // pending events in the order they will run
+    pub fn pending(&self) -> Vec<&ScheduledEvent> {
+        let mut events: Vec<&ScheduledEvent> = self.queue.iter().collect();
+        events.sort_by(|a, b| b.cmp(a));
+        events
+    }
+
+    // pending events in the order they will run
+    pub fn write_checksum(&self, sum: &mut Checksum) {
+        let pending = self.pending();
+        sum.write(&(pending.len() as u32).to_le_bytes());
+        for event in pending {
+            sum.write(&event.id.to_le_bytes());
+            sum.write(&event.tick.to_le_bytes());
+            sum.write(&event.interval.map_or(0, |interval| interval as u64 + 1).to_le_bytes());
+            event.action.write_checksum(sum);
+        }
+    }
+
+    pub fn take_fired(&mut self) -> Vec<(u32, u64, String)> {
+        self.fired.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(name: &str) -> EventAction {
+        EventAction::Custom {name: name.to_string()}
+    }
+
+    #[test]
+    fn recurring_event_runs_every_interval() {
+        let mut scheduler = Scheduler::new();
+        let id = scheduler.schedule(2, Some(3), custom("tick"));
+        assert!(scheduler.pop_due(1).is_none());
+        for now in [2, 5, 8] {
+            let event = scheduler.pop_due(now).expect("event should be due");
+            assert_eq!((event.id, event.tick), (id, now));
+            assert!(scheduler.pop_due(now).is_none());
+        }
+        assert_eq!(scheduler.pending()[0].tick, 11);
+    }
+
+    #[test]
+    fn late_recurring_event_skips_missed_occurrences() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(2, Some(3), custom("tick"));
+        assert_eq!(scheduler.pop_due(12).map(|event| event.tick), Some(2));
+        // 5, 8 and 11 were missed, 14 is the first occurrence after 12
+        assert!(scheduler.pop_due(12).is_none());
+        assert_eq!(scheduler.pending()[0].tick, 14);
+    }
+
+    #[test]
+    fn recurring_event_past_the_last_tick_is_dropped() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(u32::MAX - 1, Some(5), custom("tick"));
+        assert!(scheduler.pop_due(u32::MAX).is_some());
+        assert!(scheduler.pending().is_empty());
+    }
+
+    #[test]
+    fn events_on_the_same_tick_run_in_scheduled_order() {
+        let mut scheduler = Scheduler::new();
+        let first = scheduler.schedule(4, None, custom("first"));
+        let second = scheduler.schedule(4, Some(2), custom("second"));
+        let earlier = scheduler.schedule(3, None, custom("earlier"));
+        let order: Vec<u64> = std::iter::from_fn(|| scheduler.pop_due(4).map(|event| event.id)).collect();
+        assert_eq!(order, vec![earlier, first, second]);
+        assert_eq!(scheduler.pending()[0].tick, 6);
+    }
+}
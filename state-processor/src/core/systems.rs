@@ -0,0 +1,412 @@
+// Each tick runs an ordered list of systems, one per mechanic. Systems can be
+// switched off, run every few ticks or reordered without touching the others,
+// and new mechanics are added by implementing System and registering it
+use crate::core::{EntityMgmt, Environment, Scheduler, Terrain, TimeMgmt, TimeMode, Weather};
+use crate::core::entity_management::PopulationStats;
+use crate::core::profiling::Profiler;
+use crate::core::time_management::Date;
+use std::sync::Arc;
+
+mod builtin;
+
+pub use builtin::{Ageing, Death, Decisions, Feeding, Movement, Reproduction, Statistics, TerrainUpdate, WeatherUpdate};
+
+// the parts of the game a system can read and change during a tick
+pub struct World<'a> {
+    pub time: &'a mut TimeMgmt,
+    pub date: Date,
+    // 0 at night to 1 at midday, see TimeMgmt::daylight
+    pub daylight: f64,
+    // ticks since this system last ran, i.e. its interval
+    pub elapsed: u32,
+    // movement within a tick is split into this many equal steps
+    pub steps: u8,
+    // whether the decisions system is on, which feeding depends on
+    pub deciding: bool,
+    pub terrain: &'a mut Terrain,
+    pub weather: &'a mut Weather,
+    pub entities: &'a mut EntityMgmt,
+    pub scheduler: &'a mut Scheduler,
+    pub stats: &'a mut PopulationStats,
+}
+
+impl World<'_> {
+    // what entities see of the world, alongside the entities themselves
+    pub fn split(&mut self) -> (Environment<'_>, &mut EntityMgmt) {
+        let env = Environment {map: &*self.terrain, weather: self.weather, season: self.date.season, daylight: self.daylight};
+        (env, self.entities)
+    }
+
+    // whole calendar years that ended since the system last ran
+    pub fn years_elapsed(&self) -> u32 {
+        let now = self.time.get_time();
+        self.date.year - self.time.date_at(now.saturating_sub(self.elapsed)).year
+    }
+}
+
+pub trait System: Send + Sync {
+    // unique, used to refer to the system from Python and in replays
+    fn name(&self) -> &'static str;
+    fn run(&self, world: &mut World);
+
+    // true for systems that run as continuous-time events in event mode. They
+    // fire for one entity at a time, at event_rate times per tick, instead of run
+    fn event_driven(&self) -> bool {
+        false
+    }
+
+    fn event_rate(&self, _env: &Environment, _entities: &EntityMgmt, _id: u32) -> f64 {
+        0.0
+    }
+
+    fn fire(&self, _world: &mut World, _id: u32) {}
+
+    // how far away an event can change an entity's rate, e.g. by moving a mate
+    // into range. Only entities this close to where an event happened have
+    // their rates recomputed after it
+    fn rate_radius(&self, _entities: &EntityMgmt) -> f64 {
+        0.0
+    }
+
+    // in event mode, the part of run that happens once a tick rather than as
+    // events, run before the tick's events
+    fn before_events(&self, _world: &mut World) {}
+}
+
+#[derive(Clone)]
+struct Slot {
+    system: Arc<dyn System>,
+    enabled: bool,
+    // runs on ticks that are a multiple of this
+    interval: u32,
+}
+
+#[derive(Clone)]
+pub struct Pipeline {
+    slots: Vec<Slot>,
+}
+
+impl Default for Pipeline {
+    // weather and terrain update first so entities react to this tick's world, and
+    // statistics come last so they describe the finished tick. Reproduction is
+    // opt in, births otherwise only come from scheduled gestations
+    fn default() -> Pipeline {
+        let mut pipeline = Pipeline {slots: vec![]};
+        pipeline.register(Arc::new(WeatherUpdate), true, 1);
+        pipeline.register(Arc::new(TerrainUpdate), true, 1);
+        pipeline.register(Arc::new(Decisions), false, 1);
+        pipeline.register(Arc::new(Movement), true, 1);
+        pipeline.register(Arc::new(Feeding), true, 1);
+        pipeline.register(Arc::new(Ageing), true, 1);
+        pipeline.register(Arc::new(Death), true, 1);
+        pipeline.register(Arc::new(Reproduction), false, 1);
+        pipeline.register(Arc::new(Statistics), true, 1);
+        pipeline
+    }
+}
+
+impl Pipeline {
+    // adds a system at the end, replacing any system with the same name
+    pub fn register(&mut self, system: Arc<dyn System>, enabled: bool, interval: u32) {
+        self.slots.retain(|slot| slot.system.name() != system.name());
+        self.slots.push(Slot {system, enabled, interval: interval.max(1)});
+    }
+
+    // runs the enabled systems due this tick, in order, timing each under its name.
+    // In event mode the event driven systems all run together, as events over
+    // the whole tick, where the first of them would have run
+    pub fn run(&self, world: &mut World, profiler: &mut Profiler) {
+        let now = world.time.get_time();
+        let events_mode = world.time.get_mode() == TimeMode::Events;
+        let mut events_run = false;
+        for slot in &self.slots {
+            if events_mode && slot.system.event_driven() {
+                if slot.enabled && !events_run {
+                    events_run = true;
+                    let start = profiler.start();
+                    for system in self.event_systems() {
+                        system.before_events(world);
+                    }
+                    self.run_events(world);
+                    profiler.record("gillespie", start);
+                }
+            } else if slot.enabled && now.is_multiple_of(slot.interval) {
+                world.elapsed = slot.interval;
+                let start = profiler.start();
+                slot.system.run(world);
+                profiler.record(slot.system.name(), start);
+            }
+        }
+    }
+
+    fn event_systems(&self) -> Vec<&Arc<dyn System>> {
+        self.slots.iter()
+            .filter(|slot| slot.enabled && slot.system.event_driven())
+            .map(|slot| &slot.system)
+            .collect()
+    }
+
+    // Gillespie's direct method over the tick ending now. Each living entity has
+    // a rate per event driven system, the wait until the next event is
+    // exponential in their total and the event is picked in proportion to its
+    // rate. After an event only the rates of entities within rate_radius of it
+    // are recomputed. This suits the small populations where per tick steps are
+    // too coarse
+    fn run_events(&self, world: &mut World) {
+        let systems = self.event_systems();
+        let radius = systems.iter().map(|system| system.rate_radius(world.entities)).fold(0.0, f64::max);
+        let end = world.time.get_time() as f64;
+        let mut clock = (end - 1.0).max(world.time.get_clock());
+        // entities alive at the start of the tick, sorted. Births are scheduled
+        // for later ticks, so no entity joins during the tick
+        let ids = world.entities.living_ids();
+        // rate of each system for each entity, entity by entity
+        let mut rates = vec![0.0; ids.len() * systems.len()];
+        let mut total = 0.0;
+        let mut stale: Vec<usize> = (0..ids.len()).collect();
+        loop {
+            let (env, entities) = world.split();
+            for index in stale.drain(..) {
+                for (offset, system) in systems.iter().enumerate() {
+                    let channel = index * systems.len() + offset;
+                    let rate = system.event_rate(&env, entities, ids[index]);
+                    total += rate - rates[channel];
+                    rates[channel] = rate;
+                }
+            }
+            if total <= 0.0 {
+                break;
+            }
+            let wait = -(1.0 - world.entities.random_unit()).ln() / total;
+            if clock + wait >= end {
+                break;
+            }
+            clock += wait;
+            world.time.set_clock(clock);
+            let mut pick = world.entities.random_unit() * total;
+            let chosen = rates.iter().position(|rate| {
+                pick -= rate;
+                pick < 0.0
+            }).unwrap_or_else(|| rates.iter().rposition(|rate| *rate > 0.0).unwrap_or(0));
+            let (index, id) = (chosen / systems.len(), ids[chosen / systems.len()]);
+            let before = world.entities.get_entity_location(id);
+            systems[chosen % systems.len()].fire(world, id);
+            let after = world.entities.get_entity_location(id);
+            stale.push(index);
+            if radius > 0.0 {
+                for location in [before, after].into_iter().flatten() {
+                    let near = world.entities.entities_within(location, radius, Some(id));
+                    stale.extend(near.iter().filter_map(|other| ids.binary_search(other).ok()));
+                }
+            }
+            stale.sort_unstable();
+            stale.dedup();
+        }
+        // the wait that ran past the end of the tick is dropped, which the
+        // exponential wait allows, and drawn again next tick from the new rates
+        world.time.set_clock(end);
+    }
+
+    // (name, enabled, interval) in running order
+    pub fn systems(&self) -> Vec<(&'static str, bool, u32)> {
+        self.slots.iter().map(|slot| (slot.system.name(), slot.enabled, slot.interval)).collect()
+    }
+
+    fn slot_mut(&mut self, name: &str) -> Result<&mut Slot, String> {
+        self.slots.iter_mut()
+            .find(|slot| slot.system.name() == name)
+            .ok_or(format!("unknown system '{}'", name))
+    }
+
+    // false for unknown systems
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.slots.iter().any(|slot| slot.system.name() == name && slot.enabled)
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        self.slot_mut(name)?.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn set_interval(&mut self, name: &str, interval: u32) -> Result<(), String> {
+        if interval == 0 {
+            return Err("interval must be at least 1".to_string());
+        }
+        self.slot_mut(name)?.interval = interval;
+        Ok(())
+    }
+
+    // names must list every system exactly once
+    pub fn set_order(&mut self, names: &[String]) -> Result<(), String> {
+        let mut reordered: Vec<Slot> = Vec::with_capacity(self.slots.len());
+        for name in names {
+            let slot = self.slots.iter().find(|slot| slot.system.name() == name)
+                .ok_or(format!("unknown system '{}'", name))?;
+            if reordered.iter().any(|placed| placed.system.name() == name) {
+                return Err(format!("system '{}' is listed twice", name));
+            }
+            reordered.push(slot.clone());
+        }
+        if reordered.len() != self.slots.len() {
+            let all: Vec<&str> = self.slots.iter().map(|slot| slot.system.name()).collect();
+            return Err(format!("order must list every system: {}", all.join(", ")));
+        }
+        self.slots = reordered;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // what World borrows, owned by the test
+    struct Parts {
+        time: TimeMgmt,
+        terrain: Terrain,
+        weather: Weather,
+        entities: EntityMgmt,
+        scheduler: Scheduler,
+        stats: PopulationStats,
+    }
+
+    impl Parts {
+        fn new(entities: &[(u32, u32)]) -> Parts {
+            let mut parts = Parts {
+                time: TimeMgmt::new(0),
+                terrain: Terrain::new(10, 10, 10),
+                weather: Weather::new((10, 10), 1),
+                entities: EntityMgmt::new((0, 0, 10, 10), (10, 10), 1),
+                scheduler: Scheduler::new(),
+                stats: PopulationStats::default(),
+            };
+            parts.entities.spawn_at(entities);
+            parts
+        }
+
+        // advances a tick and runs the pipeline over it
+        fn tick(&mut self, pipeline: &Pipeline) {
+            self.time.update();
+            let date = self.time.get_date();
+            let mut world = World {
+                time: &mut self.time,
+                date,
+                daylight: 1.0,
+                elapsed: 1,
+                steps: 1,
+                deciding: false,
+                terrain: &mut self.terrain,
+                weather: &mut self.weather,
+                entities: &mut self.entities,
+                scheduler: &mut self.scheduler,
+                stats: &mut self.stats,
+            };
+            pipeline.run(&mut world, &mut Profiler::default());
+        }
+    }
+
+    type Log = Arc<Mutex<Vec<(&'static str, u32, f64)>>>;
+
+    // records (name, tick, elapsed) when it runs and (name, tick, clock) when it
+    // fires, and fires for every entity at rate times per tick
+    struct Probe {
+        name: &'static str,
+        rate: Option<f64>,
+        log: Log,
+    }
+
+    impl System for Probe {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn run(&self, world: &mut World) {
+            self.log.lock().unwrap().push((self.name, world.time.get_time(), world.elapsed as f64));
+        }
+
+        fn event_driven(&self) -> bool {
+            self.rate.is_some()
+        }
+
+        fn event_rate(&self, _env: &Environment, _entities: &EntityMgmt, _id: u32) -> f64 {
+            self.rate.unwrap_or(0.0)
+        }
+
+        fn fire(&self, world: &mut World, _id: u32) {
+            self.log.lock().unwrap().push((self.name, world.time.get_time(), world.time.get_clock()));
+        }
+    }
+
+    fn pipeline(log: &Log, probes: &[(&'static str, Option<f64>)]) -> Pipeline {
+        let mut pipeline = Pipeline {slots: vec![]};
+        for (name, rate) in probes {
+            pipeline.register(Arc::new(Probe {name, rate: *rate, log: log.clone()}), true, 1);
+        }
+        pipeline
+    }
+
+    #[test]
+    fn systems_run_in_order_when_enabled_and_due() {
+        let log = Log::default();
+        let mut pipeline = pipeline(&log, &[("a", None), ("b", None), ("c", None)]);
+        pipeline.set_interval("b", 2).unwrap();
+        pipeline.set_enabled("c", false).unwrap();
+        pipeline.set_order(&["c".to_string(), "b".to_string(), "a".to_string()]).unwrap();
+        let mut parts = Parts::new(&[]);
+        for _ in 0..4 {
+            parts.tick(&pipeline);
+        }
+        // b sees the two ticks since it last ran as elapsed
+        let expected = vec![("a", 1, 1.0), ("b", 2, 2.0), ("a", 2, 1.0), ("a", 3, 1.0), ("b", 4, 2.0), ("a", 4, 1.0)];
+        assert_eq!(*log.lock().unwrap(), expected);
+        assert_eq!(pipeline.systems(), vec![("c", false, 1), ("b", true, 2), ("a", true, 1)]);
+    }
+
+    #[test]
+    fn bad_pipeline_changes_are_rejected() {
+        let log = Log::default();
+        let mut pipeline = pipeline(&log, &[("a", None), ("b", None)]);
+        assert!(pipeline.set_enabled("z", false).is_err());
+        assert!(pipeline.set_interval("a", 0).is_err());
+        assert!(pipeline.set_order(&["a".to_string()]).is_err());
+        assert!(pipeline.set_order(&["a".to_string(), "a".to_string()]).is_err());
+        assert!(pipeline.set_order(&["a".to_string(), "z".to_string()]).is_err());
+        // registering a name again replaces the system and moves it to the end
+        pipeline.register(Arc::new(Probe {name: "a", rate: None, log: log.clone()}), false, 3);
+        assert_eq!(pipeline.systems(), vec![("b", true, 1), ("a", false, 3)]);
+    }
+
+    #[test]
+    fn events_fire_during_the_tick_and_leave_the_clock_at_its_end() {
+        let log = Log::default();
+        let pipeline = pipeline(&log, &[("a", None), ("often", Some(2.0)), ("rarely", Some(1.0)), ("never", Some(0.0))]);
+        let mut parts = Parts::new(&[(1, 1), (3, 3), (5, 5), (7, 7), (9, 9)]);
+        parts.time.set_mode(TimeMode::Events);
+        for tick in 1..=3 {
+            parts.tick(&pipeline);
+            assert_eq!(parts.time.get_clock(), tick as f64);
+        }
+        let log = log.lock().unwrap();
+        let runs: Vec<u32> = log.iter().filter(|entry| entry.0 == "a").map(|entry| entry.1).collect();
+        assert_eq!(runs, vec![1, 2, 3]);
+        let events: Vec<_> = log.iter().filter(|entry| entry.0 != "a").collect();
+        assert!(events.iter().all(|(name, _, _)| *name != "never"));
+        // each event lies within its tick, in order
+        for (_, tick, clock) in &events {
+            assert!(*clock > *tick as f64 - 1.0 && *clock < *tick as f64);
+        }
+        assert!(events.windows(2).all(|pair| pair[0].2 <= pair[1].2));
+        let count = |name: &str| events.iter().filter(|entry| entry.0 == name).count();
+        assert!(count("often") > count("rarely") && count("rarely") > 0);
+    }
+
+    #[test]
+    fn event_systems_run_once_a_tick_in_tick_mode() {
+        let log = Log::default();
+        let pipeline = pipeline(&log, &[("events", Some(5.0))]);
+        let mut parts = Parts::new(&[(1, 1)]);
+        parts.tick(&pipeline);
+        assert_eq!(*log.lock().unwrap(), vec![("events", 1, 1.0)]);
+    }
+}
@@ -0,0 +1,193 @@
+// The systems every game starts with, see Pipeline::default
+use super::{System, World};
+use crate::core::{EntityMgmt, Environment, EventAction};
+
+// ticks from conception to birth for pregnancies started by Reproduction
+const GESTATION_TICKS: u32 = 90;
+
+pub struct WeatherUpdate;
+
+impl System for WeatherUpdate {
+    fn name(&self) -> &'static str {
+        "weather"
+    }
+
+    fn run(&self, world: &mut World) {
+        world.weather.update(world.date.season);
+    }
+}
+
+// applies the start of a new season to the map
+pub struct TerrainUpdate;
+
+impl System for TerrainUpdate {
+    fn name(&self) -> &'static str {
+        "terrain"
+    }
+
+    fn run(&self, world: &mut World) {
+        let previous = world.time.date_at(world.time.get_time().saturating_sub(world.elapsed));
+        // a whole year apart is the same season but still a change of season
+        if previous.season != world.date.season || previous.year != world.date.year {
+            world.terrain.start_season();
+        }
+    }
+}
+
+// each entity picks an action for its needs, which sets how it moves until
+// the next decision
+pub struct Decisions;
+
+impl System for Decisions {
+    fn name(&self) -> &'static str {
+        "decisions"
+    }
+
+    fn run(&self, world: &mut World) {
+        let (env, entities) = world.split();
+        entities.decide_all(&env);
+    }
+}
+
+// random walks, split into steps so fast entities don't skip over obstacles
+pub struct Movement;
+
+impl System for Movement {
+    fn name(&self) -> &'static str {
+        "movement"
+    }
+
+    fn run(&self, world: &mut World) {
+        let steps = world.steps.max(1);
+        let dt = world.elapsed as f64 / steps as f64;
+        let (env, entities) = world.split();
+        for _ in 0..steps {
+            entities.random_move_all_entities(&env, dt);
+        }
+    }
+
+    fn event_driven(&self) -> bool {
+        true
+    }
+
+    fn event_rate(&self, env: &Environment, entities: &EntityMgmt, id: u32) -> f64 {
+        entities.movement_rate(id, env)
+    }
+
+    // one move covers a tick's worth of distance
+    fn fire(&self, world: &mut World, id: u32) {
+        let (env, entities) = world.split();
+        entities.random_move_entity(id, &env, 1.0);
+    }
+}
+
+pub struct Feeding;
+
+impl System for Feeding {
+    fn name(&self) -> &'static str {
+        "feeding"
+    }
+
+    fn run(&self, world: &mut World) {
+        let (dt, deciding) = (world.elapsed as f64, world.deciding);
+        let (env, entities) = world.split();
+        entities.feed_all_entities(&env, dt, deciding);
+    }
+}
+
+// only does anything on ticks where a calendar year ends
+pub struct Ageing;
+
+impl System for Ageing {
+    fn name(&self) -> &'static str {
+        "ageing"
+    }
+
+    fn run(&self, world: &mut World) {
+        let years = world.years_elapsed();
+        if years > 0 {
+            world.entities.age_all_entities(years);
+        }
+    }
+}
+
+// old age, and exposure to harsh weather
+pub struct Death;
+
+impl System for Death {
+    fn name(&self) -> &'static str {
+        "death"
+    }
+
+    fn run(&self, world: &mut World) {
+        world.entities.apply_old_age();
+        let dt = world.elapsed as f64;
+        let (env, entities) = world.split();
+        entities.apply_exposure(&env, dt);
+    }
+
+    // old age isn't random in time, entities past their death age die at once
+    fn before_events(&self, world: &mut World) {
+        world.entities.apply_old_age();
+    }
+
+    fn event_driven(&self) -> bool {
+        true
+    }
+
+    fn event_rate(&self, env: &Environment, entities: &EntityMgmt, id: u32) -> f64 {
+        entities.exposure_hazard(id, env)
+    }
+
+    fn fire(&self, world: &mut World, id: u32) {
+        world.entities.kill(id);
+    }
+}
+
+// conception between nearby entities, with births scheduled like gestations
+pub struct Reproduction;
+
+impl System for Reproduction {
+    fn name(&self) -> &'static str {
+        "reproduction"
+    }
+
+    fn run(&self, world: &mut World) {
+        let birth_tick = world.time.get_time().saturating_add(GESTATION_TICKS);
+        for mother in world.entities.conceive(world.elapsed as f64) {
+            world.scheduler.schedule(birth_tick, None, EventAction::EndGestation {mother});
+        }
+    }
+
+    fn event_driven(&self) -> bool {
+        true
+    }
+
+    fn event_rate(&self, _env: &Environment, entities: &EntityMgmt, id: u32) -> f64 {
+        entities.conception_hazard(id)
+    }
+
+    fn rate_radius(&self, entities: &EntityMgmt) -> f64 {
+        entities.mating_radius()
+    }
+
+    // births land on the first tick after the gestation period
+    fn fire(&self, world: &mut World, id: u32) {
+        if world.entities.set_pregnant(id, true) {
+            let birth_tick = (world.time.get_clock() + GESTATION_TICKS as f64).ceil() as u32;
+            world.scheduler.schedule(birth_tick, None, EventAction::EndGestation {mother: id});
+        }
+    }
+}
+
+pub struct Statistics;
+
+impl System for Statistics {
+    fn name(&self) -> &'static str {
+        "statistics"
+    }
+
+    fn run(&self, world: &mut World) {
+        *world.stats = world.entities.population_stats();
+    }
+}
@@ -1,22 +1,30 @@
-use noise::{Perlin, NoiseFn};
+use crate::core::materials::MaterialRegistry;
+use crate::core::topology::Topology;
+use crate::core::time_management::Season;
 use pyo3::prelude::*;
-use rand::Rng;
+use rayon::prelude::*;
 use std::fmt;
 
+mod analysis;
+mod chunks;
+mod editing;
+mod noise_graph;
+mod obstacles;
+mod pathfinding;
+mod relief;
+mod seasons;
 
-const BASE_NOISE_SCALE: f64 = 6.0;
-const BASE_BIOME_SCALE: f64 = 0.8;  // Much larger scale = bigger, smoother biomes
+use chunks::ChunkStore;
+use noise_graph::NoiseSource;
+pub use noise_graph::TerrainConfig;
+pub use pathfinding::{Path, PathCache, PathCost};
+pub use relief::Relief;
+use seasons::SeasonalLayer;
 
-#[pyclass]
-#[derive(Clone, Copy, Debug)]
-pub enum Material {
-    Mud = 0,
-    Grass = 1,
-    Ice = 2,
-}
+const DEFAULT_CLIFF_HEIGHT: u8 = 3;
 
 #[pyclass]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct MapPoint {
     pub height: u8,
     pub material: u8, 
@@ -28,15 +36,84 @@ impl fmt::Debug for MapPoint {
     }
 }
 
+// Evaluates the terrain noise for a single cell. Generation is a pure function
+// of the seed and the coordinates, so chunks can be built in any order
+#[derive(Clone)]
+pub struct PointSampler {
+    noise: NoiseSource,
+    biome_noise: NoiseSource,
+    scale: f64,
+    biome_scale: f64,
+    depth: u8,
+    biome_bands: Vec<(f64, u8)>,
+    topology: Topology,
+}
+
+impl PointSampler {
+    pub fn new(config: &TerrainConfig, materials: &MaterialRegistry, seed: u32, width: u32, depth: u8) -> PointSampler {
+        let noise = config.height.build(seed).expect("terrain config is validated when parsed");
+        let biome_noise = config.biome.build(seed).expect("terrain config is validated when parsed");
+        let scale: f64 = config.height.scale / (width as f64 * 0.5);
+        let biome_scale = config.biome.scale / (width as f64 * 0.5);
+        let biome_bands = materials.biome_bands();
+        PointSampler {noise, biome_noise, scale, biome_scale, depth, biome_bands, topology: config.topology}
+    }
+
+    fn noise_range_change(noise: f64, upper: f64) -> u8 {
+        // fractal nodes can overshoot [-1.0, 1.0] slightly
+        let normalised_noise = (noise.clamp(-1.0, 1.0) + 1.0) / 2.0; // noise: [-1.0, 1.0] -> [0.0, 1.0] 
+        let scaled_height = normalised_noise * upper; 
+        scaled_height as u8
+    }
+
+    fn biome_noise_to_material(&self, noise: f64) -> u8 {
+        let normalised = (noise + 1.0) / 2.0; // noise: [-1.0, 1.0] -> [0.0, 1.0]
+        
+        // bands are sorted by threshold, anything past the last one uses it
+        for (threshold, material) in &self.biome_bands {
+            if normalised < *threshold {
+                return *material;
+            }
+        }
+        self.biome_bands.last().map(|band| band.1).unwrap_or(0)
+    }
+
+    pub fn sample(&self, x: u32, y: u32) -> MapPoint {
+        // sampled at the cell centre so hex maps aren't squashed vertically
+        let (world_x, world_y) = self.topology.world_position(x, y);
+        let noise_val = self.noise.get([world_x * self.scale, world_y * self.scale]);
+        let combined_biome_noise = self.biome_noise.get([world_x * self.biome_scale, world_y * self.biome_scale]);
+        
+        let height_val = Self::noise_range_change(noise_val, self.depth as f64);
+        let material_val = self.biome_noise_to_material(combined_biome_noise);
+        MapPoint {height: height_val, material: material_val}
+    }
+}
+
 #[pyclass]
+#[derive(Clone)]
 pub struct Terrain {
     #[pyo3(get)]
-    width: u16,
+    width: u32,
     #[pyo3(get)]
-    height: u16,
+    height: u32,
     #[pyo3(get)]
     depth: u8,
-    pub map: Vec<MapPoint>
+    pub map: Vec<MapPoint>,
+    // when set, points live in lazily generated chunks instead of `map`
+    chunks: Option<ChunkStore>,
+    // bounding box (min_x, min_y, max_x, max_y) of cells edited since last taken
+    dirty: Option<(u32, u32, u32, u32)>,
+    config: TerrainConfig,
+    pub materials: MaterialRegistry,
+    // None when seasonal changes are switched off
+    seasons: Option<SeasonalLayer>,
+    // largest height difference between neighbouring cells that can be walked
+    cliff_height: u8,
+    relief: Relief,
+    // counts changes that affect movement, so derived data like cached paths
+    // can tell when it's stale
+    revision: u64,
 }
 
 impl fmt::Debug for Terrain {
@@ -51,7 +128,10 @@ impl fmt::Debug for Terrain {
 
 impl fmt::Display for Terrain {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Terrain of size {}x{}, and depth {}. Has {} Points", self.width, self.height, self.depth, self.map.len())
+        match &self.chunks {
+            Some(store) => write!(f, "Chunked terrain of size {}x{}, and depth {}. Has {} resident chunks", self.width, self.height, self.depth, store.resident_chunks()),
+            None => write!(f, "Terrain of size {}x{}, and depth {}. Has {} Points", self.width, self.height, self.depth, self.map.len()),
+        }
     }
 }
 
@@ -64,84 +144,171 @@ impl Terrain {
 
 
 impl Terrain {
-    pub fn new(width: u16, height: u16, depth: u8) -> Terrain {
-        Terrain {width , height, depth, map: vec![]}
+    pub fn new(width: u32, height: u32, depth: u8) -> Terrain {
+        Self::with_config(width, height, depth, TerrainConfig::default(), MaterialRegistry::default())
+    }
+
+    pub fn with_config(width: u32, height: u32, depth: u8, config: TerrainConfig, materials: MaterialRegistry) -> Terrain {
+        Terrain {width , height, depth, map: vec![], chunks: None, dirty: None, config, materials, seasons: Some(SeasonalLayer::default()), cliff_height: DEFAULT_CLIFF_HEIGHT, relief: Relief::default(), revision: 0}
     }
 
-    pub fn reset(&mut self) {
-        *self = Self::new(self.width, self.height, self.depth);
-        let mut rng = rand::rng();
-        let random_seed: u32 = rng.random();
-        let perlin = Perlin::new(random_seed);
-        let biome_perlin = Perlin::new(random_seed.wrapping_add(1000));
-        self.initialise_terrain(&perlin, &biome_perlin);
+    // chunk_size is the side length of a chunk in cells, memory_budget is the 
+    // number of bytes of point data allowed to stay resident at once
+    pub fn new_chunked(dimensions: (u32, u32, u8), seed: u32, chunk_size: u32, memory_budget: usize, config: TerrainConfig, materials: MaterialRegistry) -> Terrain {
+        let (width, height, depth) = dimensions;
+        let sampler = PointSampler::new(&config, &materials, seed, width, depth);
+        let store = ChunkStore::new(sampler, chunk_size, memory_budget);
+        Terrain {width, height, depth, map: vec![], chunks: Some(store), dirty: None, config, materials, seasons: Some(SeasonalLayer::default()), cliff_height: DEFAULT_CLIFF_HEIGHT, relief: Relief::default(), revision: 0}
     }
 
-    pub fn get_point(&self, x: u16, y: u16) -> &MapPoint {
+    pub fn is_chunked(&self) -> bool {
+        self.chunks.is_some()
+    }
+
+    // generates a new map from seed
+    pub fn reset(&mut self, random_seed: u32) {
+        self.revision += 1;
+        if let Some(store) = &mut self.chunks {
+            store.reseed(PointSampler::new(&self.config, &self.materials, random_seed, self.width, self.depth));
+            return;
+        }
+        // settings such as seasons and cliff height carry over to the new map
+        self.dirty = None;
+        self.initialise_terrain(random_seed);
+    }
+
+    pub fn get_point(&self, x: u32, y: u32) -> MapPoint {
+        if let Some(store) = &self.chunks {
+            return store.get_point(x, y);
+        }
         let idx: usize = (y as usize * self.width as usize) + (x as usize);
-        return &(self.map[idx]);
+        self.map[idx]
     }
 
-    pub fn get_material(&self, x: u16, y: u16) -> u8 {
+    // edits to chunked maps are refused once the chunks they would pin don't
+    // fit in the memory budget. Coordinates are clipped to the map
+    pub fn check_edit(&self, min_x: u32, min_y: u32, max_x: u32, max_y: u32) -> Result<(), String> {
+        let Some(store) = &self.chunks else {
+            return Ok(());
+        };
+        if min_x >= self.width || min_y >= self.height {
+            return Ok(());
+        }
+        store.check_edit(min_x, min_y, max_x.min(self.width - 1), max_y.min(self.height - 1))
+    }
+
+    pub fn set_point(&mut self, x: u32, y: u32, point: MapPoint) {
+        if let Some(store) = &mut self.chunks {
+            store.set_point(x, y, point);
+        } else {
+            let idx: usize = (y as usize * self.width as usize) + (x as usize);
+            self.map[idx] = point;
+        }
+        self.revision += 1;
+        self.mark_dirty(x, y);
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn mark_dirty(&mut self, x: u32, y: u32) {
+        self.dirty = match self.dirty {
+            Some((min_x, min_y, max_x, max_y)) => Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))),
+            None => Some((x, y, x, y)),
+        };
+    }
+
+    // returns the (x, y, width, height) area changed since the last call, if any
+    pub fn take_dirty_rect(&mut self) -> Option<(u32, u32, u32, u32)> {
+        self.dirty.take().map(|(min_x, min_y, max_x, max_y)| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+    }
+
+    pub fn get_material(&self, x: u32, y: u32) -> u8 {
         let point = self.get_point(x, y);
         point.material
     }
 
-    pub fn get_height(&self, x: u16, y: u16) -> u8 {
+    // by the material as it is in the season, so frozen or thawed ground counts
+    pub fn is_passable(&self, x: u32, y: u32, season: Season) -> bool {
+        self.materials.is_passable(self.effective_material(x, y, season))
+    }
+
+    pub fn get_height(&self, x: u32, y: u32) -> u8 {
         let point = self.get_point(x, y);
         point.height
     }
 
-    fn noise_range_change(&self, noise: f64, upper: f64) -> u8 {
-        let normalised_noise = (noise + 1.0) / 2.0; // noise: [-1.0, 1.0] -> [0.0, 1.0] 
-        let scaled_height = normalised_noise * upper; 
-        scaled_height as u8
+    pub fn topology(&self) -> Topology {
+        self.config.topology
     }
 
-    fn biome_noise_to_material(&self, noise: f64) -> u8 {
-        let normalised = (noise + 1.0) / 2.0; // noise: [-1.0, 1.0] -> [0.0, 1.0]
-        
-        // Adjust thresholds to reduce grass proportion
-        // Grass now occupies the middle 25% instead of 33%
-        if normalised < 0.375 {
-            0  // Mud
-        } else if normalised < 0.625 {
-            1  // Grass (reduced range)
-        } else {
-            2  // Ice
-        }
+    // adjacent cells that lie on the map, 4 for square grids and 6 for hex grids
+    pub fn neighbours(&self, x: u32, y: u32) -> Vec<(u32, u32)> {
+        self.topology().neighbours(x as i64, y as i64).into_iter()
+            .filter(|(nx, ny)| *nx >= 0 && *ny >= 0 && (*nx as u32) < self.width && (*ny as u32) < self.height)
+            .map(|(nx, ny)| (nx as u32, ny as u32))
+            .collect()
     }
 
-    pub fn get_dims(&self) -> (u16, u16) {
+    pub fn get_dims(&self) -> (u32, u32) {
         (self.width, self.height)
     }
 
-    pub fn initialise_terrain(&mut self, noise: &Perlin, biome_noise: &Perlin) -> bool {
-        let scale: f64 = BASE_NOISE_SCALE / (self.width as f64 * 0.5);
-        let biome_scale = BASE_BIOME_SCALE / (self.width as f64 * 0.5);
-        
-        println!("{scale}");
-        for y in 0..self.height {
-            for x in 0..self.width { 
-                let noise_val = noise.get([x as f64 * scale, y as f64 * scale]);
-                
-                // Multi-octave biome noise for more organic shapes
-                let biome_x = x as f64 * biome_scale;
-                let biome_y = y as f64 * biome_scale;
-                
-                // Layer multiple frequencies (octaves) for natural-looking biomes
-                let biome_noise_1 = biome_noise.get([biome_x, biome_y]) * 1.0;
-                let biome_noise_2 = biome_noise.get([biome_x * 2.5, biome_y * 2.5]) * 0.5;
-                let biome_noise_3 = biome_noise.get([biome_x * 5.0, biome_y * 5.0]) * 0.25;
-                
-                let combined_biome_noise = (biome_noise_1 + biome_noise_2 + biome_noise_3) / 1.75;
-                
-                let height_val = Self::noise_range_change(self, noise_val, self.depth as f64);
-                let material_val = Self::biome_noise_to_material(self, combined_biome_noise);
-                self.map.push(MapPoint {height: height_val, material: material_val});
+    // copies a rectangle of points out in row-major order, clipped to the map edge.
+    // For chunked terrain only the chunks overlapping the rectangle get generated
+    pub fn get_region(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32, Vec<MapPoint>) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        let region_width = x_end.saturating_sub(x);
+        let region_height = y_end.saturating_sub(y);
+        let mut points = Vec::with_capacity(region_width as usize * region_height as usize);
+        for row in y..y_end {
+            for col in x..x_end {
+                points.push(self.get_point(col, row));
+            }
+        }
+        (region_width, region_height, points)
+    }
+
+    pub fn initialise_terrain(&mut self, seed: u32) -> bool {
+        self.initialise_terrain_with(seed, true)
+    }
+
+    // every cell only depends on its own coordinates, so filling rows in parallel
+    // gives exactly the same map as filling them one after another
+    pub fn initialise_terrain_with(&mut self, seed: u32, parallel: bool) -> bool {
+        let sampler = PointSampler::new(&self.config, &self.materials, seed, self.width, self.depth);
+        let width = self.width as usize;
+        self.map = vec![MapPoint {height: 0, material: 0}; width * self.height as usize];
+        if width == 0 {
+            return true;
+        }
+        let fill_row = |(y, row): (usize, &mut [MapPoint])| {
+            for (x, point) in row.iter_mut().enumerate() {
+                *point = sampler.sample(x as u32, y as u32);
             }
+        };
+        if parallel {
+            self.map.par_chunks_mut(width).enumerate().for_each(fill_row);
+        } else {
+            self.map.chunks_mut(width).enumerate().for_each(fill_row);
         }
         true
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_generation_matches_sequential() {
+        let maps: Vec<Vec<MapPoint>> = [false, true].into_iter().map(|parallel| {
+            let mut terrain = Terrain::new(96, 64, 20);
+            terrain.initialise_terrain_with(7, parallel);
+            terrain.map
+        }).collect();
+        assert!(maps[0] == maps[1], "parallel generation differs from sequential");
+    }
+}
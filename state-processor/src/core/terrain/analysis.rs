@@ -0,0 +1,190 @@
+// Whole-map analysis: material and height statistics, connected regions and
+// movement costs. These visit every cell, so GameState refuses them for
+// chunked terrain, where they would generate the full map
+use super::{PathCost, Terrain};
+use crate::core::time_management::Season;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+// label given to cells that don't belong to any walkable region
+pub const UNWALKABLE: i32 = -1;
+
+// min-heap entry for Dijkstra's algorithm and A*
+pub struct Visit {
+    pub cost: f64,
+    pub index: usize,
+}
+
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl Terrain {
+    pub fn cell_index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn cell_at(&self, index: usize) -> (u32, u32) {
+        ((index % self.width as usize) as u32, (index / self.width as usize) as u32)
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    // number of cells of each material
+    pub fn material_areas(&self) -> HashMap<u8, u64> {
+        let mut areas = HashMap::new();
+        for index in 0..self.cell_count() {
+            let (x, y) = self.cell_at(index);
+            *areas.entry(self.get_material(x, y)).or_insert(0) += 1;
+        }
+        areas
+    }
+
+    // per material, the number of cells at each height from 0 to depth
+    pub fn height_histograms(&self) -> HashMap<u8, Vec<u64>> {
+        let mut histograms: HashMap<u8, Vec<u64>> = HashMap::new();
+        for index in 0..self.cell_count() {
+            let (x, y) = self.cell_at(index);
+            let point = self.get_point(x, y);
+            let histogram = histograms.entry(point.material).or_insert_with(|| vec![0; self.depth as usize + 1]);
+            let bucket = (point.height as usize).min(self.depth as usize);
+            histogram[bucket] += 1;
+        }
+        histograms
+    }
+
+    // flood fills from every unlabelled cell where include holds, joining
+    // neighbours for which connected holds. Returns row-major labels and the count
+    fn label_components(&self, include: impl Fn((u32, u32)) -> bool, connected: impl Fn((u32, u32), (u32, u32)) -> bool) -> (Vec<i32>, u32) {
+        let mut labels = vec![UNWALKABLE; self.cell_count()];
+        let mut count: u32 = 0;
+        let mut queue = VecDeque::new();
+        for start in 0..labels.len() {
+            if labels[start] != UNWALKABLE || !include(self.cell_at(start)) {
+                continue;
+            }
+            labels[start] = count as i32;
+            queue.push_back(start);
+            while let Some(index) = queue.pop_front() {
+                let cell = self.cell_at(index);
+                for neighbour in self.neighbours(cell.0, cell.1) {
+                    let neighbour_index = self.cell_index(neighbour.0, neighbour.1);
+                    if labels[neighbour_index] == UNWALKABLE && include(neighbour) && connected(cell, neighbour) {
+                        labels[neighbour_index] = count as i32;
+                        queue.push_back(neighbour_index);
+                    }
+                }
+            }
+            count += 1;
+        }
+        (labels, count)
+    }
+
+    // regions of neighbouring cells sharing a material, every cell gets a label
+    pub fn label_material_regions(&self) -> (Vec<i32>, u32) {
+        self.label_components(|_| true, |a, b| self.get_material(a.0, a.1) == self.get_material(b.0, b.1))
+    }
+
+    // regions entities can move between without crossing an obstacle. Impassable
+    // cells are labelled UNWALKABLE
+    pub fn label_walkable_regions(&self, season: Season) -> (Vec<i32>, u32) {
+        self.label_components(|(x, y)| self.is_passable(x, y, season), |a, b| self.can_step(a, b, season))
+    }
+
+    // cheapest cost of reaching every cell from start, where a step costs its
+    // length divided by the speed multiplier of the cell being entered for an
+    // entity with the given traits. Unreachable cells, and cells costing more
+    // than max_cost, are infinite
+    pub fn reachability(&self, start: (u32, u32), traits: &[String], max_cost: Option<f64>, season: Season) -> Vec<f64> {
+        let mut costs = vec![f64::INFINITY; self.cell_count()];
+        if start.0 >= self.width || start.1 >= self.height || !self.is_passable(start.0, start.1, season) {
+            return costs;
+        }
+        let limit = max_cost.unwrap_or(f64::INFINITY);
+        let path_cost = PathCost::new(traits, season, Some(0.0));
+        let mut heap = BinaryHeap::new();
+        let start_index = self.cell_index(start.0, start.1);
+        costs[start_index] = 0.0;
+        heap.push(Visit {cost: 0.0, index: start_index});
+
+        while let Some(Visit {cost, index}) = heap.pop() {
+            if cost > costs[index] {
+                continue;
+            }
+            let cell = self.cell_at(index);
+            for neighbour in self.neighbours(cell.0, cell.1) {
+                let Some(step) = self.step_cost(cell, neighbour, &path_cost) else {
+                    continue;
+                };
+                let next_cost = cost + step;
+                let neighbour_index = self.cell_index(neighbour.0, neighbour.1);
+                if next_cost <= limit && next_cost < costs[neighbour_index] {
+                    costs[neighbour_index] = next_cost;
+                    heap.push(Visit {cost: next_cost, index: neighbour_index});
+                }
+            }
+        }
+        costs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::materials::{GRASS, MUD, ROCK};
+    use crate::core::terrain::MapPoint;
+
+    // grass | rock | mud mud, three rows of flat ground split by a rock column
+    fn split_map() -> Terrain {
+        let mut terrain = Terrain::new(5, 3, 4);
+        terrain.set_seasons_enabled(false);
+        let row = [GRASS, GRASS, ROCK, MUD, MUD];
+        terrain.map = (0..15).map(|i| MapPoint {height: 0, material: row[i % 5]}).collect();
+        terrain
+    }
+
+    #[test]
+    fn areas_and_histograms_count_every_cell() {
+        let terrain = split_map();
+        assert_eq!(terrain.material_areas(), HashMap::from([(GRASS, 6), (ROCK, 3), (MUD, 6)]));
+        assert_eq!(terrain.height_histograms()[&MUD], vec![6, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rock_splits_the_walkable_regions() {
+        let terrain = split_map();
+        assert_eq!(terrain.label_material_regions().1, 3);
+        let (labels, count) = terrain.label_walkable_regions(Season::Summer);
+        assert_eq!(count, 2);
+        assert_eq!(labels[terrain.cell_index(2, 1)], UNWALKABLE);
+        assert_ne!(labels[terrain.cell_index(0, 0)], labels[terrain.cell_index(4, 2)]);
+    }
+
+    #[test]
+    fn reachability_stops_at_walls_and_max_cost() {
+        let terrain = split_map();
+        let costs = terrain.reachability((0, 0), &[], None, Season::Summer);
+        assert_eq!(costs[terrain.cell_index(1, 2)], 3.0);
+        assert_eq!(costs[terrain.cell_index(3, 0)], f64::INFINITY);
+        let near = terrain.reachability((0, 0), &[], Some(1.5), Season::Summer);
+        assert_eq!(near.iter().filter(|cost| cost.is_finite()).count(), 3);
+    }
+}
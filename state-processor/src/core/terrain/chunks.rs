@@ -0,0 +1,219 @@
+use super::{MapPoint, PointSampler};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::Mutex;
+
+const MIN_RESIDENT_CHUNKS: usize = 4;
+
+#[derive(Clone)]
+struct Chunk {
+    points: Vec<MapPoint>,
+    last_access: u64,
+    // edited chunks can't be regenerated from the seed, so they are never
+    // evicted and count against the memory budget
+    edited: bool,
+}
+
+#[derive(Clone)]
+struct ChunkCache {
+    chunks: HashMap<(u32, u32), Chunk>,
+    access_clock: u64,
+}
+
+// Holds terrain as square chunks generated on first access from the seed.
+// Least recently used chunks are dropped once the memory budget is exceeded,
+// they get regenerated identically if they are needed again
+pub struct ChunkStore {
+    sampler: PointSampler,
+    chunk_size: u32,
+    max_chunks: usize,
+    cache: Mutex<ChunkCache>,
+}
+
+// copies the resident chunks, so edits carry over
+impl Clone for ChunkStore {
+    fn clone(&self) -> ChunkStore {
+        let cache = self.cache.lock().unwrap().clone();
+        ChunkStore {sampler: self.sampler.clone(), chunk_size: self.chunk_size, max_chunks: self.max_chunks, cache: Mutex::new(cache)}
+    }
+}
+
+impl ChunkStore {
+    pub fn new(sampler: PointSampler, chunk_size: u32, memory_budget: usize) -> ChunkStore {
+        let chunk_size = chunk_size.max(1);
+        let chunk_bytes = (chunk_size as usize).pow(2) * size_of::<MapPoint>();
+        let max_chunks = (memory_budget / chunk_bytes).max(MIN_RESIDENT_CHUNKS);
+        let cache = ChunkCache {chunks: HashMap::new(), access_clock: 0};
+        ChunkStore {sampler, chunk_size, max_chunks, cache: Mutex::new(cache)}
+    }
+
+    pub fn reseed(&mut self, sampler: PointSampler) {
+        self.sampler = sampler;
+        self.cache.get_mut().unwrap().chunks.clear();
+    }
+
+    pub fn resident_chunks(&self) -> usize {
+        self.cache.lock().unwrap().chunks.len()
+    }
+
+    // one slot is always left for chunks that are only read
+    fn max_edited_chunks(&self) -> usize {
+        self.max_chunks - 1
+    }
+
+    // fails if editing cells in the rectangle would leave more edited chunks
+    // resident than the memory budget allows
+    pub fn check_edit(&self, min_x: u32, min_y: u32, max_x: u32, max_y: u32) -> Result<(), String> {
+        let cache = self.cache.lock().unwrap();
+        let limit = self.max_edited_chunks();
+        let mut edited = cache.chunks.values().filter(|chunk| chunk.edited).count();
+        for chunk_y in (min_y / self.chunk_size)..=(max_y / self.chunk_size) {
+            for chunk_x in (min_x / self.chunk_size)..=(max_x / self.chunk_size) {
+                if !cache.chunks.get(&(chunk_x, chunk_y)).is_some_and(|chunk| chunk.edited) {
+                    edited += 1;
+                }
+                if edited > limit {
+                    return Err(format!("edit would keep more than {} edited chunks in memory, raise the memory budget", limit));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_chunk(&self, chunk_x: u32, chunk_y: u32) -> Vec<MapPoint> {
+        let origin_x = chunk_x * self.chunk_size;
+        let origin_y = chunk_y * self.chunk_size;
+        let side = self.chunk_size as usize;
+        let mut points = vec![MapPoint {height: 0, material: 0}; side * side];
+        points.par_chunks_mut(side).enumerate().for_each(|(y, row)| {
+            for (x, point) in row.iter_mut().enumerate() {
+                *point = self.sampler.sample(origin_x.wrapping_add(x as u32), origin_y.wrapping_add(y as u32));
+            }
+        });
+        points
+    }
+
+    fn evict_least_recent(cache: &mut ChunkCache) {
+        let oldest = cache.chunks.iter()
+            .filter(|(_key, chunk)| !chunk.edited)
+            .min_by_key(|(_key, chunk)| chunk.last_access)
+            .map(|(key, _chunk)| *key);
+        if let Some(key) = oldest {
+            cache.chunks.remove(&key);
+        }
+    }
+
+    fn chunk_key_and_index(&self, x: u32, y: u32) -> ((u32, u32), usize) {
+        let key = (x / self.chunk_size, y / self.chunk_size);
+        let idx = ((y % self.chunk_size) * self.chunk_size + (x % self.chunk_size)) as usize;
+        (key, idx)
+    }
+
+    // edited chunks are never evicted, so callers must check_edit first or the
+    // store grows past its budget. GameState checks every edit input
+    pub fn set_point(&mut self, x: u32, y: u32, point: MapPoint) {
+        let (key, idx) = self.chunk_key_and_index(x, y);
+        let missing = !self.cache.get_mut().unwrap().chunks.contains_key(&key);
+        let generated = if missing { Some(self.generate_chunk(key.0, key.1)) } else { None };
+
+        let cache = self.cache.get_mut().unwrap();
+        if missing && cache.chunks.len() >= self.max_chunks {
+            Self::evict_least_recent(cache);
+        }
+        let last_access = cache.access_clock;
+        let chunk = cache.chunks.entry(key)
+            .or_insert_with(|| Chunk {points: generated.unwrap(), last_access, edited: true});
+        chunk.edited = true;
+        chunk.points[idx] = point;
+    }
+
+    fn resident_point(&self, key: (u32, u32), idx: usize) -> Option<MapPoint> {
+        let mut cache = self.cache.lock().unwrap();
+        cache.access_clock += 1;
+        let now = cache.access_clock;
+        let chunk = cache.chunks.get_mut(&key)?;
+        chunk.last_access = now;
+        Some(chunk.points[idx])
+    }
+
+    pub fn get_point(&self, x: u32, y: u32) -> MapPoint {
+        let (key, idx) = self.chunk_key_and_index(x, y);
+        if let Some(point) = self.resident_point(key, idx) {
+            return point;
+        }
+
+        // generated without holding the lock so other threads can keep reading
+        // resident chunks. If another thread generated the same chunk meanwhile
+        // its copy is identical, so whichever got in first is kept
+        let points = self.generate_chunk(key.0, key.1);
+        let point = points[idx];
+        let mut cache = self.cache.lock().unwrap();
+        if !cache.chunks.contains_key(&key) {
+            if cache.chunks.len() >= self.max_chunks {
+                Self::evict_least_recent(&mut cache);
+            }
+            let last_access = cache.access_clock;
+            cache.chunks.insert(key, Chunk {points, last_access, edited: false});
+        }
+        point
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{MaterialRegistry, TerrainConfig};
+
+    const EDITED: MapPoint = MapPoint {height: 9, material: 3};
+
+    // 8x8 chunks, with no budget beyond MIN_RESIDENT_CHUNKS of them
+    fn small_store() -> ChunkStore {
+        let sampler = PointSampler::new(&TerrainConfig::default(), &MaterialRegistry::default(), 3, 1024, 10);
+        ChunkStore::new(sampler, 8, 0)
+    }
+
+    fn is_resident(store: &ChunkStore, key: (u32, u32)) -> bool {
+        store.cache.lock().unwrap().chunks.contains_key(&key)
+    }
+
+    #[test]
+    fn least_recently_read_chunk_is_evicted_and_regenerated_the_same() {
+        let store = small_store();
+        let first = store.get_point(1, 1);
+        for chunk_x in 1..4 {
+            store.get_point(chunk_x * 8, 0);
+        }
+        // reading (0, 0) again makes (1, 0) the least recently used
+        store.get_point(0, 0);
+        store.get_point(32, 0);
+        assert_eq!(store.resident_chunks(), MIN_RESIDENT_CHUNKS);
+        assert!(is_resident(&store, (0, 0)));
+        assert!(!is_resident(&store, (1, 0)));
+        for chunk_x in 5..10 {
+            store.get_point(chunk_x * 8, 0);
+        }
+        assert!(!is_resident(&store, (0, 0)));
+        assert!(store.get_point(1, 1) == first);
+    }
+
+    #[test]
+    fn edited_chunks_are_kept_up_to_the_budget() {
+        let mut store = small_store();
+        // one slot is left for reads, so three chunks can be edited
+        assert!(store.check_edit(0, 0, 23, 7).is_ok());
+        assert!(store.check_edit(0, 0, 31, 7).is_err());
+        for chunk_x in 0..3 {
+            store.set_point(chunk_x * 8, 0, EDITED);
+        }
+        // chunks that are already edited don't count again
+        assert!(store.check_edit(0, 0, 23, 7).is_ok());
+        assert!(store.check_edit(24, 0, 24, 0).is_err());
+        for chunk_x in 0..10 {
+            store.get_point(chunk_x * 8, 8);
+        }
+        for chunk_x in 0..3 {
+            assert!(store.get_point(chunk_x * 8, 0) == EDITED, "edited chunk {} was evicted", chunk_x);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// Edit operations on generated terrain. Every change goes through set_point
+// so the dirty rectangle always covers what the renderer needs to redraw
+use super::{MapPoint, Terrain};
+
+impl Terrain {
+    // cells whose centre lies within radius of (cx, cy), clipped to the map
+    fn cells_in_radius(&self, cx: u32, cy: u32, radius: u32) -> Vec<(u32, u32)> {
+        let min_x = cx.saturating_sub(radius);
+        let min_y = cy.saturating_sub(radius);
+        let max_x = cx.saturating_add(radius).min(self.width.saturating_sub(1));
+        let max_y = cy.saturating_add(radius).min(self.height.saturating_sub(1));
+        let radius_sq = (radius as i64).pow(2);
+
+        let mut cells = vec![];
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let dx = x as i64 - cx as i64;
+                let dy = y as i64 - cy as i64;
+                if dx * dx + dy * dy <= radius_sq {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    // even-odd rule, tested against the centre of the cell
+    fn point_in_polygon(px: f64, py: f64, polygon: &[(f64, f64)]) -> bool {
+        let mut inside = false;
+        let mut j = polygon.len() - 1;
+        for i in 0..polygon.len() {
+            let (xi, yi) = polygon[i];
+            let (xj, yj) = polygon[j];
+            if (yi > py) != (yj > py) && px < (xj - xi) * (py - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+            j = i;
+        }
+        inside
+    }
+
+    fn update_point(&mut self, x: u32, y: u32, edit: impl Fn(MapPoint) -> MapPoint) {
+        let point = self.get_point(x, y);
+        self.set_point(x, y, edit(point));
+    }
+
+    pub fn paint_circle(&mut self, cx: u32, cy: u32, radius: u32, material: u8) {
+        for (x, y) in self.cells_in_radius(cx, cy, radius) {
+            self.update_point(x, y, |point| MapPoint {material, ..point});
+        }
+    }
+
+    pub fn paint_rect(&mut self, x: u32, y: u32, width: u32, height: u32, material: u8) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        for row in y..y_end {
+            for col in x..x_end {
+                self.update_point(col, row, |point| MapPoint {material, ..point});
+            }
+        }
+    }
+
+    pub fn paint_polygon(&mut self, polygon: &[(f64, f64)], material: u8) {
+        if polygon.len() < 3 || self.width == 0 || self.height == 0 {
+            return;
+        }
+        let clip = |value: f64, upper: u32| value.clamp(0.0, (upper - 1) as f64) as u32;
+        let min_x = clip(polygon.iter().map(|p| p.0).fold(f64::INFINITY, f64::min), self.width);
+        let max_x = clip(polygon.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max), self.width);
+        let min_y = clip(polygon.iter().map(|p| p.1).fold(f64::INFINITY, f64::min), self.height);
+        let max_y = clip(polygon.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max), self.height);
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                if Self::point_in_polygon(x as f64 + 0.5, y as f64 + 0.5, polygon) {
+                    self.update_point(x, y, |point| MapPoint {material, ..point});
+                }
+            }
+        }
+    }
+
+    // positive amounts raise, negative amounts lower, clamped to [0, depth]
+    pub fn adjust_height(&mut self, cx: u32, cy: u32, radius: u32, amount: i16) {
+        let depth = self.depth as i16;
+        for (x, y) in self.cells_in_radius(cx, cy, radius) {
+            self.update_point(x, y, |point| {
+                let height = (point.height as i16 + amount).clamp(0, depth) as u8;
+                MapPoint {height, ..point}
+            });
+        }
+    }
+
+    pub fn flatten(&mut self, cx: u32, cy: u32, radius: u32, target: u8) {
+        let height = target.min(self.depth);
+        for (x, y) in self.cells_in_radius(cx, cy, radius) {
+            self.update_point(x, y, |point| MapPoint {height, ..point});
+        }
+    }
+
+    // swaps in an earlier copy of this map, marking all of it for redrawing
+    pub fn restore(&mut self, earlier: Terrain) {
+        *self = earlier;
+        self.mark_dirty(0, 0);
+        self.mark_dirty(self.width - 1, self.height - 1);
+    }
+
+    // box blur of heights over each cell's 3x3 neighbourhood, repeated per iteration
+    pub fn smooth(&mut self, cx: u32, cy: u32, radius: u32, iterations: u8) {
+        let cells = self.cells_in_radius(cx, cy, radius);
+        for _ in 0..iterations {
+            let smoothed: Vec<u8> = cells.iter().map(|&(x, y)| self.neighbourhood_mean(x, y)).collect();
+            for (&(x, y), height) in cells.iter().zip(smoothed) {
+                self.update_point(x, y, |point| MapPoint {height, ..point});
+            }
+        }
+    }
+
+    fn neighbourhood_mean(&self, x: u32, y: u32) -> u8 {
+        let mut total: u32 = 0;
+        let mut count: u32 = 0;
+        for ny in y.saturating_sub(1)..=(y + 1).min(self.height - 1) {
+            for nx in x.saturating_sub(1)..=(x + 1).min(self.width - 1) {
+                total += self.get_height(nx, ny) as u32;
+                count += 1;
+            }
+        }
+        ((total as f64 / count as f64).round()) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::materials::{GRASS, ROCK};
+
+    fn flat_map(height: u8) -> Terrain {
+        let mut terrain = Terrain::new(10, 8, 10);
+        terrain.map = vec![MapPoint {height, material: GRASS}; 10 * 8];
+        terrain
+    }
+
+    #[test]
+    fn paint_circle_covers_cells_within_the_radius() {
+        let mut terrain = flat_map(5);
+        terrain.paint_circle(4, 4, 2, ROCK);
+        assert_eq!(terrain.get_material(4, 2), ROCK);
+        assert_eq!(terrain.get_material(6, 4), ROCK);
+        // the corner of the bounding square is further than 2 away
+        assert_eq!(terrain.get_material(6, 6), GRASS);
+        assert_eq!(terrain.take_dirty_rect(), Some((2, 2, 5, 5)));
+        assert_eq!(terrain.take_dirty_rect(), None);
+    }
+
+    #[test]
+    fn paint_rect_is_clipped_to_the_map() {
+        let mut terrain = flat_map(5);
+        terrain.paint_rect(8, 6, 5, 5, ROCK);
+        let painted = terrain.map.iter().filter(|point| point.material == ROCK).count();
+        assert_eq!(painted, 4);
+        assert_eq!(terrain.take_dirty_rect(), Some((8, 6, 2, 2)));
+    }
+
+    #[test]
+    fn height_edits_stay_within_the_depth() {
+        let mut terrain = flat_map(5);
+        terrain.adjust_height(0, 0, 1, 100);
+        assert_eq!(terrain.get_height(0, 0), 10);
+        terrain.adjust_height(0, 0, 1, -100);
+        assert_eq!(terrain.get_height(1, 0), 0);
+        terrain.flatten(5, 5, 0, 200);
+        assert_eq!(terrain.get_height(5, 5), 10);
+        // smoothing a single peak spreads it into its neighbours
+        terrain.smooth(5, 5, 1, 1);
+        assert!(terrain.get_height(5, 5) < 10);
+        assert!(terrain.get_height(5, 4) > 5);
+    }
+}
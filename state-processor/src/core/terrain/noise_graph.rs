@@ -0,0 +1,330 @@
+// Declarative description of the noise used for each terrain layer. A graph is
+// a tree of nodes that gets built into noise crate functions for a given seed
+use noise::{Blend, Fbm, MultiFractal, NoiseFn, Perlin, RidgedMulti, ScaleBias, ScalePoint, Seedable, Select, Terrace, Turbulence};
+use crate::core::topology::Topology;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+type BoxedNoise = Box<dyn NoiseFn<f64, 2> + Send + Sync>;
+// built layers are shared between the terrain and its chunk store
+pub type NoiseSource = Arc<dyn NoiseFn<f64, 2> + Send + Sync>;
+
+const BIOME_SEED_OFFSET: u32 = 1000;
+
+fn default_octaves() -> usize { 6 }
+fn default_one() -> f64 { 1.0 }
+fn default_lacunarity() -> f64 { 2.0 }
+fn default_persistence() -> f64 { 0.5 }
+fn default_attenuation() -> f64 { 2.0 }
+fn default_roughness() -> usize { 3 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NoiseNode {
+    Perlin {
+        #[serde(default)]
+        seed_offset: u32,
+    },
+    Fbm {
+        #[serde(default)]
+        seed_offset: u32,
+        #[serde(default = "default_octaves")]
+        octaves: usize,
+        #[serde(default = "default_one")]
+        frequency: f64,
+        #[serde(default = "default_lacunarity")]
+        lacunarity: f64,
+        #[serde(default = "default_persistence")]
+        persistence: f64,
+    },
+    Ridged {
+        #[serde(default)]
+        seed_offset: u32,
+        #[serde(default = "default_octaves")]
+        octaves: usize,
+        #[serde(default = "default_one")]
+        frequency: f64,
+        #[serde(default = "default_lacunarity")]
+        lacunarity: f64,
+        #[serde(default = "default_one")]
+        persistence: f64,
+        #[serde(default = "default_attenuation")]
+        attenuation: f64,
+    },
+    // multiplies the input coordinates, i.e. changes the feature size of source
+    Scale {
+        factor: f64,
+        source: Box<NoiseNode>,
+    },
+    // output * scale + bias
+    ScaleBias {
+        #[serde(default = "default_one")]
+        scale: f64,
+        #[serde(default)]
+        bias: f64,
+        source: Box<NoiseNode>,
+    },
+    // weighted sum of the terms, divided by the total weight
+    Sum {
+        terms: Vec<(f64, NoiseNode)>,
+    },
+    // domain warping, offsets the input coordinates of source by turbulence
+    Warp {
+        #[serde(default)]
+        seed_offset: u32,
+        #[serde(default = "default_one")]
+        frequency: f64,
+        #[serde(default = "default_one")]
+        power: f64,
+        #[serde(default = "default_roughness")]
+        roughness: usize,
+        source: Box<NoiseNode>,
+    },
+    // control points are output values in [-1, 1], at least two different ones
+    // are required
+    Terrace {
+        control_points: Vec<f64>,
+        #[serde(default)]
+        invert: bool,
+        source: Box<NoiseNode>,
+    },
+    // linear interpolation between a and b, weighted by control
+    Blend {
+        a: Box<NoiseNode>,
+        b: Box<NoiseNode>,
+        control: Box<NoiseNode>,
+    },
+    // b where control is within [lower, upper], otherwise a
+    Select {
+        a: Box<NoiseNode>,
+        b: Box<NoiseNode>,
+        control: Box<NoiseNode>,
+        lower: f64,
+        upper: f64,
+        #[serde(default)]
+        falloff: f64,
+    },
+}
+
+impl NoiseNode {
+    fn boxed(self) -> Box<NoiseNode> {
+        Box::new(self)
+    }
+
+    pub fn build(&self, seed: u32) -> Result<BoxedNoise, String> {
+        let source: BoxedNoise = match self {
+            NoiseNode::Perlin {seed_offset} => Box::new(Perlin::new(seed.wrapping_add(*seed_offset))),
+            NoiseNode::Fbm {seed_offset, octaves, frequency, lacunarity, persistence} => {
+                Box::new(Fbm::<Perlin>::new(seed.wrapping_add(*seed_offset))
+                    .set_octaves(*octaves)
+                    .set_frequency(*frequency)
+                    .set_lacunarity(*lacunarity)
+                    .set_persistence(*persistence))
+            },
+            NoiseNode::Ridged {seed_offset, octaves, frequency, lacunarity, persistence, attenuation} => {
+                Box::new(RidgedMulti::<Perlin>::new(seed.wrapping_add(*seed_offset))
+                    .set_octaves(*octaves)
+                    .set_frequency(*frequency)
+                    .set_lacunarity(*lacunarity)
+                    .set_persistence(*persistence)
+                    .set_attenuation(*attenuation))
+            },
+            NoiseNode::Scale {factor, source} => {
+                Box::new(ScalePoint::new(source.build(seed)?).set_scale(*factor))
+            },
+            NoiseNode::ScaleBias {scale, bias, source} => {
+                Box::new(ScaleBias::new(source.build(seed)?).set_scale(*scale).set_bias(*bias))
+            },
+            NoiseNode::Sum {terms} => {
+                let total_weight: f64 = terms.iter().map(|(weight, _)| weight).sum();
+                if terms.is_empty() || total_weight == 0.0 {
+                    return Err("sum node needs at least one term with non-zero weight".to_string());
+                }
+                let built = terms.iter()
+                    .map(|(weight, node)| Ok((*weight, node.build(seed)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Box::new(WeightedSum {terms: built, total_weight})
+            },
+            NoiseNode::Warp {seed_offset, frequency, power, roughness, source} => {
+                let warp: Turbulence<BoxedNoise, Perlin> = Turbulence::new(source.build(seed)?);
+                Box::new(warp
+                    .set_seed(seed.wrapping_add(*seed_offset))
+                    .set_frequency(*frequency)
+                    .set_power(*power)
+                    .set_roughness(*roughness))
+            },
+            NoiseNode::Terrace {control_points, invert, source} => {
+                if control_points.len() < 2 {
+                    return Err("terrace node needs at least two control points".to_string());
+                }
+                // the noise crate silently drops repeated points, which can leave
+                // too few to interpolate between
+                let mut sorted = control_points.clone();
+                sorted.sort_by(f64::total_cmp);
+                if sorted.iter().any(|point| !point.is_finite()) || sorted.windows(2).any(|pair| (pair[1] - pair[0]).abs() < f64::EPSILON) {
+                    return Err("terrace control points must be finite and all different".to_string());
+                }
+                let mut terrace = Terrace::new(source.build(seed)?).invert_terraces(*invert);
+                for point in control_points {
+                    terrace = terrace.add_control_point(*point);
+                }
+                Box::new(terrace)
+            },
+            NoiseNode::Blend {a, b, control} => {
+                Box::new(Blend::new(a.build(seed)?, b.build(seed)?, control.build(seed)?))
+            },
+            NoiseNode::Select {a, b, control, lower, upper, falloff} => {
+                Box::new(Select::new(a.build(seed)?, b.build(seed)?, control.build(seed)?)
+                    .set_bounds(*lower, *upper)
+                    .set_falloff(*falloff))
+            },
+        };
+        Ok(source)
+    }
+}
+
+struct WeightedSum {
+    terms: Vec<(f64, BoxedNoise)>,
+    total_weight: f64,
+}
+
+impl NoiseFn<f64, 2> for WeightedSum {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        let total: f64 = self.terms.iter().map(|(weight, source)| source.get(point) * weight).sum();
+        total / self.total_weight
+    }
+}
+
+// scale is the number of features across half the map width, so a layer
+// looks the same regardless of map size
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoiseLayer {
+    pub scale: f64,
+    #[serde(default)]
+    pub seed_offset: u32,
+    pub node: NoiseNode,
+}
+
+impl NoiseLayer {
+    pub fn build(&self, seed: u32) -> Result<NoiseSource, String> {
+        Ok(Arc::from(self.node.build(seed.wrapping_add(self.seed_offset))?))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TerrainConfig {
+    pub height: NoiseLayer,
+    pub biome: NoiseLayer,
+    #[serde(default)]
+    pub topology: Topology,
+}
+
+impl Default for TerrainConfig {
+    // single Perlin octave for height and three summed octaves for biomes
+    fn default() -> TerrainConfig {
+        let octave = |factor: f64| NoiseNode::Scale {factor, source: NoiseNode::Perlin {seed_offset: 0}.boxed()};
+        TerrainConfig {
+            height: NoiseLayer {scale: 6.0, seed_offset: 0, node: NoiseNode::Perlin {seed_offset: 0}},
+            biome: NoiseLayer {
+                scale: 0.8,
+                seed_offset: BIOME_SEED_OFFSET,
+                node: NoiseNode::Sum {terms: vec![(1.0, octave(1.0)), (0.5, octave(2.5)), (0.25, octave(5.0))]},
+            },
+            topology: Topology::Square,
+        }
+    }
+}
+
+impl TerrainConfig {
+    // land rising out of low ground, with gentle terraced shores
+    fn islands() -> TerrainConfig {
+        let land = NoiseNode::Fbm {seed_offset: 0, octaves: 5, frequency: 1.0, lacunarity: 2.0, persistence: 0.5};
+        let warped = NoiseNode::Warp {seed_offset: 7, frequency: 2.0, power: 0.15, roughness: 3, source: land.boxed()};
+        let sunk = NoiseNode::ScaleBias {scale: 1.2, bias: -0.35, source: warped.boxed()};
+        let shores = NoiseNode::Terrace {control_points: vec![-1.0, -0.3, -0.1, 0.4, 1.0], invert: false, source: sunk.boxed()};
+        TerrainConfig {height: NoiseLayer {scale: 4.0, seed_offset: 0, node: shores}, ..TerrainConfig::default()}
+    }
+
+    // ridged peaks where the control noise is high, rolling hills elsewhere
+    fn mountains() -> TerrainConfig {
+        let ridges = NoiseNode::Ridged {seed_offset: 0, octaves: 6, frequency: 1.0, lacunarity: 2.0, persistence: 1.0, attenuation: 2.0};
+        let hills = NoiseNode::Fbm {seed_offset: 1, octaves: 4, frequency: 1.0, lacunarity: 2.0, persistence: 0.5};
+        let control = NoiseNode::Scale {factor: 0.5, source: NoiseNode::Perlin {seed_offset: 2}.boxed()};
+        let height = NoiseNode::Blend {a: hills.boxed(), b: ridges.boxed(), control: control.boxed()};
+        TerrainConfig {height: NoiseLayer {scale: 5.0, seed_offset: 0, node: height}, ..TerrainConfig::default()}
+    }
+
+    // low amplitude undulation around the middle of the height range
+    fn plains() -> TerrainConfig {
+        let gentle = NoiseNode::Fbm {seed_offset: 0, octaves: 3, frequency: 1.0, lacunarity: 2.0, persistence: 0.4};
+        let flat = NoiseNode::ScaleBias {scale: 0.25, bias: 0.0, source: gentle.boxed()};
+        TerrainConfig {height: NoiseLayer {scale: 2.0, seed_offset: 0, node: flat}, ..TerrainConfig::default()}
+    }
+
+    pub fn preset(name: &str) -> Option<TerrainConfig> {
+        match name {
+            "default" => Some(TerrainConfig::default()),
+            "islands" => Some(TerrainConfig::islands()),
+            "mountains" => Some(TerrainConfig::mountains()),
+            "plains" => Some(TerrainConfig::plains()),
+            _ => None,
+        }
+    }
+
+    // accepts either a preset name or a JSON description of the layers
+    pub fn parse(text: &str) -> Result<TerrainConfig, String> {
+        let trimmed = text.trim();
+        if trimmed.starts_with('{') {
+            let config: TerrainConfig = serde_json::from_str(trimmed).map_err(|e| e.to_string())?;
+            // catch invalid graphs now rather than during generation
+            config.height.build(0)?;
+            config.biome.build(0)?;
+            return Ok(config);
+        }
+        Self::preset(trimmed).ok_or_else(|| format!("unknown terrain preset '{}'", trimmed))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: [[f64; 2]; 3] = [[0.1, 0.2], [1.7, -3.3], [12.5, 40.25]];
+
+    #[test]
+    fn presets_round_trip_through_json() {
+        for name in ["default", "islands", "mountains", "plains"] {
+            let preset = TerrainConfig::preset(name).unwrap();
+            let parsed = TerrainConfig::parse(&preset.to_json()).unwrap();
+            let (original, copy) = (preset.height.build(9).unwrap(), parsed.height.build(9).unwrap());
+            for point in SAMPLES {
+                assert_eq!(original.get(point), copy.get(point), "{} differs after parsing", name);
+            }
+        }
+        assert!(TerrainConfig::parse("swamp").is_err());
+    }
+
+    #[test]
+    fn sum_divides_by_the_total_weight() {
+        let perlin = NoiseNode::Perlin {seed_offset: 0};
+        let sum = NoiseNode::Sum {terms: vec![(1.0, perlin.clone()), (3.0, perlin.clone())]};
+        let (single, summed) = (perlin.build(4).unwrap(), sum.build(4).unwrap());
+        for point in SAMPLES {
+            assert!((single.get(point) - summed.get(point)).abs() < 1e-12);
+        }
+        assert!(NoiseNode::Sum {terms: vec![(0.0, perlin)]}.build(4).is_err());
+    }
+
+    #[test]
+    fn invalid_graphs_are_rejected_when_parsed() {
+        let repeated = r#"{"height": {"scale": 1.0, "node": {"type": "terrace", "control_points": [0.5, 0.5], "source": {"type": "perlin"}}},
+            "biome": {"scale": 1.0, "node": {"type": "perlin"}}}"#;
+        assert!(TerrainConfig::parse(repeated).is_err());
+        let valid = repeated.replace("[0.5, 0.5]", "[-0.5, 0.5]");
+        assert!(TerrainConfig::parse(&valid).is_ok());
+    }
+}
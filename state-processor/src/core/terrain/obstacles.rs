@@ -0,0 +1,53 @@
+// Cells entities can't move into: impassable materials such as rock, and
+// cliffs where the ground changes height too steeply between neighbours
+use super::Terrain;
+use crate::core::time_management::Season;
+
+impl Terrain {
+    pub fn set_cliff_height(&mut self, cliff_height: u8) {
+        self.cliff_height = cliff_height;
+        self.revision += 1;
+    }
+
+    pub fn get_cliff_height(&self) -> u8 {
+        self.cliff_height
+    }
+
+    fn is_cliff(&self, from: (u32, u32), to: (u32, u32)) -> bool {
+        self.get_height(from.0, from.1).abs_diff(self.get_height(to.0, to.1)) > self.cliff_height
+    }
+
+    // whether an entity can move between two adjacent cells
+    pub fn can_step(&self, from: (u32, u32), to: (u32, u32), season: Season) -> bool {
+        if from == to {
+            return true;
+        }
+        self.is_passable(to.0, to.1, season) && !self.is_cliff(from, to)
+    }
+
+    // impassable, or at the top or bottom of a cliff on any side
+    pub fn is_obstacle(&self, x: u32, y: u32, season: Season) -> bool {
+        !self.is_passable(x, y, season) || self.neighbours(x, y).into_iter().any(|n| self.is_cliff((x, y), n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::materials::{GRASS, ROCK};
+    use crate::core::terrain::MapPoint;
+
+    #[test]
+    fn steps_are_blocked_by_rock_and_cliffs() {
+        let mut terrain = Terrain::new(3, 1, 10);
+        terrain.map = vec![MapPoint {height: 0, material: GRASS}, MapPoint {height: 5, material: GRASS}, MapPoint {height: 5, material: ROCK}];
+        assert!(!terrain.can_step((0, 0), (1, 0), Season::Summer));
+        assert!(terrain.is_obstacle(0, 0, Season::Summer));
+        assert!(!terrain.can_step((1, 0), (2, 0), Season::Summer));
+        terrain.set_cliff_height(5);
+        assert!(terrain.can_step((0, 0), (1, 0), Season::Summer));
+        assert!(terrain.can_step((1, 0), (0, 0), Season::Summer));
+        assert!(!terrain.is_obstacle(0, 0, Season::Summer));
+        assert!(terrain.is_obstacle(2, 0, Season::Summer));
+    }
+}
@@ -0,0 +1,303 @@
+// Routes across the terrain. A step costs its length divided by the speed
+// multiplier of the cell entered for the entity's traits, as in reachability,
+// plus a penalty per height unit climbed. Single routes use A*, or Dijkstra's
+// algorithm for comparison, and flow fields give every cell's next step
+// toward a shared goal. On chunked terrain searches generate the chunks they
+// visit, and flow fields, which cover every cell, aren't available
+use super::Terrain;
+use super::analysis::Visit;
+use crate::core::time_management::Season;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::Arc;
+
+pub const DEFAULT_SLOPE_PENALTY: f64 = 0.5;
+// cached entries kept before the cache starts over
+const MAX_CACHED_PATHS: usize = 1024;
+const MAX_CACHED_FLOW_FIELDS: usize = 16;
+// cells a route search expands before giving up, so an unreachable goal on a
+// huge map fails in bounded time and memory
+const MAX_PATH_EXPANSIONS: usize = 1 << 20;
+
+const CHUNKED_FLOW_FIELD: &str = "flow fields cover the whole map, which is chunked, use path_to or find_path";
+
+// what a step costs for a particular entity
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathCost {
+    pub traits: Vec<String>,
+    pub season: Season,
+    // added per height unit climbed, descending is free
+    pub slope_penalty: f64,
+}
+
+impl PathCost {
+    pub fn new(traits: &[String], season: Season, slope_penalty: Option<f64>) -> PathCost {
+        PathCost {traits: traits.to_vec(), season, slope_penalty: slope_penalty.unwrap_or(DEFAULT_SLOPE_PENALTY).max(0.0)}
+    }
+
+    fn key(&self) -> (Vec<String>, u64) {
+        (self.traits.clone(), self.slope_penalty.to_bits())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Path {
+    // from start to goal, both included
+    pub cells: Vec<(u32, u32)>,
+    pub cost: f64,
+}
+
+// cost of the cheapest route from every cell to the goal, infinite where the
+// goal can't be reached
+#[derive(Clone, Debug)]
+pub struct FlowField {
+    pub goal: (u32, u32),
+    pub costs: Vec<f64>,
+}
+
+impl FlowField {
+    // the neighbour to step to from cell on a cheapest route, None at the goal
+    // or where it can't be reached
+    pub fn next_step(&self, terrain: &Terrain, cell: (u32, u32), cost: &PathCost) -> Option<(u32, u32)> {
+        if cell == self.goal {
+            return None;
+        }
+        terrain.neighbours(cell.0, cell.1).into_iter()
+            .filter_map(|n| Some((n, terrain.step_cost(cell, n, cost)? + self.costs[terrain.cell_index(n.0, n.1)])))
+            .filter(|(_, total)| total.is_finite())
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(n, _)| n)
+    }
+}
+
+impl Terrain {
+    // None where the step can't be taken
+    pub fn step_cost(&self, from: (u32, u32), to: (u32, u32), cost: &PathCost) -> Option<f64> {
+        if !self.can_step(from, to, cost.season) {
+            return None;
+        }
+        let multiplier = self.materials.speed_multiplier(self.effective_material(to.0, to.1, cost.season), &cost.traits);
+        if multiplier <= 0.0 {
+            return None;
+        }
+        let length = self.topology().distance((from.0 as i64, from.1 as i64), (to.0 as i64, to.1 as i64));
+        let climb = self.get_height(to.0, to.1).saturating_sub(self.get_height(from.0, from.1));
+        Some(length / multiplier + climb as f64 * cost.slope_penalty)
+    }
+
+    fn in_bounds(&self, cell: (u32, u32)) -> bool {
+        cell.0 < self.width && cell.1 < self.height
+    }
+
+    // cheapest route from start to goal. A* is guided by the straight line
+    // distance at the fastest speed available, Dijkstra's algorithm (astar
+    // false) searches outward evenly and finds a route of the same cost. None
+    // when there is no route or it would take more than MAX_PATH_EXPANSIONS
+    // cells to find
+    pub fn find_path(&self, start: (u32, u32), goal: (u32, u32), cost: &PathCost, astar: bool) -> Option<Path> {
+        if !self.in_bounds(start) || !self.in_bounds(goal) || !self.is_passable(goal.0, goal.1, cost.season) {
+            return None;
+        }
+        let topology = self.topology();
+        let fastest = self.materials.sorted().iter()
+            .map(|def| self.materials.speed_multiplier(def.id, &cost.traits))
+            .fold(0.0, f64::max);
+        let heuristic = |cell: (u32, u32)| {
+            if !astar || fastest <= 0.0 {
+                return 0.0;
+            }
+            topology.distance((cell.0 as i64, cell.1 as i64), (goal.0 as i64, goal.1 as i64)) / fastest
+        };
+
+        let mut costs: HashMap<usize, f64> = HashMap::new();
+        let mut came_from: HashMap<usize, usize> = HashMap::new();
+        let mut closed = HashSet::new();
+        let mut heap = BinaryHeap::new();
+        let start_index = self.cell_index(start.0, start.1);
+        let goal_index = self.cell_index(goal.0, goal.1);
+        costs.insert(start_index, 0.0);
+        heap.push(Visit {cost: heuristic(start), index: start_index});
+
+        while let Some(Visit {index, ..}) = heap.pop() {
+            if !closed.insert(index) {
+                continue;
+            }
+            if closed.len() > MAX_PATH_EXPANSIONS {
+                return None;
+            }
+            if index == goal_index {
+                let mut cells = vec![goal];
+                let mut current = goal_index;
+                while let Some(previous) = came_from.get(&current) {
+                    current = *previous;
+                    cells.push(self.cell_at(current));
+                }
+                cells.reverse();
+                return Some(Path {cells, cost: costs[&goal_index]});
+            }
+            let cell = self.cell_at(index);
+            let cost_here = costs[&index];
+            for neighbour in self.neighbours(cell.0, cell.1) {
+                let Some(step) = self.step_cost(cell, neighbour, cost) else {
+                    continue;
+                };
+                let next_cost = cost_here + step;
+                let neighbour_index = self.cell_index(neighbour.0, neighbour.1);
+                if next_cost < costs.get(&neighbour_index).copied().unwrap_or(f64::INFINITY) {
+                    costs.insert(neighbour_index, next_cost);
+                    came_from.insert(neighbour_index, index);
+                    heap.push(Visit {cost: next_cost + heuristic(neighbour), index: neighbour_index});
+                }
+            }
+        }
+        None
+    }
+
+    // Dijkstra's algorithm outward from the goal along reversed steps
+    pub fn flow_field(&self, goal: (u32, u32), cost: &PathCost) -> Result<FlowField, String> {
+        if self.is_chunked() {
+            return Err(CHUNKED_FLOW_FIELD.to_string());
+        }
+        let mut costs = vec![f64::INFINITY; self.cell_count()];
+        if !self.in_bounds(goal) || !self.is_passable(goal.0, goal.1, cost.season) {
+            return Ok(FlowField {goal, costs});
+        }
+        let mut heap = BinaryHeap::new();
+        let goal_index = self.cell_index(goal.0, goal.1);
+        costs[goal_index] = 0.0;
+        heap.push(Visit {cost: 0.0, index: goal_index});
+
+        while let Some(Visit {cost: cost_here, index}) = heap.pop() {
+            if cost_here > costs[index] {
+                continue;
+            }
+            let cell = self.cell_at(index);
+            for neighbour in self.neighbours(cell.0, cell.1) {
+                let Some(step) = self.step_cost(neighbour, cell, cost) else {
+                    continue;
+                };
+                let next_cost = cost_here + step;
+                let neighbour_index = self.cell_index(neighbour.0, neighbour.1);
+                if next_cost < costs[neighbour_index] {
+                    costs[neighbour_index] = next_cost;
+                    heap.push(Visit {cost: next_cost, index: neighbour_index});
+                }
+            }
+        }
+        Ok(FlowField {goal, costs})
+    }
+}
+
+type PathKey = ((u32, u32), (u32, u32), (Vec<String>, u64));
+type FlowKey = ((u32, u32), (Vec<String>, u64));
+
+// Paths and flow fields computed for the current terrain and season. Any edit
+// or change of season empties it, see Terrain::revision
+#[derive(Clone, Default)]
+pub struct PathCache {
+    valid_for: Option<(u64, Season)>,
+    paths: HashMap<PathKey, Option<Arc<Path>>>,
+    flow_fields: HashMap<FlowKey, Arc<FlowField>>,
+    hits: u64,
+    misses: u64,
+}
+
+// the contents depend on which queries happened to run, so they're left out of
+// debug output and so out of replay checksums
+impl std::fmt::Debug for PathCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PathCache")
+    }
+}
+
+impl PathCache {
+    fn refresh(&mut self, terrain: &Terrain, season: Season) {
+        let current = Some((terrain.revision(), season));
+        if self.valid_for != current {
+            self.paths.clear();
+            self.flow_fields.clear();
+            self.valid_for = current;
+        }
+    }
+
+    pub fn path(&mut self, terrain: &Terrain, start: (u32, u32), goal: (u32, u32), cost: &PathCost) -> Option<Arc<Path>> {
+        self.refresh(terrain, cost.season);
+        let key = (start, goal, cost.key());
+        if let Some(path) = self.paths.get(&key) {
+            self.hits += 1;
+            return path.clone();
+        }
+        self.misses += 1;
+        if self.paths.len() >= MAX_CACHED_PATHS {
+            self.paths.clear();
+        }
+        let path = terrain.find_path(start, goal, cost, true).map(Arc::new);
+        self.paths.insert(key, path.clone());
+        path
+    }
+
+    pub fn flow_field(&mut self, terrain: &Terrain, goal: (u32, u32), cost: &PathCost) -> Result<Arc<FlowField>, String> {
+        self.refresh(terrain, cost.season);
+        let key = (goal, cost.key());
+        if let Some(field) = self.flow_fields.get(&key) {
+            self.hits += 1;
+            return Ok(field.clone());
+        }
+        self.misses += 1;
+        if self.flow_fields.len() >= MAX_CACHED_FLOW_FIELDS {
+            self.flow_fields.clear();
+        }
+        let field = Arc::new(terrain.flow_field(goal, cost)?);
+        self.flow_fields.insert(key, field.clone());
+        Ok(field)
+    }
+
+    // (hits, misses) since the game started
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::materials::{GRASS, ROCK};
+    use crate::core::terrain::MapPoint;
+
+    // flat grass with a rock wall down column 3, open only at the bottom row
+    fn walled_map() -> Terrain {
+        let mut terrain = Terrain::new(7, 5, 10);
+        terrain.set_seasons_enabled(false);
+        terrain.map = vec![MapPoint {height: 0, material: GRASS}; 7 * 5];
+        for y in 0..4 {
+            terrain.set_point(3, y, MapPoint {height: 0, material: ROCK});
+        }
+        terrain
+    }
+
+    #[test]
+    fn astar_goes_around_a_wall() {
+        let terrain = walled_map();
+        let cost = PathCost::new(&[], Season::Spring, None);
+        let path = terrain.find_path((0, 0), (6, 0), &cost, true).expect("the gap leaves a route");
+        assert_eq!(path.cells.first(), Some(&(0, 0)));
+        assert_eq!(path.cells.last(), Some(&(6, 0)));
+        assert!(path.cells.contains(&(3, 4)));
+        for pair in path.cells.windows(2) {
+            assert!(terrain.step_cost(pair[0], pair[1], &cost).is_some(), "{:?} can't be stepped", pair);
+        }
+        // square cells step four ways, so 6 across plus 4 down to the gap and 4 back up
+        let expected = 14.0;
+        assert!((path.cost - expected).abs() < 1e-9, "cost {} instead of {}", path.cost, expected);
+        let dijkstra = terrain.find_path((0, 0), (6, 0), &cost, false).unwrap();
+        assert!((dijkstra.cost - path.cost).abs() < 1e-9);
+    }
+
+    #[test]
+    fn astar_finds_no_route_through_a_closed_wall() {
+        let mut terrain = walled_map();
+        terrain.set_point(3, 4, MapPoint {height: 0, material: ROCK});
+        let cost = PathCost::new(&[], Season::Spring, None);
+        assert!(terrain.find_path((0, 0), (6, 0), &cost, true).is_none());
+        assert!(terrain.find_path((0, 0), (3, 0), &cost, true).is_none());
+    }
+}
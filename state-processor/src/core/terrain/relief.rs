@@ -0,0 +1,159 @@
+// Layers derived from height: slope, aspect and hillshade. Gradients are fitted
+// over each cell's neighbours in world space, so hex maps are handled the same
+// way as square ones
+use super::Terrain;
+use rayon::prelude::*;
+
+// aspect given to cells with no slope
+pub const FLAT_ASPECT: f64 = -1.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Relief {
+    // compass direction the sun shines from, in degrees clockwise from north (up)
+    pub azimuth: f64,
+    // sun elevation above the horizon in degrees
+    pub altitude: f64,
+    // height units per cell width, exaggerates or flattens slopes
+    pub z_factor: f64,
+}
+
+impl Default for Relief {
+    fn default() -> Relief {
+        Relief {azimuth: 315.0, altitude: 45.0, z_factor: 1.0}
+    }
+}
+
+impl Terrain {
+    pub fn set_relief(&mut self, relief: Relief) {
+        self.relief = relief;
+    }
+
+    pub fn get_relief(&self) -> Relief {
+        self.relief
+    }
+
+    // least squares fit of a plane through the height differences to each
+    // neighbour, returns (dz/dx, dz/dy) with y pointing down the map
+    pub fn gradient(&self, x: u32, y: u32) -> (f64, f64) {
+        let topology = self.topology();
+        let (cx, cy) = topology.world_position(x, y);
+        let height = self.get_height(x, y) as f64 * self.relief.z_factor;
+        let (mut sxx, mut sxy, mut syy, mut sxz, mut syz) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (nx, ny) in self.neighbours(x, y) {
+            let (wx, wy) = topology.world_position(nx, ny);
+            let (dx, dy) = (wx - cx, wy - cy);
+            let dz = self.get_height(nx, ny) as f64 * self.relief.z_factor - height;
+            sxx += dx * dx;
+            sxy += dx * dy;
+            syy += dy * dy;
+            sxz += dx * dz;
+            syz += dy * dz;
+        }
+        let determinant = sxx * syy - sxy * sxy;
+        if determinant.abs() < 1e-12 {
+            // neighbours all in a line, e.g. on a map one cell wide
+            let along_x = if sxx > 0.0 { sxz / sxx } else { 0.0 };
+            let along_y = if syy > 0.0 { syz / syy } else { 0.0 };
+            return (along_x, along_y);
+        }
+        ((syy * sxz - sxy * syz) / determinant, (sxx * syz - sxy * sxz) / determinant)
+    }
+
+    // steepness in degrees, 0 is flat
+    pub fn slope(&self, x: u32, y: u32) -> f64 {
+        let (gx, gy) = self.gradient(x, y);
+        gx.hypot(gy).atan().to_degrees()
+    }
+
+    // compass direction the ground faces (downhill), degrees clockwise from north
+    pub fn aspect(&self, x: u32, y: u32) -> f64 {
+        let (gx, gy) = self.gradient(x, y);
+        if gx == 0.0 && gy == 0.0 {
+            return FLAT_ASPECT;
+        }
+        // downhill is -gradient, north is -y
+        (-gx).atan2(gy).to_degrees().rem_euclid(360.0)
+    }
+
+    // brightness from 0 (in shadow) to 255 (facing the sun)
+    pub fn hillshade(&self, x: u32, y: u32) -> u8 {
+        let (gx, gy) = self.gradient(x, y);
+        let slope = gx.hypot(gy).atan();
+        let zenith = (90.0 - self.relief.altitude).to_radians();
+        let azimuth = self.relief.azimuth.to_radians();
+        let aspect = (-gx).atan2(gy);
+        let shade = zenith.cos() * slope.cos() + zenith.sin() * slope.sin() * (azimuth - aspect).cos();
+        (shade.max(0.0) * 255.0).round() as u8
+    }
+
+    // evaluates layer for every cell of a window, clipped to the map
+    fn relief_region<T: Send>(&self, x: u32, y: u32, width: u32, height: u32, layer: impl Fn(u32, u32) -> T + Sync) -> (u32, u32, Vec<T>) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        let region_width = x_end.saturating_sub(x);
+        let values = (0..(region_width as usize * y_end.saturating_sub(y) as usize)).into_par_iter()
+            .map(|i| layer(x + (i % region_width as usize) as u32, y + (i / region_width as usize) as u32))
+            .collect();
+        (region_width, y_end.saturating_sub(y), values)
+    }
+
+    pub fn slope_region(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32, Vec<f64>) {
+        self.relief_region(x, y, width, height, |cx, cy| self.slope(cx, cy))
+    }
+
+    pub fn aspect_region(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32, Vec<f64>) {
+        self.relief_region(x, y, width, height, |cx, cy| self.aspect(cx, cy))
+    }
+
+    pub fn hillshade_region(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32, Vec<u8>) {
+        self.relief_region(x, y, width, height, |cx, cy| self.hillshade(cx, cy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::materials::GRASS;
+    use crate::core::terrain::MapPoint;
+
+    // 5x5 with the ground rising one unit per cell eastwards
+    fn ramp() -> Terrain {
+        let mut terrain = Terrain::new(5, 5, 10);
+        terrain.map = (0..25).map(|i| MapPoint {height: (i % 5) as u8, material: GRASS}).collect();
+        terrain
+    }
+
+    #[test]
+    fn ramp_faces_downhill_to_the_west() {
+        let mut terrain = ramp();
+        assert!((terrain.slope(2, 2) - 45.0).abs() < 1e-9);
+        assert!((terrain.aspect(2, 2) - 270.0).abs() < 1e-9);
+        terrain.set_relief(Relief {z_factor: 2.0, ..Relief::default()});
+        assert!((terrain.slope(2, 2) - 2f64.atan().to_degrees()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flat_ground_has_no_aspect_and_even_shade() {
+        let mut terrain = ramp();
+        terrain.map.iter_mut().for_each(|point| point.height = 3);
+        assert_eq!(terrain.slope(2, 2), 0.0);
+        assert_eq!(terrain.aspect(2, 2), FLAT_ASPECT);
+        // lit at 45 degrees, flat ground gets cos(45) of full brightness
+        let (_, _, shade) = terrain.hillshade_region(0, 0, 5, 5);
+        assert!(shade.iter().all(|&value| value == 180));
+    }
+
+    #[test]
+    fn slopes_facing_the_sun_are_brighter() {
+        let mut terrain = ramp();
+        // the ramp faces west, so a western sun lights it and an eastern one doesn't
+        terrain.set_relief(Relief {azimuth: 270.0, ..Relief::default()});
+        let lit = terrain.hillshade(2, 2);
+        terrain.set_relief(Relief {azimuth: 90.0, ..Relief::default()});
+        let shaded = terrain.hillshade(2, 2);
+        assert_eq!(lit, 255);
+        assert_eq!(shaded, 0);
+        let (width, height, slopes) = terrain.slope_region(3, 3, 10, 10);
+        assert_eq!((width, height, slopes.len()), (2, 2, 4));
+    }
+}
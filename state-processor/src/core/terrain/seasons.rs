@@ -0,0 +1,165 @@
+// Seasonal changes layered on top of the generated terrain. The stored map is
+// never modified, the effective material is derived from it and the season
+use super::Terrain;
+use crate::core::time_management::Season;
+
+#[derive(Clone, Debug)]
+pub struct SeasonalLayer {
+    // cells colder than this freeze in winter, frozen ground warmer than it thaws
+    pub freeze_temperature: f64,
+    // degrees lost between the lowest and highest possible cell
+    pub lapse_rate: f64,
+    // added to each material's temperature, indexed spring, summer, autumn, winter
+    pub season_offsets: [f64; 4],
+    // food yield multiplier for dormant vegetation, indexed the same way
+    pub food_multipliers: [f64; 4],
+}
+
+impl Default for SeasonalLayer {
+    fn default() -> SeasonalLayer {
+        SeasonalLayer {
+            freeze_temperature: 0.0,
+            lapse_rate: 12.0,
+            season_offsets: [6.0, 12.0, 0.0, -6.0],
+            food_multipliers: [0.8, 1.0, 0.6, 0.2],
+        }
+    }
+}
+
+impl SeasonalLayer {
+    fn season_index(season: Season) -> usize {
+        match season {
+            Season::Spring => 0,
+            Season::Summer => 1,
+            Season::Autumn => 2,
+            Season::Winter => 3,
+        }
+    }
+}
+
+impl Terrain {
+    pub fn set_seasons_enabled(&mut self, enabled: bool) {
+        self.seasons = if enabled { Some(SeasonalLayer::default()) } else { None };
+        self.revision += 1;
+    }
+
+    pub fn seasons_enabled(&self) -> bool {
+        self.seasons.is_some()
+    }
+
+    // called as a season starts. Effective materials change with it, so routes
+    // planned in the old season are out of date
+    pub fn start_season(&mut self) {
+        if self.seasons.is_some() {
+            self.revision += 1;
+        }
+    }
+
+    // temperature of a cell from its material, elevation and the season
+    pub fn cell_temperature(&self, x: u32, y: u32, season: Season) -> f64 {
+        match &self.seasons {
+            Some(layer) => self.layer_temperature(layer, x, y, season),
+            None => self.layer_temperature(&SeasonalLayer::default(), x, y, season),
+        }
+    }
+
+    fn layer_temperature(&self, layer: &SeasonalLayer, x: u32, y: u32, season: Season) -> f64 {
+        let point = self.get_point(x, y);
+        let base = self.materials.get(point.material).map(|def| def.temperature).unwrap_or(0.0);
+        let elevation = point.height as f64 / self.depth.max(1) as f64;
+        base + layer.season_offsets[SeasonalLayer::season_index(season)] - layer.lapse_rate * elevation
+    }
+
+    // ground freezes into ice in winter, and ice thaws into mud through spring
+    // and summer where it is warm enough. Impassable materials never change.
+    // Without seasons this is the stored material
+    pub fn effective_material(&self, x: u32, y: u32, season: Season) -> u8 {
+        let material = self.get_material(x, y);
+        let Some(layer) = &self.seasons else {
+            return material;
+        };
+        if !self.materials.is_passable(material) {
+            return material;
+        }
+        let (Some(ice), Some(mud)) = (self.materials.id_by_name("ice"), self.materials.id_by_name("mud")) else {
+            return material;
+        };
+
+        let temperature = self.layer_temperature(layer, x, y, season);
+        match season {
+            Season::Winter if material != ice && temperature < layer.freeze_temperature => ice,
+            Season::Spring | Season::Summer if material == ice && temperature >= layer.freeze_temperature => mud,
+            _ => material,
+        }
+    }
+
+    pub fn effective_food_yield(&self, x: u32, y: u32, season: Season) -> f64 {
+        let food = self.materials.food_yield(self.effective_material(x, y, season));
+        match &self.seasons {
+            Some(layer) => food * layer.food_multipliers[SeasonalLayer::season_index(season)],
+            None => food,
+        }
+    }
+
+    pub fn effective_water_yield(&self, x: u32, y: u32, season: Season) -> f64 {
+        self.materials.water_yield(self.effective_material(x, y, season))
+    }
+
+    pub fn effective_region(&self, x: u32, y: u32, width: u32, height: u32, season: Season) -> (u32, u32, Vec<u8>) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        let mut materials = Vec::with_capacity((x_end.saturating_sub(x) * y_end.saturating_sub(y)) as usize);
+        for row in y..y_end {
+            for col in x..x_end {
+                materials.push(self.effective_material(col, row, season));
+            }
+        }
+        (x_end.saturating_sub(x), y_end.saturating_sub(y), materials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::materials::{GRASS, ICE, MUD, ROCK};
+    use crate::core::terrain::MapPoint;
+
+    // one cell per (material, height), heights run 0 to 10
+    fn cells(points: &[(u8, u8)]) -> Terrain {
+        let mut terrain = Terrain::new(points.len() as u32, 1, 10);
+        terrain.map = points.iter().map(|&(material, height)| MapPoint {height, material}).collect();
+        terrain
+    }
+
+    #[test]
+    fn high_ground_freezes_in_winter() {
+        let terrain = cells(&[(GRASS, 0), (GRASS, 10), (MUD, 10), (ROCK, 10)]);
+        let winter: Vec<u8> = (0..4).map(|x| terrain.effective_material(x, 0, Season::Winter)).collect();
+        assert_eq!(winter, vec![GRASS, ICE, ICE, ROCK]);
+        for x in 0..4 {
+            assert_eq!(terrain.effective_material(x, 0, Season::Autumn), terrain.get_material(x, 0));
+        }
+    }
+
+    #[test]
+    fn low_ice_thaws_in_spring_and_summer() {
+        let terrain = cells(&[(ICE, 0), (ICE, 5)]);
+        assert_eq!(terrain.effective_material(0, 0, Season::Spring), MUD);
+        assert_eq!(terrain.effective_material(0, 0, Season::Winter), ICE);
+        // higher up stays below freezing until summer
+        assert_eq!(terrain.effective_material(1, 0, Season::Spring), ICE);
+        assert_eq!(terrain.effective_material(1, 0, Season::Summer), MUD);
+    }
+
+    #[test]
+    fn disabling_seasons_uses_the_stored_map() {
+        let mut terrain = cells(&[(GRASS, 10)]);
+        assert_eq!(terrain.effective_food_yield(0, 0, Season::Summer), 1.0);
+        assert!(terrain.effective_food_yield(0, 0, Season::Winter) < 0.5);
+        let revision = terrain.revision();
+        terrain.set_seasons_enabled(false);
+        assert!(terrain.revision() > revision);
+        assert_eq!(terrain.effective_material(0, 0, Season::Winter), GRASS);
+        assert_eq!(terrain.effective_food_yield(0, 0, Season::Winter), 1.0);
+    }
+}
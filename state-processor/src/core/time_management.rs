@@ -1,13 +1,102 @@
 use pyo3::prelude::*;
+use std::f64::consts::TAU;
+
+const DEFAULT_TICKS_PER_DAY: u32 = 4;
+const DEFAULT_DAYS_PER_SEASON: u32 = 10;
+const DEFAULT_SEASONS_PER_YEAR: u32 = 4;
+// ambient light at midnight, midday is 1
+const MIN_AMBIENT_LIGHT: f64 = 0.2;
+// sun height below the horizon (as a fraction of its peak) where twilight begins
+const TWILIGHT: f64 = 0.2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Season::Spring => "spring",
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+            Season::Winter => "winter",
+        }
+    }
+
+    // years with more or fewer than four seasons spread these evenly over the year
+    fn from_position(index: u32, seasons_per_year: u32) -> Season {
+        match index * 4 / seasons_per_year {
+            0 => Season::Spring,
+            1 => Season::Summer,
+            2 => Season::Autumn,
+            _ => Season::Winter,
+        }
+    }
+}
+
+// Ticks runs every system once per tick. Events runs movement, exposure and
+// conception as continuous-time events with exponential waiting times, see
+// Pipeline::run_events, while the rest still run per tick
+#[pyclass(eq, eq_int)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeMode {
+    Ticks,
+    Events,
+}
+
+impl TimeMode {
+    pub fn from_name(name: &str) -> Option<TimeMode> {
+        match name {
+            "ticks" => Some(TimeMode::Ticks),
+            "events" => Some(TimeMode::Events),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimeMode::Ticks => "ticks",
+            TimeMode::Events => "events",
+        }
+    }
+}
+
+// a point in calendar time, counted from zero. day is the day of the season
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Date {
+    pub year: u32,
+    pub season: Season,
+    pub day: u32,
+    pub tick_of_day: u32,
+}
 
 #[pyclass]
+#[derive(Clone)]
 pub struct TimeMgmt {
     time: u32,
+    ticks_per_day: u32,
+    days_per_season: u32,
+    seasons_per_year: u32,
+    mode: TimeMode,
+    // continuous time in event mode, e.g. 41.37 lies within tick 42. It is
+    // at the latest event during a tick and at the tick's end after it
+    clock: f64,
 }
 
 impl TimeMgmt {
     pub fn new(start_time: u32) -> TimeMgmt {
-        TimeMgmt {time: start_time}
+        TimeMgmt {
+            time: start_time,
+            ticks_per_day: DEFAULT_TICKS_PER_DAY,
+            days_per_season: DEFAULT_DAYS_PER_SEASON,
+            seasons_per_year: DEFAULT_SEASONS_PER_YEAR,
+            mode: TimeMode::Ticks,
+            clock: start_time as f64,
+        }
     }
 
     pub fn update(&mut self) -> u32 {
@@ -17,5 +106,134 @@ impl TimeMgmt {
 
     pub fn reset(&mut self) {
         self.time = 0;
+        self.clock = 0.0;
+    }
+
+    pub fn get_time(&self) -> u32 {
+        self.time
+    }
+
+    pub fn get_mode(&self) -> TimeMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: TimeMode) {
+        self.mode = mode;
+    }
+
+    // the continuous time in event mode, the current tick otherwise
+    pub fn get_clock(&self) -> f64 {
+        match self.mode {
+            TimeMode::Ticks => self.time as f64,
+            TimeMode::Events => self.clock,
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: f64) {
+        self.clock = clock;
+    }
+
+    // all values must be at least 1, and a year can't be longer than u32::MAX ticks
+    pub fn set_calendar(&mut self, ticks_per_day: u32, days_per_season: u32, seasons_per_year: u32) -> Result<(), String> {
+        if ticks_per_day == 0 || days_per_season == 0 || seasons_per_year == 0 {
+            return Err("calendar values must be at least 1".to_string());
+        }
+        if ticks_per_day.checked_mul(days_per_season).and_then(|ticks| ticks.checked_mul(seasons_per_year)).is_none() {
+            return Err("a year of the calendar has too many ticks to count".to_string());
+        }
+        self.ticks_per_day = ticks_per_day;
+        self.days_per_season = days_per_season;
+        self.seasons_per_year = seasons_per_year;
+        Ok(())
+    }
+
+    pub fn get_calendar(&self) -> (u32, u32, u32) {
+        (self.ticks_per_day, self.days_per_season, self.seasons_per_year)
+    }
+
+    pub fn ticks_per_season(&self) -> u32 {
+        self.ticks_per_day * self.days_per_season
+    }
+
+    pub fn ticks_per_year(&self) -> u32 {
+        self.ticks_per_season() * self.seasons_per_year
+    }
+
+    // the year starts in spring at tick 0
+    pub fn date_at(&self, tick: u32) -> Date {
+        let tick_of_year = tick % self.ticks_per_year();
+        let season_index = tick_of_year / self.ticks_per_season();
+        Date {
+            year: tick / self.ticks_per_year(),
+            season: Season::from_position(season_index, self.seasons_per_year),
+            day: (tick_of_year % self.ticks_per_season()) / self.ticks_per_day,
+            tick_of_day: tick % self.ticks_per_day,
+        }
+    }
+
+    pub fn get_date(&self) -> Date {
+        self.date_at(self.time)
+    }
+
+    // fraction of the day from 0 (midnight) to 1, taken at the middle of the
+    // tick so a single tick per day always falls at midday
+    pub fn time_of_day(&self, tick: u32) -> f64 {
+        ((tick % self.ticks_per_day) as f64 + 0.5) / self.ticks_per_day as f64
+    }
+
+    // 0 through the night, rising through twilight to 1 at midday
+    pub fn daylight(&self, tick: u32) -> f64 {
+        let sun_height = -(self.time_of_day(tick) * TAU).cos();
+        ((sun_height + TWILIGHT) / (1.0 + TWILIGHT)).clamp(0.0, 1.0)
+    }
+
+    // brightness for the renderer, never fully dark
+    pub fn ambient_light(&self, tick: u32) -> f64 {
+        MIN_AMBIENT_LIGHT + (1.0 - MIN_AMBIENT_LIGHT) * self.daylight(tick)
+    }
+
+    pub fn season_at(&self, tick: u32) -> Season {
+        self.date_at(tick).season
+    }
+
+    pub fn get_season(&self) -> Season {
+        self.season_at(self.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_roll_over_into_the_next_year() {
+        let time = TimeMgmt::new(0);
+        assert_eq!(time.ticks_per_year(), 160);
+        assert_eq!(time.date_at(159), Date {year: 0, season: Season::Winter, day: 9, tick_of_day: 3});
+        assert_eq!(time.date_at(160), Date {year: 1, season: Season::Spring, day: 0, tick_of_day: 0});
+        assert_eq!(time.season_at(40), Season::Summer);
+    }
+
+    #[test]
+    fn short_years_spread_the_seasons() {
+        let mut time = TimeMgmt::new(0);
+        time.set_calendar(1, 1, 2).unwrap();
+        assert_eq!([0, 1, 2].map(|tick| time.season_at(tick)), [Season::Spring, Season::Autumn, Season::Spring]);
+        assert!(time.set_calendar(0, 1, 1).is_err());
+        assert!(time.set_calendar(u32::MAX, 2, 1).is_err());
+        assert_eq!(time.get_calendar(), (1, 1, 2));
+    }
+
+    #[test]
+    fn daylight_peaks_at_midday_and_is_dark_at_night() {
+        let mut time = TimeMgmt::new(0);
+        // four ticks a day, centred at 3am, 9am, 3pm and 9pm
+        assert_eq!(time.daylight(0), 0.0);
+        assert_eq!(time.daylight(3), 0.0);
+        assert!(time.daylight(1) > 0.5);
+        assert!((time.daylight(1) - time.daylight(2)).abs() < 1e-12);
+        assert_eq!(time.ambient_light(0), MIN_AMBIENT_LIGHT);
+        time.set_calendar(1, 10, 4).unwrap();
+        assert!((time.daylight(7) - 1.0).abs() < 1e-12);
     }
 }
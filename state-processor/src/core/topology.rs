@@ -0,0 +1,235 @@
+// Grid layout of the world. Cells are always stored row-major as (x, y), for
+// hex grids that is the "odd-r" offset layout (pointy-top hexes with odd rows
+// shifted half a cell right), which converts to axial (q, r) coordinates
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const SQUARE_NEIGHBOURS: [(i64, i64); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const HEX_AXIAL_NEIGHBOURS: [(i64, i64); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+const HEX_ROW_HEIGHT: f64 = 0.8660254037844386; // sqrt(3) / 2, with unit spacing between centres
+
+#[pyclass(eq, eq_int)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    #[default]
+    Square,
+    Hex,
+}
+
+impl Topology {
+    pub fn from_name(name: &str) -> Option<Topology> {
+        match name {
+            "square" => Some(Topology::Square),
+            "hex" => Some(Topology::Hex),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topology::Square => "square",
+            Topology::Hex => "hex",
+        }
+    }
+
+    pub fn offset_to_axial(x: i64, y: i64) -> (i64, i64) {
+        (x - (y - (y & 1)) / 2, y)
+    }
+
+    pub fn axial_to_offset(q: i64, r: i64) -> (i64, i64) {
+        (q + (r - (r & 1)) / 2, r)
+    }
+
+    // rounds fractional axial coordinates to the containing hex
+    fn axial_round(q: f64, r: f64) -> (i64, i64) {
+        let s = -q - r;
+        let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+        let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+        if dq > dr && dq > ds {
+            rq = -rr - rs;
+        } else if dr > ds {
+            rr = -rq - rs;
+        }
+        (rq as i64, rr as i64)
+    }
+
+    // centre of a cell in world space, where neighbouring centres are 1 apart
+    pub fn world_position(&self, x: u32, y: u32) -> (f64, f64) {
+        self.cell_centre(x as i64, y as i64)
+    }
+
+    // world_position for cells that may lie off the map
+    pub fn cell_centre(&self, x: i64, y: i64) -> (f64, f64) {
+        match self {
+            Topology::Square => (x as f64, y as f64),
+            Topology::Hex => (x as f64 + 0.5 * (y & 1) as f64, y as f64 * HEX_ROW_HEIGHT),
+        }
+    }
+
+    // cell whose centre is closest to a point in world space, may lie off the map
+    pub fn cell_at(&self, position: (f64, f64)) -> (i64, i64) {
+        match self {
+            Topology::Square => (position.0.round() as i64, position.1.round() as i64),
+            Topology::Hex => {
+                let r = position.1 / HEX_ROW_HEIGHT;
+                let q = position.0 - r / 2.0;
+                let (q, r) = Self::axial_round(q, r);
+                Self::axial_to_offset(q, r)
+            },
+        }
+    }
+
+    // adjacent cells, not clipped to the map
+    pub fn neighbours(&self, x: i64, y: i64) -> Vec<(i64, i64)> {
+        match self {
+            Topology::Square => SQUARE_NEIGHBOURS.iter().map(|(dx, dy)| (x + dx, y + dy)).collect(),
+            Topology::Hex => {
+                let (q, r) = Self::offset_to_axial(x, y);
+                HEX_AXIAL_NEIGHBOURS.iter().map(|(dq, dr)| Self::axial_to_offset(q + dq, r + dr)).collect()
+            },
+        }
+    }
+
+    // straight line distance for square grids, steps between hexes for hex grids
+    pub fn distance(&self, a: (i64, i64), b: (i64, i64)) -> f64 {
+        match self {
+            Topology::Square => {
+                let (dx, dy) = ((b.0 - a.0) as f64, (b.1 - a.1) as f64);
+                (dx * dx + dy * dy).sqrt()
+            },
+            Topology::Hex => {
+                let (aq, ar) = Self::offset_to_axial(a.0, a.1);
+                let (bq, br) = Self::offset_to_axial(b.0, b.1);
+                let (dq, dr) = (aq - bq, ar - br);
+                ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as f64
+            },
+        }
+    }
+
+    // unit steps along the straight line from a to b. Square grids step in offset
+    // coordinates (diagonals included), hex grids in axial directions, see step
+    pub fn line_steps(&self, from: (i64, i64), to: (i64, i64)) -> Vec<(i64, i64)> {
+        let mut steps = vec![];
+        match self {
+            Topology::Square => {
+                // Bresenham's line algorithm
+                let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+                let (sx, sy) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+                let mut error = dx + dy;
+                let mut current = from;
+                while current != to {
+                    let mut step = (0, 0);
+                    let doubled = 2 * error;
+                    if doubled >= dy {
+                        error += dy;
+                        step.0 = sx;
+                    }
+                    if doubled <= dx {
+                        error += dx;
+                        step.1 = sy;
+                    }
+                    current = (current.0 + step.0, current.1 + step.1);
+                    steps.push(step);
+                }
+            },
+            Topology::Hex => {
+                let (aq, ar) = Self::offset_to_axial(from.0, from.1);
+                let (bq, br) = Self::offset_to_axial(to.0, to.1);
+                let count = self.distance(from, to) as i64;
+                let mut previous = (aq, ar);
+                for i in 1..=count {
+                    let t = i as f64 / count as f64;
+                    // nudged so points exactly between two hexes always round the same way
+                    let q = aq as f64 + (bq - aq) as f64 * t + 1e-6;
+                    let r = ar as f64 + (br - ar) as f64 * t + 2e-6;
+                    let cell = Self::axial_round(q, r);
+                    steps.push((cell.0 - previous.0, cell.1 - previous.1));
+                    previous = cell;
+                }
+            },
+        }
+        steps
+    }
+
+    // applies a step from line_steps or slide_steps to a cell
+    pub fn step(&self, from: (i64, i64), direction: (i64, i64)) -> (i64, i64) {
+        match self {
+            Topology::Square => (from.0 + direction.0, from.1 + direction.1),
+            Topology::Hex => {
+                let (q, r) = Self::offset_to_axial(from.0, from.1);
+                Self::axial_to_offset(q + direction.0, r + direction.1)
+            },
+        }
+    }
+
+    // steps to try instead of the given one, used to slide along an obstacle.
+    // Diagonals split into their axes, which still go part of the way. Axis
+    // aligned steps go sideways, perpendicular to travel, making no progress.
+    // Hex steps turn 60 degrees either way
+    pub fn slide_steps(&self, direction: (i64, i64)) -> Vec<(i64, i64)> {
+        match self {
+            Topology::Square if direction.0 != 0 && direction.1 != 0 => vec![(direction.0, 0), (0, direction.1)],
+            Topology::Square if direction.0 != 0 => vec![(0, 1), (0, -1)],
+            Topology::Square if direction.1 != 0 => vec![(1, 0), (-1, 0)],
+            Topology::Square => vec![],
+            Topology::Hex => match HEX_AXIAL_NEIGHBOURS.iter().position(|d| *d == direction) {
+                Some(i) => vec![HEX_AXIAL_NEIGHBOURS[(i + 5) % 6], HEX_AXIAL_NEIGHBOURS[(i + 1) % 6]],
+                None => vec![],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut cells: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn hex_neighbours_follow_the_odd_r_layout() {
+        let hex = Topology::Hex;
+        assert_eq!(sorted(hex.neighbours(2, 2)), vec![(1, 1), (1, 2), (1, 3), (2, 1), (2, 3), (3, 2)]);
+        // odd rows are shifted right, so their diagonal neighbours are too
+        assert_eq!(sorted(hex.neighbours(2, 3)), vec![(1, 3), (2, 2), (2, 4), (3, 2), (3, 3), (3, 4)]);
+        for y in 0..4 {
+            for (nx, ny) in hex.neighbours(2, y) {
+                assert_eq!(hex.distance((2, y), (nx, ny)), 1.0);
+                let (a, b) = (hex.cell_centre(2, y), hex.cell_centre(nx, ny));
+                assert!(((a.0 - b.0).hypot(a.1 - b.1) - 1.0).abs() < 1e-9, "({}, {}) isn't 1 from (2, {})", nx, ny, y);
+            }
+        }
+    }
+
+    #[test]
+    fn cell_at_inverts_cell_centre() {
+        for topology in [Topology::Square, Topology::Hex] {
+            for (x, y) in [(0, 0), (3, 1), (-2, 5), (7, -3)] {
+                let (cx, cy) = topology.cell_centre(x, y);
+                assert_eq!(topology.cell_at((cx + 0.2, cy - 0.2)), (x, y), "{} cell", topology.name());
+            }
+        }
+    }
+
+    #[test]
+    fn line_steps_reach_the_target_one_cell_at_a_time() {
+        for topology in [Topology::Square, Topology::Hex] {
+            let (from, to) = ((1, 2), (8, -3));
+            let steps = topology.line_steps(from, to);
+            let end = steps.iter().fold(from, |cell, step| {
+                let next = topology.step(cell, *step);
+                // square lines also step diagonally, which aren't neighbours
+                if topology == Topology::Hex {
+                    assert!(topology.neighbours(cell.0, cell.1).contains(&next));
+                }
+                next
+            });
+            assert_eq!(end, to, "{} line", topology.name());
+        }
+        assert_eq!(Topology::Hex.line_steps((0, 0), (4, 0)).len(), 4);
+    }
+}
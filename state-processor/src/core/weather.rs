@@ -0,0 +1,245 @@
+// Weather fronts drifting across the map. Each front is a circular field whose
+// intensity falls off towards its edge, entities beneath one move slower, get
+// hungrier and can die of exposure
+use crate::core::replay::Checksum;
+use crate::core::time_management::Season;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+const FRONT_SPAWN_CHANCE: f64 = 0.15;
+const MAX_FRONTS: usize = 4;
+// as fractions of the smaller map side
+const MIN_FRONT_RADIUS: f64 = 0.05;
+const MAX_FRONT_RADIUS: f64 = 0.2;
+const MAX_FRONT_SPEED: f64 = 0.02;
+const MIN_FRONT_TICKS: u32 = 20;
+const MAX_FRONT_TICKS: u32 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeatherKind {
+    Clear,
+    Rain,
+    Snow,
+    Heat,
+}
+
+// multipliers at full intensity, scaled towards 1 (no effect) with intensity
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeatherEffects {
+    pub speed: f64,
+    pub hunger: f64,
+    // chance per tick of dying from exposure
+    pub death_chance: f64,
+}
+
+impl WeatherKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeatherKind::Clear => "clear",
+            WeatherKind::Rain => "rain",
+            WeatherKind::Snow => "snow",
+            WeatherKind::Heat => "heat",
+        }
+    }
+
+    // raster value for this kind, 0 is clear
+    pub fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    fn full_effects(&self) -> WeatherEffects {
+        match self {
+            WeatherKind::Clear => WeatherEffects {speed: 1.0, hunger: 1.0, death_chance: 0.0},
+            WeatherKind::Rain => WeatherEffects {speed: 0.8, hunger: 1.1, death_chance: 0.0},
+            WeatherKind::Snow => WeatherEffects {speed: 0.5, hunger: 1.5, death_chance: 0.01},
+            WeatherKind::Heat => WeatherEffects {speed: 0.9, hunger: 1.3, death_chance: 0.005},
+        }
+    }
+
+    pub fn effects(&self, intensity: f64) -> WeatherEffects {
+        let full = self.full_effects();
+        let scale = |value: f64| 1.0 + (value - 1.0) * intensity;
+        WeatherEffects {speed: scale(full.speed), hunger: scale(full.hunger), death_chance: full.death_chance * intensity}
+    }
+
+    // which kind of front forms depends on the time of year
+    fn for_season(season: Season, roll: f64) -> WeatherKind {
+        match season {
+            Season::Winter if roll < 0.7 => WeatherKind::Snow,
+            Season::Summer if roll < 0.6 => WeatherKind::Heat,
+            _ => WeatherKind::Rain,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Front {
+    pub kind: WeatherKind,
+    pub centre: (f64, f64),
+    // cells per tick
+    pub velocity: (f64, f64),
+    pub radius: f64,
+    // strength at the centre, from 0 to 1
+    pub intensity: f64,
+    pub remaining_ticks: u32,
+}
+
+impl Front {
+    // strength at a cell, falling off linearly to 0 at the radius
+    fn intensity_at(&self, x: f64, y: f64) -> f64 {
+        let distance = (x - self.centre.0).hypot(y - self.centre.1);
+        if distance >= self.radius {
+            return 0.0;
+        }
+        self.intensity * (1.0 - distance / self.radius)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Weather {
+    dims: (u32, u32),
+    fronts: Vec<Front>,
+    enabled: bool,
+    rng: StdRng,
+}
+
+impl Weather {
+    pub fn new(dims: (u32, u32), seed: u64) -> Weather {
+        Weather {dims, fronts: vec![], enabled: true, rng: StdRng::seed_from_u64(seed)}
+    }
+
+    pub fn reset(&mut self) {
+        self.fronts.clear();
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.fronts.clear();
+        }
+    }
+
+    pub fn fronts(&self) -> &[Front] {
+        &self.fronts
+    }
+
+    // every front in order, with its kind as its raster code
+    pub fn write_checksum(&self, sum: &mut Checksum) {
+        sum.write(&[self.enabled as u8]);
+        sum.write(&(self.fronts.len() as u32).to_le_bytes());
+        for front in &self.fronts {
+            sum.write(&[front.kind.code()]);
+            for value in [front.centre.0, front.centre.1, front.velocity.0, front.velocity.1, front.radius, front.intensity] {
+                sum.write(&value.to_le_bytes());
+            }
+            sum.write(&front.remaining_ticks.to_le_bytes());
+        }
+    }
+
+    // moves fronts on by a tick, drops expired ones and sometimes forms a new one
+    pub fn update(&mut self, season: Season) {
+        if !self.enabled {
+            return;
+        }
+        for front in &mut self.fronts {
+            front.centre = (front.centre.0 + front.velocity.0, front.centre.1 + front.velocity.1);
+            front.remaining_ticks = front.remaining_ticks.saturating_sub(1);
+        }
+        self.fronts.retain(|front| front.remaining_ticks > 0);
+
+        let rng = &mut self.rng;
+        if self.fronts.len() < MAX_FRONTS && rng.random_bool(FRONT_SPAWN_CHANCE) {
+            let side = self.dims.0.min(self.dims.1).max(1) as f64;
+            let heading = rng.random_range(0.0..std::f64::consts::TAU);
+            let speed = rng.random_range(0.0..=MAX_FRONT_SPEED) * side;
+            self.fronts.push(Front {
+                kind: WeatherKind::for_season(season, rng.random()),
+                centre: (rng.random_range(0.0..=self.dims.0 as f64), rng.random_range(0.0..=self.dims.1 as f64)),
+                velocity: (heading.cos() * speed, heading.sin() * speed),
+                radius: rng.random_range(MIN_FRONT_RADIUS..=MAX_FRONT_RADIUS) * side,
+                intensity: rng.random_range(0.5..=1.0),
+                remaining_ticks: rng.random_range(MIN_FRONT_TICKS..=MAX_FRONT_TICKS),
+            });
+        }
+    }
+
+    // strongest weather over a cell, and its intensity
+    pub fn weather_at(&self, x: u32, y: u32) -> (WeatherKind, f64) {
+        let (cx, cy) = (x as f64, y as f64);
+        self.fronts.iter()
+            .map(|front| (front.kind, front.intensity_at(cx, cy)))
+            .filter(|(_, intensity)| *intensity > 0.0)
+            .fold((WeatherKind::Clear, 0.0), |best, current| if current.1 > best.1 { current } else { best })
+    }
+
+    pub fn effects_at(&self, x: u32, y: u32) -> WeatherEffects {
+        let (kind, intensity) = self.weather_at(x, y);
+        kind.effects(intensity)
+    }
+
+    // row-major (width, height, kind codes, intensities) for a window of the map
+    pub fn raster(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32, Vec<u8>, Vec<f32>) {
+        let x_end = x.saturating_add(width).min(self.dims.0);
+        let y_end = y.saturating_add(height).min(self.dims.1);
+        let cells = x_end.saturating_sub(x) as usize * y_end.saturating_sub(y) as usize;
+        let mut kinds = Vec::with_capacity(cells);
+        let mut intensities = Vec::with_capacity(cells);
+        for row in y..y_end {
+            for col in x..x_end {
+                let (kind, intensity) = self.weather_at(col, row);
+                kinds.push(kind.code());
+                intensities.push(intensity as f32);
+            }
+        }
+        (x_end.saturating_sub(x), y_end.saturating_sub(y), kinds, intensities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn front(kind: WeatherKind, centre: (f64, f64), intensity: f64) -> Front {
+        Front {kind, centre, velocity: (1.0, 0.0), radius: 4.0, intensity, remaining_ticks: 2}
+    }
+
+    #[test]
+    fn strongest_front_sets_the_weather() {
+        let mut weather = Weather::new((20, 10), 1);
+        weather.fronts = vec![front(WeatherKind::Rain, (5.0, 5.0), 1.0), front(WeatherKind::Snow, (9.0, 5.0), 1.0)];
+        assert_eq!(weather.weather_at(5, 5), (WeatherKind::Rain, 1.0));
+        assert_eq!(weather.weather_at(7, 5), (WeatherKind::Rain, 0.5));
+        assert_eq!(weather.weather_at(8, 5).0, WeatherKind::Snow);
+        assert_eq!(weather.weather_at(15, 5), (WeatherKind::Clear, 0.0));
+        // half intensity gives half the slowdown
+        assert_eq!(weather.effects_at(7, 5).speed, 0.9);
+        let (width, height, kinds, _) = weather.raster(18, 8, 5, 5);
+        assert_eq!((width, height, kinds), (2, 2, vec![0; 4]));
+    }
+
+    #[test]
+    fn fronts_drift_and_expire() {
+        let mut weather = Weather::new((20, 10), 1);
+        let lasting = Front {remaining_ticks: 3, ..front(WeatherKind::Heat, (5.0, 5.0), 1.0)};
+        weather.fronts = vec![Front {remaining_ticks: 1, ..lasting.clone()}, lasting];
+        // winter never forms heat, so only the fronts above are heat
+        weather.update(Season::Winter);
+        let heat: Vec<&Front> = weather.fronts.iter().filter(|front| front.kind == WeatherKind::Heat).collect();
+        assert_eq!(heat.len(), 1);
+        assert_eq!((heat[0].centre, heat[0].remaining_ticks), ((6.0, 5.0), 2));
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_weather() {
+        let (mut a, mut b) = (Weather::new((50, 50), 9), Weather::new((50, 50), 9));
+        for _ in 0..100 {
+            a.update(Season::Winter);
+            b.update(Season::Winter);
+        }
+        assert!(!a.fronts.is_empty());
+        assert_eq!(a.raster(0, 0, 50, 50), b.raster(0, 0, 50, 50));
+        a.set_enabled(false);
+        a.update(Season::Winter);
+        assert!(a.fronts.is_empty());
+    }
+}
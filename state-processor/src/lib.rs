@@ -1,22 +1,106 @@
 // Exposes function wrappers for creating python lib
 mod core;
 
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 
+// terrain_config is either a preset name ("default", "islands", "mountains",
+// "plains") or a JSON noise graph, see get_terrain_config for the format.
+// topology ("square" or "hex") overrides the one in the config
+fn parse_terrain_config(terrain_config: Option<String>, topology: Option<String>) -> PyResult<Option<core::TerrainConfig>> {
+    let config = match terrain_config {
+        Some(text) => Some(core::TerrainConfig::parse(&text).map_err(PyValueError::new_err)?),
+        None => None,
+    };
+    match topology {
+        Some(name) => {
+            let topology = core::Topology::from_name(&name)
+                .ok_or_else(|| PyValueError::new_err(format!("unknown topology '{}'", name)))?;
+            Ok(Some(core::TerrainConfig {topology, ..config.unwrap_or_default()}))
+        },
+        None => Ok(config),
+    }
+}
+
+// material_config is a JSON list of material definitions, see
+// get_material_config for the defaults
+fn parse_material_config(material_config: Option<String>) -> PyResult<Option<core::MaterialRegistry>> {
+    match material_config {
+        Some(text) => core::MaterialRegistry::from_json(&text).map(Some).map_err(PyValueError::new_err),
+        None => Ok(None),
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (width, height, depth, terrain_config=None, material_config=None, topology=None))]
+fn run_terrain_gen(width: u16, height: u16, depth: u8, terrain_config: Option<String>, material_config: Option<String>, topology: Option<String>) -> PyResult<core::Terrain> {
+    let config = parse_terrain_config(terrain_config, topology)?;
+    let materials = parse_material_config(material_config)?;
+    Ok(core::generate_terrain((width, height, depth), None, config, materials))
+}
+
 #[pyfunction]
-fn run_terrain_gen(width: u16, height: u16, depth: u8) -> core::Terrain{
-    core::generate_terrain((width, height, depth), None)
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (width, height, depth, seed=None, chunk_size=None, memory_budget_mb=None, terrain_config=None, material_config=None, topology=None))]
+fn run_chunked_terrain_gen(width: u32, height: u32, depth: u8, seed: Option<u32>, chunk_size: Option<u32>, memory_budget_mb: Option<u32>, terrain_config: Option<String>, material_config: Option<String>, topology: Option<String>) -> PyResult<core::Terrain> {
+    let config = parse_terrain_config(terrain_config, topology)?;
+    let materials = parse_material_config(material_config)?;
+    Ok(core::generate_chunked_terrain((width, height, depth), seed, chunk_size, memory_budget_mb, config, materials))
 }
 
+// the same seed, configs and inputs always give the same run, see get_replay
 #[pyfunction] 
-fn generate_game_state(map_size: (u16, u16, u8), spawn_zone: (u16, u16, u16, u16), num_entities: Option<u16>) -> core::GameState {
-    core::generate_game_state(map_size, spawn_zone, num_entities)
+#[pyo3(signature = (map_size, spawn_zone, num_entities=None, terrain_config=None, material_config=None, topology=None, seed=None))]
+fn generate_game_state(map_size: (u16, u16, u8), spawn_zone: (u32, u32, u32, u32), num_entities: Option<u32>, terrain_config: Option<String>, material_config: Option<String>, topology: Option<String>, seed: Option<u32>) -> PyResult<core::GameState> {
+    let config = parse_terrain_config(terrain_config, topology)?;
+    let materials = parse_material_config(material_config)?;
+    Ok(core::generate_game_state(map_size, spawn_zone, num_entities, seed, config, materials))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (map_size, spawn_zone, num_entities=None, chunk_size=None, memory_budget_mb=None, terrain_config=None, material_config=None, topology=None, seed=None))]
+fn generate_chunked_game_state(map_size: (u32, u32, u8), spawn_zone: (u32, u32, u32, u32), num_entities: Option<u32>, chunk_size: Option<u32>, memory_budget_mb: Option<u32>, terrain_config: Option<String>, material_config: Option<String>, topology: Option<String>, seed: Option<u32>) -> PyResult<core::GameState> {
+    let config = parse_terrain_config(terrain_config, topology)?;
+    let materials = parse_material_config(material_config)?;
+    Ok(core::generate_chunked_game_state(map_size, spawn_zone, num_entities, seed, chunk_size, memory_budget_mb, config, materials))
+}
+
+// replays JSON from GameState.get_replay. Returns the final state and the tick
+// at which it first stopped matching the recording, or None if it never did
+#[pyfunction]
+fn replay_game_state(replay_log: String) -> PyResult<(core::GameState, Option<u32>)> {
+    let log = core::ReplayLog::from_json(&replay_log).map_err(PyValueError::new_err)?;
+    core::GameState::replay(&log).map_err(PyValueError::new_err)
+}
+
+// returns the JSON for a preset, as a starting point for custom configs
+#[pyfunction]
+#[pyo3(signature = (preset=None))]
+fn get_terrain_config(preset: Option<String>) -> PyResult<String> {
+    let name = preset.unwrap_or("default".to_string());
+    match core::TerrainConfig::preset(&name) {
+        Some(config) => Ok(config.to_json()),
+        None => Err(PyValueError::new_err(format!("unknown terrain preset '{}'", name))),
+    }
+}
+
+#[pyfunction]
+fn get_material_config() -> String {
+    core::MaterialRegistry::default().to_json()
 }
 
 
 #[pymodule]
 fn state_processor(m: &Bound<'_, PyModule>) -> PyResult<()> {
     let _ = m.add_function(wrap_pyfunction!(run_terrain_gen, m)?);
+    let _ = m.add_function(wrap_pyfunction!(run_chunked_terrain_gen, m)?);
     let _ = m.add_function(wrap_pyfunction!(generate_game_state, m)?);
+    let _ = m.add_function(wrap_pyfunction!(generate_chunked_game_state, m)?);
+    let _ = m.add_function(wrap_pyfunction!(replay_game_state, m)?);
+    let _ = m.add_function(wrap_pyfunction!(get_terrain_config, m)?);
+    let _ = m.add_function(wrap_pyfunction!(get_material_config, m)?);
+    let _ = m.add_class::<core::SimRunner>();
+    let _ = m.add_class::<core::Snapshot>();
     Ok(())
 }
@@ -3,6 +3,7 @@ mod core;
 
 use more_asserts::assert_lt;
 use std::env;
+use std::time::Instant;
 
 
 fn validate_and_run_terrain_gen(args: &[String]) {
@@ -11,13 +12,49 @@ fn validate_and_run_terrain_gen(args: &[String]) {
     assert_lt!(args.len(), 4);                            // Shouldn't have nonsense
     println!("{}", args.len());
     if args.len() == 2 {
-        core::generate_terrain((100, 100, 20), None);
+        core::generate_terrain((100, 100, 20), None, None, None);
 
+    } else {
+        let config = core::TerrainConfig::parse(&args[2]).expect("Failed to parse terrain config");
+        core::generate_terrain((100, 100, 20), None, Some(config), None);
+    }
+}
+
+// samples a few points spread across a map far larger than fits in memory,
+// then runs entities on a chunked map
+fn run_chunked_terrain_gen(_args: &[String]) {
+    let terrain = core::generate_chunked_terrain((1_000_000, 1_000_000, 20), Some(0), None, Some(64), None, None);
+    for i in 0..10u32 {
+        let (x, y) = (i * 99_999, i * 99_999);
+        println!("Point ({}, {}) has height {} and material {}", x, y, terrain.get_height(x, y), terrain.get_material(x, y));
+    }
+    println!("{}", terrain);
+
+    let mut gs = core::generate_chunked_game_state((1_000_000, 1_000_000, 20), (0, 0, 1000, 1000), Some(5), Some(0), None, Some(64), None, None);
+    gs.apply(core::Input::Advance {steps: None}).expect("advancing failed");
+    println!("{}", gs.terrain_map);
+}
+
+// times sequential and parallel generation of the same seed, and checks the
+// two maps are identical
+fn run_terrain_benchmark(_args: &[String]) {
+    for size in [800u32, 4096] {
+        let mut timings = vec![];
+        let mut maps = vec![];
+        for parallel in [false, true] {
+            let mut terrain = core::Terrain::with_config(size, size, 20, core::TerrainConfig::default(), core::MaterialRegistry::default());
+            let start = Instant::now();
+            terrain.initialise_terrain_with(0, parallel);
+            timings.push(start.elapsed());
+            maps.push(terrain.map);
+        }
+        assert!(maps[0] == maps[1], "parallel generation differs from sequential");
+        println!("{}x{}: sequential {:?}, parallel {:?}, identical output", size, size, timings[0], timings[1]);
     }
 }
 
 fn run_entity_gen(_args: &[String]) {
-    let mut mgmt = core::EntityMgmt::new((0,0,100,100), (800, 800));
+    let mut mgmt = core::EntityMgmt::new((0,0,100,100), (800, 800), 0);
     mgmt.generate_random_entities(5, None, None);
     let entity_locs = mgmt.get_all_entity_locs();
     for (id, loc) in &entity_locs {
@@ -27,11 +64,11 @@ fn run_entity_gen(_args: &[String]) {
 }
 
 fn advance_game_state(args: &[String]) {
-    let mut gs = core::generate_game_state((800, 800, 10), (200, 200, 400, 400), None);
+    let mut gs = core::generate_game_state((800, 800, 10), (200, 200, 400, 400), None, None, None, None);
     let iterations: u16 = args[2].parse().expect("Failed to parse string to integer");
     for i in 0..iterations {
         println!("Iteration number: {:#?}", i);
-        gs.advance_state();
+        gs.apply(core::Input::Advance {steps: None}).expect("advancing failed");
         let entity_locs = gs.entity_mgmt.get_all_entity_locs();
         for (id, loc) in entity_locs {
             println!("Entity ID: {}, is at {:#?}", id, loc);
@@ -39,6 +76,68 @@ fn advance_game_state(args: &[String]) {
     }
 }
 
+// record <ticks> <path> [seed], runs a game with a few edits and saves its replay log
+fn record_replay(args: &[String]) {
+    let ticks: u32 = args[2].parse().expect("Failed to parse string to integer");
+    let seed = args.get(4).map(|seed| seed.parse().expect("Failed to parse seed"));
+    let mut gs = core::generate_game_state((200, 200, 10), (50, 50, 150, 150), Some(20), seed, None, None);
+    let inputs = [
+        core::Input::PaintCircle {x: 100, y: 100, radius: 10, material: 3},
+        core::Input::Schedule {tick: ticks / 2, interval: None, action: core::EventAction::SpawnCohort {count: 5, area: None}},
+    ];
+    for input in inputs {
+        gs.apply(input).expect("input failed");
+    }
+    for _ in 0..ticks {
+        gs.apply(core::Input::Advance {steps: None}).expect("advancing failed");
+    }
+    std::fs::write(&args[3], gs.replay_log().to_json()).expect("Failed to write replay");
+    println!("Recorded {} ticks with seed {}, final checksum {:x}", ticks, gs.replay_log().setup.seed, gs.checksum());
+}
+
+// replay <path>, replays a log and reports the first tick that differs
+fn run_replay(args: &[String]) {
+    let text = std::fs::read_to_string(&args[2]).expect("Failed to read replay");
+    let log = core::ReplayLog::from_json(&text).expect("Failed to parse replay");
+    let (gs, divergence) = core::GameState::replay(&log).expect("Replay failed");
+    match divergence {
+        Some(tick) => println!("Replay diverged from the recording at tick {}", tick),
+        None => println!("Replay matched the recording, final checksum {:x}", gs.checksum()),
+    }
+}
+
+// run-threaded <seconds> [ticks per second], advances a game on a background
+// thread and reports how far it got while the main thread only reads snapshots
+fn run_threaded(args: &[String]) {
+    let seconds: f64 = args[2].parse().expect("Failed to parse seconds");
+    let ticks_per_second = args.get(3).map(|tps| tps.parse().expect("Failed to parse tick rate")).unwrap_or(10.0);
+    let gs = core::generate_game_state((200, 200, 10), (50, 50, 150, 150), Some(20), None, None, None);
+    let mut runner = core::SimRunner::start(gs, ticks_per_second, false);
+    runner.queue(core::Input::PaintCircle {x: 100, y: 100, radius: 10, material: 3});
+    let start = Instant::now();
+    let mut reads = 0;
+    while start.elapsed().as_secs_f64() < seconds {
+        let _ = runner.latest();
+        reads += 1;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    runner.stop();
+    let snapshot: core::Snapshot = runner.latest();
+    println!("Reached tick {} at {} ticks per second, {} snapshot reads", snapshot.get_time(), snapshot.get_ticks_per_second(), reads);
+}
+
+// profile <ticks> [entities], times each phase of the given number of ticks
+fn run_profile(args: &[String]) {
+    let ticks: u32 = args[2].parse().expect("Failed to parse string to integer");
+    let entities = args.get(3).map(|count| count.parse().expect("Failed to parse entity count"));
+    let mut gs = core::generate_game_state((800, 800, 10), (100, 100, 700, 700), entities, None, None, None);
+    gs.profiler.set_enabled(true);
+    for _ in 0..ticks {
+        gs.apply(core::Input::Advance {steps: None}).expect("advancing failed");
+    }
+    print!("{}", gs.profiler.format_report());
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -52,8 +151,14 @@ fn main() {
 
     match mode.as_str() {
         "gen-map" => validate_and_run_terrain_gen(&args),
+        "gen-chunked" => run_chunked_terrain_gen(&args),
+        "bench-terrain" => run_terrain_benchmark(&args),
         "gen-entities" => run_entity_gen(&args),
         "gen-state" => advance_game_state(&args),
+        "record" => record_replay(&args),
+        "replay" => run_replay(&args),
+        "run-threaded" => run_threaded(&args),
+        "profile" => run_profile(&args),
         _ => println!("Error, invalid mode!"),
     }
 